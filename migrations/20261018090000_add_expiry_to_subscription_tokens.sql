@@ -0,0 +1,14 @@
+-- Add migration script here
+START TRANSACTION;
+
+ALTER TABLE subscription_tokens ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+ALTER TABLE subscription_tokens ADD COLUMN expires_at TIMESTAMPTZ NULL;
+
+-- Existing tokens get a fresh grace period instead of living forever
+UPDATE subscription_tokens
+  SET expires_at = NOW() + INTERVAL '1 day'
+  WHERE expires_at IS NULL;
+
+ALTER TABLE subscription_tokens ALTER COLUMN expires_at SET NOT NULL;
+
+COMMIT;
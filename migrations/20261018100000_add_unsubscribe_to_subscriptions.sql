@@ -0,0 +1,15 @@
+-- Add migration script here
+START TRANSACTION;
+
+ALTER TABLE subscriptions ADD COLUMN unsubscribe_token TEXT NULL;
+ALTER TABLE subscriptions ADD COLUMN unsubscribed_at TIMESTAMPTZ NULL;
+
+-- Every existing subscriber needs a way to leave the list
+UPDATE subscriptions
+  SET unsubscribe_token = md5(random()::text || id::text)
+  WHERE unsubscribe_token IS NULL;
+
+ALTER TABLE subscriptions ALTER COLUMN unsubscribe_token SET NOT NULL;
+ALTER TABLE subscriptions ADD CONSTRAINT subscriptions_unsubscribe_token_key UNIQUE (unsubscribe_token);
+
+COMMIT;
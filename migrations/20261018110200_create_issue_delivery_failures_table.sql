@@ -0,0 +1,11 @@
+-- Add migration script here
+CREATE TABLE issue_delivery_failures(
+  newsletter_issue_id UUID NOT NULL,
+  subscriber_email TEXT NOT NULL,
+  n_retries SMALLINT NOT NULL,
+  error TEXT NOT NULL,
+  failed_at TIMESTAMPTZ NOT NULL,
+
+  PRIMARY KEY (newsletter_issue_id, subscriber_email),
+  FOREIGN KEY (newsletter_issue_id) REFERENCES newsletter_issues (newsletter_issue_id)
+);
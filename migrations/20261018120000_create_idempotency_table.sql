@@ -0,0 +1,17 @@
+-- Add migration script here
+CREATE TYPE header_pair AS (
+  name TEXT,
+  value BYTEA
+);
+
+CREATE TABLE idempotency(
+  user_id UUID NOT NULL,
+  idempotency_key TEXT NOT NULL,
+  -- Empty until the first request finishes processing
+  response_status_code SMALLINT NULL,
+  response_headers header_pair[] NULL,
+  response_body BYTEA NULL,
+  created_at TIMESTAMPTZ NOT NULL,
+
+  PRIMARY KEY (user_id, idempotency_key)
+);
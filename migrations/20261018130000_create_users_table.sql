@@ -0,0 +1,7 @@
+-- Add migration script here
+CREATE TABLE users(
+  user_id UUID PRIMARY KEY,
+  username TEXT NOT NULL UNIQUE,
+  -- Argon2id hash in PHC string format, it embeds the salt and the parameters
+  password_hash TEXT NOT NULL
+);
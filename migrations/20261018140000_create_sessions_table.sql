@@ -0,0 +1,9 @@
+-- Add migration script here
+CREATE TABLE sessions(
+  session_key TEXT PRIMARY KEY,
+  session_state JSONB NOT NULL,
+  expires_at TIMESTAMPTZ NOT NULL
+);
+
+-- Used by the periodic cleanup of expired sessions
+CREATE INDEX sessions_expires_at_idx ON sessions (expires_at);
@@ -0,0 +1,3 @@
+-- Add migration script here
+-- Where password reset links are sent, accounts without one can't reset their password
+ALTER TABLE users ADD COLUMN email TEXT NULL UNIQUE;
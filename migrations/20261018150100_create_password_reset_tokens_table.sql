@@ -0,0 +1,8 @@
+-- Add migration script here
+CREATE TABLE password_reset_tokens(
+  -- SHA-256 of the token sent by email, the token itself is never stored
+  token_hash TEXT PRIMARY KEY,
+  user_id UUID NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+  created_at TIMESTAMPTZ NOT NULL,
+  expires_at TIMESTAMPTZ NOT NULL
+);
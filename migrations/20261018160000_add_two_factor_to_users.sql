@@ -0,0 +1,14 @@
+-- Add migration script here
+START TRANSACTION;
+-- Base32 TOTP secret, NULL until the user completes the enrolment
+ALTER TABLE users ADD COLUMN totp_secret TEXT NULL;
+-- Last accepted time step, so a code can't be replayed within its validity window
+ALTER TABLE users ADD COLUMN totp_last_used_step BIGINT NULL;
+
+CREATE TABLE recovery_codes(
+  user_id UUID NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+  -- SHA-256 of the code shown to the user, the code itself is never stored
+  code_hash TEXT NOT NULL,
+  PRIMARY KEY (user_id, code_hash)
+);
+COMMIT;
@@ -0,0 +1,14 @@
+-- Add migration script here
+CREATE TABLE api_keys(
+  api_key_id UUID PRIMARY KEY,
+  user_id UUID NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+  name TEXT NOT NULL,
+  -- Public start of the key, shown in the admin to tell keys apart
+  prefix TEXT NOT NULL UNIQUE,
+  -- SHA-256 of the full key, the key itself is only shown once on creation
+  key_hash TEXT NOT NULL UNIQUE,
+  scopes TEXT[] NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL,
+  last_used_at TIMESTAMPTZ NULL,
+  revoked_at TIMESTAMPTZ NULL
+);
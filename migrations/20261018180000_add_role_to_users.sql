@@ -0,0 +1,8 @@
+-- Add migration script here
+START TRANSACTION;
+-- One of `owner`, `editor` or `viewer`, see `domain::user_role`
+ALTER TABLE users ADD COLUMN role TEXT NULL;
+-- Accounts created before roles existed had full access
+UPDATE users SET role = 'owner' WHERE role IS NULL;
+ALTER TABLE users ALTER COLUMN role SET NOT NULL;
+COMMIT;
@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE user_invites(
+  -- SHA-256 of the token sent by email, the token itself is never stored
+  token_hash TEXT PRIMARY KEY,
+  email TEXT NOT NULL,
+  role TEXT NOT NULL,
+  invited_by UUID NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+  created_at TIMESTAMPTZ NOT NULL,
+  expires_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,3 @@
+-- Add migration script here
+-- Extra details about the subscriber, their names and types are set in the configuration
+ALTER TABLE subscriptions ADD COLUMN attributes JSONB NOT NULL DEFAULT '{}';
@@ -0,0 +1,3 @@
+-- Add migration script here
+-- Set for accounts created with a temporary password, cleared by the next password change
+ALTER TABLE users ADD COLUMN password_change_required BOOLEAN NOT NULL DEFAULT FALSE;
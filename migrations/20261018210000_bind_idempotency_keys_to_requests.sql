@@ -0,0 +1,12 @@
+-- Add migration script here
+-- Keys saved by anonymous callers, which no longer get idempotency
+DELETE FROM idempotency WHERE user_id = '00000000-0000-0000-0000-000000000000';
+
+-- Replaying a key for another endpoint or another payload is refused
+-- Older rows get empty values, so a retry of them is refused rather than answered with another response
+ALTER TABLE idempotency ADD COLUMN endpoint TEXT NOT NULL DEFAULT '';
+ALTER TABLE idempotency ADD COLUMN request_hash TEXT NOT NULL DEFAULT '';
+ALTER TABLE idempotency ALTER COLUMN endpoint DROP DEFAULT;
+ALTER TABLE idempotency ALTER COLUMN request_hash DROP DEFAULT;
+
+CREATE INDEX idempotency_created_at_idx ON idempotency (created_at);
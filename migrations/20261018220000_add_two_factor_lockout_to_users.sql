@@ -0,0 +1,5 @@
+-- Add migration script here
+-- Invalid second factor codes are counted per user, not per session, so logging in again doesn't reset them
+ALTER TABLE users ADD COLUMN two_factor_failed_attempts INTEGER NOT NULL DEFAULT 0;
+-- Set once the attempts run out, no code is checked before then
+ALTER TABLE users ADD COLUMN two_factor_locked_until TIMESTAMPTZ NULL;
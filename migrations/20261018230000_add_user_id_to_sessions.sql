@@ -0,0 +1,5 @@
+-- Add migration script here
+-- Set once the session is logged in, so all the sessions of a user can be ended at once
+ALTER TABLE sessions ADD COLUMN user_id UUID NULL REFERENCES users (user_id) ON DELETE CASCADE;
+
+CREATE INDEX sessions_user_id_idx ON sessions (user_id);
@@ -0,0 +1,3 @@
+-- Add migration script here
+-- Keyset pagination of the subscribers API, newest first
+CREATE INDEX subscriptions_subscribed_at_id_idx ON subscriptions (subscribed_at DESC, id DESC);
@@ -0,0 +1,7 @@
+-- Add migration script here
+-- Set on every confirmation email, resent links included, so the resend rate limit applies to them too
+ALTER TABLE subscriptions ADD COLUMN confirmation_sent_at TIMESTAMPTZ NULL;
+UPDATE subscriptions
+SET confirmation_sent_at = (
+    SELECT MAX(created_at) FROM subscription_tokens WHERE subscriber_id = subscriptions.id
+);
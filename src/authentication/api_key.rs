@@ -0,0 +1,248 @@
+use std::{future::Future, ops::DerefMut, pin::Pin};
+
+use actix_web::{
+    dev::Payload,
+    error::InternalError,
+    http::header::{AUTHORIZATION, WWW_AUTHENTICATE},
+    web, FromRequest, HttpRequest, HttpResponse,
+};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    domain::{api_key::ApiKey, api_key_scope::ApiKeyScope, user_role::UserRole},
+    utils::e500,
+};
+
+// A request authenticated with an `Authorization: Bearer <api key>` header
+#[derive(Debug)]
+pub struct AuthenticatedApiKey {
+    pub api_key_id: Uuid,
+    // Owner of the key, requests are made on their behalf
+    pub user_id: Uuid,
+    pub scopes: Vec<ApiKeyScope>,
+    // Read on every request, like for sessions, so a role change applies to existing keys
+    pub role: UserRole,
+}
+
+impl AuthenticatedApiKey {
+    pub fn has_scope(&self, scope: ApiKeyScope) -> bool {
+        self.scopes.contains(&scope) && self.role.has_permission(scope.required_permission())
+    }
+}
+
+impl FromRequest for AuthenticatedApiKey {
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let api_key = bearer_api_key(req);
+        let db_pool = req.app_data::<web::Data<PgPool>>().cloned();
+
+        Box::pin(async move {
+            let api_key = api_key.map_err(unauthorized)?;
+            let db_pool =
+                db_pool.ok_or_else(|| e500("The database pool is missing from the app data"))?;
+
+            authenticate_api_key(&db_pool, &api_key)
+                .await
+                .map_err(e500)?
+                .ok_or_else(|| unauthorized("The API key is unknown or has been revoked".into()))
+        })
+    }
+}
+
+fn bearer_api_key(req: &HttpRequest) -> Result<ApiKey, String> {
+    let header = req
+        .headers()
+        .get(AUTHORIZATION)
+        .ok_or("The Authorization header is missing")?
+        .to_str()
+        .map_err(|_| "The Authorization header is not a valid string")?;
+    let api_key = header
+        .strip_prefix("Bearer ")
+        .ok_or("The authorization scheme is not Bearer")?;
+
+    ApiKey::parse(api_key.trim().to_string())
+}
+
+fn unauthorized(err: String) -> actix_web::Error {
+    let response = HttpResponse::Unauthorized()
+        .insert_header((WWW_AUTHENTICATE, r#"Bearer realm="z2p""#))
+        .finish();
+    InternalError::from_response(err, response).into()
+}
+
+// Looking the key up also records its use
+#[tracing::instrument(name = "Authenticate API key", skip(db_pool))]
+async fn authenticate_api_key(
+    db_pool: &PgPool,
+    api_key: &ApiKey,
+) -> Result<Option<AuthenticatedApiKey>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE api_keys SET last_used_at = $1
+        FROM users
+        WHERE api_keys.key_hash = $2
+            AND api_keys.revoked_at IS NULL
+            AND users.user_id = api_keys.user_id
+        RETURNING api_keys.api_key_id, api_keys.user_id, api_keys.scopes, users.role
+        "#,
+        Utc::now(),
+        api_key.hash(),
+    )
+    .fetch_optional(db_pool)
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    let scopes = row
+        .scopes
+        .into_iter()
+        .map(ApiKeyScope::try_from)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(anyhow::Error::msg)
+        .context("Invalid scope stored for the API key")?;
+    let role = UserRole::try_from(row.role)
+        .map_err(anyhow::Error::msg)
+        .context("Invalid role stored for the owner of the API key")?;
+
+    Ok(Some(AuthenticatedApiKey {
+        api_key_id: row.api_key_id,
+        user_id: row.user_id,
+        scopes,
+        role,
+    }))
+}
+
+// Stored keys, as listed in the admin
+pub struct ApiKeySummary {
+    pub api_key_id: Uuid,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(name = "Save new API key", skip(db_pool, api_key, scopes))]
+pub async fn insert_api_key(
+    db_pool: &PgPool,
+    user_id: Uuid,
+    name: &str,
+    api_key: &ApiKey,
+    scopes: &[ApiKeyScope],
+) -> Result<Uuid, sqlx::Error> {
+    let api_key_id = Uuid::new_v4();
+    let scopes: Vec<String> = scopes.iter().map(|s| s.as_str().to_string()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO api_keys (api_key_id, user_id, name, prefix, key_hash, scopes, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        api_key_id,
+        user_id,
+        name,
+        api_key.prefix(),
+        api_key.hash(),
+        &scopes,
+        Utc::now(),
+    )
+    .execute(db_pool)
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(api_key_id)
+}
+
+#[tracing::instrument(name = "Get API keys", skip(db_pool))]
+pub async fn get_api_keys(
+    db_pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Vec<ApiKeySummary>, sqlx::Error> {
+    sqlx::query_as!(
+        ApiKeySummary,
+        r#"
+        SELECT api_key_id, name, prefix, scopes, created_at, last_used_at, revoked_at
+        FROM api_keys
+        WHERE user_id = $1
+        ORDER BY created_at DESC
+        "#,
+        user_id
+    )
+    .fetch_all(db_pool)
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })
+}
+
+// Returns false when the key doesn't exist, belongs to someone else or is already revoked
+#[tracing::instrument(name = "Revoke API key", skip(db_pool))]
+pub async fn revoke_api_key(
+    db_pool: &PgPool,
+    user_id: Uuid,
+    api_key_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE api_keys SET revoked_at = $1
+        WHERE api_key_id = $2 AND user_id = $3 AND revoked_at IS NULL
+        "#,
+        Utc::now(),
+        api_key_id,
+        user_id,
+    )
+    .execute(db_pool)
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+// Keys with a scope the owner's new role no longer allows, returns how many were revoked
+#[tracing::instrument(name = "Revoke API keys beyond role", skip(tx))]
+pub async fn revoke_api_keys_beyond_role(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    role: UserRole,
+) -> Result<u64, sqlx::Error> {
+    let forbidden_scopes: Vec<String> = ApiKeyScope::ALL
+        .iter()
+        .filter(|scope| !role.has_permission(scope.required_permission()))
+        .map(|scope| scope.as_str().to_string())
+        .collect();
+    let result = sqlx::query!(
+        r#"
+        UPDATE api_keys SET revoked_at = $1
+        WHERE user_id = $2 AND revoked_at IS NULL AND scopes && $3
+        "#,
+        Utc::now(),
+        user_id,
+        &forbidden_scopes,
+    )
+    .execute(tx.deref_mut())
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,81 @@
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    error::InternalError,
+    http::header::ContentType,
+    middleware::Next,
+    web, HttpMessage, HttpResponse,
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authentication::UserId,
+    domain::user_role::{Permission, UserRole},
+    utils::{e500, MiddlewareFuture},
+};
+
+const FORBIDDEN_PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Forbidden</title>
+</head>
+<body>
+    <p>Your role does not allow you to access this page.</p>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#;
+
+// Per route check, to be wrapped with `from_fn` inside the scope guarded by `reject_anonymous_users`
+// e.g. `web::resource("/users").wrap(from_fn(require_permission(Permission::ManageUsers)))`
+pub fn require_permission<B>(
+    permission: Permission,
+) -> impl Fn(ServiceRequest, Next<B>) -> MiddlewareFuture<B> + 'static
+where
+    B: MessageBody + 'static,
+{
+    move |req, next| Box::pin(check_permission(permission, req, next))
+}
+
+async fn check_permission<B: MessageBody>(
+    permission: Permission,
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<B>, actix_web::Error> {
+    let user_id = req
+        .extensions()
+        .get::<UserId>()
+        .copied()
+        .ok_or_else(|| e500("The user id is missing from the request"))?;
+    let db_pool = req
+        .app_data::<web::Data<PgPool>>()
+        .cloned()
+        .ok_or_else(|| e500("The database pool is missing from the app data"))?;
+
+    // Read on every request, so a role change applies to existing sessions right away
+    let role = get_user_role(&db_pool, *user_id).await.map_err(e500)?;
+    if !role.has_permission(permission) {
+        let response = HttpResponse::Forbidden()
+            .content_type(ContentType::html())
+            .body(FORBIDDEN_PAGE);
+        let err = anyhow::anyhow!("The {:?} role lacks the {:?} permission", role, permission);
+        return Err(InternalError::from_response(err, response).into());
+    }
+
+    req.extensions_mut().insert(role);
+    next.call(req).await
+}
+
+#[tracing::instrument(name = "Get user role", skip(db_pool))]
+pub async fn get_user_role(db_pool: &PgPool, user_id: Uuid) -> Result<UserRole, sqlx::Error> {
+    let row = sqlx::query!(r#"SELECT role FROM users WHERE user_id = $1"#, user_id)
+        .fetch_one(db_pool)
+        .await
+        .map_err(|err| {
+            tracing::error!("Failed to execute query: {:?}", err);
+            err
+        })?;
+
+    UserRole::try_from(row.role).map_err(|err| sqlx::Error::Decode(err.into()))
+}
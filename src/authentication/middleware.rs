@@ -0,0 +1,126 @@
+use std::ops::Deref;
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    error::InternalError,
+    middleware::Next,
+    web, FromRequest, HttpMessage,
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authentication::{get_totp_secret, is_password_change_required},
+    configurations::TwoFactorSettings,
+    session_state::TypedSession,
+    utils::{e500, see_other},
+};
+
+// Id of the logged in user, available to handlers behind `reject_anonymous_users`
+#[derive(Copy, Clone, Debug)]
+pub struct UserId(Uuid);
+
+impl std::fmt::Display for UserId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Deref for UserId {
+    type Target = Uuid;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+pub async fn reject_anonymous_users(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let session = {
+        let (http_request, payload) = req.parts_mut();
+        TypedSession::from_request(http_request, payload).await
+    }?;
+
+    match session.get_user_id().map_err(e500)? {
+        Some(user_id) => {
+            req.extensions_mut().insert(UserId(user_id));
+            next.call(req).await
+        }
+        None => {
+            let response = see_other("/login");
+            let err = anyhow::anyhow!("The user has not logged in");
+            Err(InternalError::from_response(err, response).into())
+        }
+    }
+}
+
+// Pages an admin without a second factor can still reach when enrolment is enforced
+const TWO_FACTOR_ENROLMENT_PATHS: [&str; 2] = ["/admin/2fa", "/admin/logout"];
+
+// Runs after `reject_anonymous_users`, the user id is already known
+pub async fn require_two_factor_enrolment(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let enforce = req
+        .app_data::<web::Data<TwoFactorSettings>>()
+        .is_some_and(|settings| settings.enforce);
+    if !enforce || TWO_FACTOR_ENROLMENT_PATHS.contains(&req.path()) {
+        return next.call(req).await;
+    }
+
+    let user_id = req
+        .extensions()
+        .get::<UserId>()
+        .copied()
+        .ok_or_else(|| e500("The user id is missing from the request"))?;
+    let db_pool = req
+        .app_data::<web::Data<PgPool>>()
+        .cloned()
+        .ok_or_else(|| e500("The database pool is missing from the app data"))?;
+
+    match get_totp_secret(&db_pool, *user_id).await.map_err(e500)? {
+        Some(_) => next.call(req).await,
+        None => {
+            let response = see_other("/admin/2fa");
+            let err = anyhow::anyhow!("The user has not enrolled a second factor");
+            Err(InternalError::from_response(err, response).into())
+        }
+    }
+}
+
+// Pages a user with a temporary password can still reach, enrolment comes first when enforced
+const PASSWORD_CHANGE_PATHS: [&str; 3] = ["/admin/password", "/admin/2fa", "/admin/logout"];
+
+// Runs after `reject_anonymous_users`, the user id is already known
+pub async fn require_password_change(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    if PASSWORD_CHANGE_PATHS.contains(&req.path()) {
+        return next.call(req).await;
+    }
+
+    let user_id = req
+        .extensions()
+        .get::<UserId>()
+        .copied()
+        .ok_or_else(|| e500("The user id is missing from the request"))?;
+    let db_pool = req
+        .app_data::<web::Data<PgPool>>()
+        .cloned()
+        .ok_or_else(|| e500("The database pool is missing from the app data"))?;
+
+    if is_password_change_required(&db_pool, *user_id)
+        .await
+        .map_err(e500)?
+    {
+        let response = see_other("/admin/password");
+        let err = anyhow::anyhow!("The user has to change their temporary password");
+        return Err(InternalError::from_response(err, response).into());
+    }
+    next.call(req).await
+}
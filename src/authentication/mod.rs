@@ -0,0 +1,11 @@
+mod api_key;
+mod authorization;
+mod middleware;
+mod password;
+mod two_factor;
+
+pub use api_key::*;
+pub use authorization::*;
+pub use middleware::*;
+pub use password::*;
+pub use two_factor::*;
@@ -0,0 +1,280 @@
+use std::ops::DerefMut;
+
+use anyhow::Context;
+use argon2::{
+    password_hash::SaltString, Algorithm, Argon2, Params, PasswordHash, PasswordHasher,
+    PasswordVerifier, Version,
+};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{domain::user_role::UserRole, telemetry::spawn_blocking_with_tracing};
+
+// Hash of a random password, computed with the same parameters as the real ones
+// Verified against when the username is unknown, so both cases take the same time
+const DUMMY_PASSWORD_HASH: &str = "$argon2id$v=19$m=15000,t=2,p=1$\
+    gZiV/M1gPc22ElAH/Jh1Hw$\
+    CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno";
+
+pub struct Credentials {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum AuthError {
+    #[error("Invalid credentials.")]
+    InvalidCredentials(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum BootstrapOwnerError {
+    #[error("An account already exists, invite new users from the admin instead.")]
+    UsersExist,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+#[tracing::instrument(name = "Validate credentials", skip(credentials, db_pool))]
+pub async fn validate_credentials(
+    credentials: Credentials,
+    db_pool: &PgPool,
+) -> Result<Uuid, AuthError> {
+    let mut user_id = None;
+    let mut expected_password_hash = Secret::new(DUMMY_PASSWORD_HASH.to_string());
+
+    if let Some((stored_user_id, stored_password_hash)) =
+        get_stored_credentials(&credentials.username, db_pool).await?
+    {
+        user_id = Some(stored_user_id);
+        expected_password_hash = stored_password_hash;
+    }
+
+    spawn_blocking_with_tracing(move || {
+        verify_password_hash(expected_password_hash, credentials.password)
+    })
+    .await
+    .context("Failed to spawn blocking task")??;
+
+    // Only reachable with the dummy hash if someone guessed its password
+    user_id
+        .ok_or_else(|| anyhow::anyhow!("Unknown username"))
+        .map_err(AuthError::InvalidCredentials)
+}
+
+#[tracing::instrument(name = "Change password", skip(tx, password))]
+pub async fn change_password(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    password: Secret<String>,
+) -> Result<(), anyhow::Error> {
+    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
+        .await
+        .context("Failed to spawn blocking task")??;
+
+    sqlx::query!(
+        r#"
+        UPDATE users SET password_hash = $1, password_change_required = FALSE
+        WHERE user_id = $2
+        "#,
+        password_hash.expose_secret(),
+        user_id,
+    )
+    .execute(tx.deref_mut())
+    .await
+    .context("Failed to change user's password in the database")?;
+
+    Ok(())
+}
+
+// Returns None when the username or the email is already taken
+#[tracing::instrument(name = "Create user", skip(tx, password))]
+pub async fn create_user(
+    tx: &mut Transaction<'_, Postgres>,
+    username: &str,
+    email: &str,
+    role: UserRole,
+    password: Secret<String>,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
+        .await
+        .context("Failed to spawn blocking task")??;
+
+    let user_id = Uuid::new_v4();
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, password_hash, email, role)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT DO NOTHING
+        "#,
+        user_id,
+        username,
+        password_hash.expose_secret(),
+        email,
+        role.as_str(),
+    )
+    .execute(tx.deref_mut())
+    .await
+    .context("Failed to create the user in the database")?;
+
+    Ok((result.rows_affected() == 1).then_some(user_id))
+}
+
+// First owner of a fresh install, only allowed while there are no accounts at all
+// Returns a temporary password, which has to be changed at the first login
+#[tracing::instrument(name = "Bootstrap the first owner", skip(db_pool))]
+pub async fn bootstrap_owner(
+    db_pool: &PgPool,
+    username: &str,
+    email: &str,
+) -> Result<Secret<String>, BootstrapOwnerError> {
+    let mut tx = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    // Two bootstraps at once would both see an empty table
+    sqlx::query!("LOCK TABLE users IN EXCLUSIVE MODE")
+        .execute(tx.deref_mut())
+        .await
+        .context("Failed to lock the users table")?;
+
+    let users_exist = sqlx::query_scalar!(r#"SELECT EXISTS(SELECT 1 FROM users) AS "exists!""#)
+        .fetch_one(tx.deref_mut())
+        .await
+        .context("Failed to check for existing users")?;
+    if users_exist {
+        return Err(BootstrapOwnerError::UsersExist);
+    }
+
+    let password = generate_temporary_password();
+    let user_id = create_user(&mut tx, username, email, UserRole::Owner, password.clone())
+        .await?
+        .context("Failed to create the owner in an empty users table")?;
+    sqlx::query!(
+        "UPDATE users SET password_change_required = TRUE WHERE user_id = $1",
+        user_id,
+    )
+    .execute(tx.deref_mut())
+    .await
+    .context("Failed to require a password change")?;
+
+    tx.commit()
+        .await
+        .context("Failed to commit SQL transaction to store the owner")?;
+    Ok(password)
+}
+
+#[tracing::instrument(name = "Check if a password change is required", skip(db_pool))]
+pub async fn is_password_change_required(
+    db_pool: &PgPool,
+    user_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let required = sqlx::query_scalar!(
+        "SELECT password_change_required FROM users WHERE user_id = $1",
+        user_id,
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to check if the user has to change their password")?;
+
+    Ok(required.unwrap_or(false))
+}
+
+fn generate_temporary_password() -> Secret<String> {
+    let mut rng = thread_rng();
+    let password = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(24)
+        .collect();
+    Secret::new(password)
+}
+
+// Argon2id with the OWASP recommended parameters, stored as a PHC string
+pub fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let password_hash = Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        Params::new(15000, 2, 1, None).unwrap(),
+    )
+    .hash_password(password.expose_secret().as_bytes(), &salt)?
+    .to_string();
+
+    Ok(Secret::new(password_hash))
+}
+
+#[tracing::instrument(
+    name = "Verify password hash",
+    skip(expected_password_hash, password_candidate)
+)]
+fn verify_password_hash(
+    expected_password_hash: Secret<String>,
+    password_candidate: Secret<String>,
+) -> Result<(), AuthError> {
+    // The PHC string carries its own algorithm and parameters
+    let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
+        .context("Failed to parse hash in PHC string format")?;
+
+    Argon2::default()
+        .verify_password(
+            password_candidate.expose_secret().as_bytes(),
+            &expected_password_hash,
+        )
+        .context("Invalid password")
+        .map_err(AuthError::InvalidCredentials)
+}
+
+#[tracing::instrument(name = "Get stored credentials", skip(username, db_pool))]
+async fn get_stored_credentials(
+    username: &str,
+    db_pool: &PgPool,
+) -> Result<Option<(Uuid, Secret<String>)>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT user_id, password_hash FROM users WHERE username = $1"#,
+        username,
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to perform a query to retrieve stored credentials")?
+    .map(|row| (row.user_id, Secret::new(row.password_hash)));
+
+    Ok(row)
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+    use secrecy::Secret;
+
+    use super::{compute_password_hash, verify_password_hash, DUMMY_PASSWORD_HASH};
+
+    #[test]
+    fn password_hash_verifies_against_the_original_password() {
+        let password = Secret::new("correct horse battery staple".to_string());
+        let hash = compute_password_hash(password.clone()).unwrap();
+        assert_ok!(verify_password_hash(hash, password));
+    }
+
+    #[test]
+    fn password_hash_rejects_a_different_password() {
+        let hash = compute_password_hash(Secret::new("correct horse".to_string())).unwrap();
+        assert_err!(verify_password_hash(
+            hash,
+            Secret::new("battery staple".to_string())
+        ));
+    }
+
+    #[test]
+    fn dummy_password_hash_is_a_valid_phc_string() {
+        let hash = Secret::new(DUMMY_PASSWORD_HASH.to_string());
+        let result = verify_password_hash(hash, Secret::new("password".to_string()));
+        assert!(matches!(
+            result,
+            Err(super::AuthError::InvalidCredentials(_))
+        ));
+    }
+}
@@ -0,0 +1,425 @@
+use std::{
+    ops::DerefMut,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use chrono::Duration;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use sha2::{Digest, Sha256};
+use sqlx::{
+    types::chrono::{DateTime, Utc},
+    PgPool, Postgres, Transaction,
+};
+use totp_rs::{Algorithm, Secret, TOTP};
+use uuid::Uuid;
+
+// RFC 6238 defaults, the only ones every authenticator app supports
+const TOTP_DIGITS: usize = 6;
+const TOTP_STEP: u64 = 30;
+const TOTP_SECRET_LENGTH: usize = 20;
+// Codes from the previous and next time step are accepted to absorb clock drift
+const TOTP_SKEW: i64 = 1;
+
+const RECOVERY_CODES_COUNT: usize = 10;
+const RECOVERY_CODE_LENGTH: usize = 10;
+
+// Base32 encoded, the format authenticator apps expect
+pub fn generate_totp_secret() -> String {
+    let mut rng = thread_rng();
+    let secret: Vec<u8> = (0..TOTP_SECRET_LENGTH).map(|_| rng.gen()).collect();
+    Secret::Raw(secret).to_encoded().to_string()
+}
+
+pub fn build_totp(secret: &str, issuer: &str, account_name: &str) -> Result<TOTP, anyhow::Error> {
+    let secret = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(|err| anyhow::anyhow!("Failed to decode TOTP secret: {}", err))?;
+
+    // Skew is handled by `verify_totp_code`, which needs to know the matching step
+    TOTP::new(
+        Algorithm::SHA1,
+        TOTP_DIGITS,
+        0,
+        TOTP_STEP,
+        secret,
+        Some(issuer.to_string()),
+        account_name.to_string(),
+    )
+    .context("Failed to build TOTP")
+}
+
+// Returns the time step the code was generated for, if it is valid
+pub fn verify_totp_code(totp: &TOTP, code: &str) -> Option<i64> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+    let current_step = (now / TOTP_STEP) as i64;
+
+    (current_step - TOTP_SKEW..=current_step + TOTP_SKEW)
+        .find(|step| totp.check(code, *step as u64 * TOTP_STEP))
+}
+
+// Formatted as `xxxxx-xxxxx` to be easier to copy by hand
+pub fn generate_recovery_codes() -> Vec<String> {
+    let mut rng = thread_rng();
+    (0..RECOVERY_CODES_COUNT)
+        .map(|_| {
+            let code: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+                .map(|c| char::from(c).to_ascii_lowercase())
+                .take(RECOVERY_CODE_LENGTH)
+                .collect();
+            format!(
+                "{}-{}",
+                &code[..RECOVERY_CODE_LENGTH / 2],
+                &code[RECOVERY_CODE_LENGTH / 2..]
+            )
+        })
+        .collect()
+}
+
+// Codes are random and high entropy, a fast unsalted hash is enough
+fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    format!("{:x}", Sha256::digest(normalized.as_bytes()))
+}
+
+// Second login step, accepts either a TOTP code or one of the recovery codes
+#[tracing::instrument(name = "Verify second factor", skip(db_pool, code))]
+pub async fn verify_second_factor(
+    db_pool: &PgPool,
+    user_id: Uuid,
+    code: &str,
+) -> Result<bool, anyhow::Error> {
+    let secret = match get_totp_secret(db_pool, user_id).await? {
+        Some(secret) => secret,
+        None => return Ok(false),
+    };
+
+    let is_totp_code = code.len() == TOTP_DIGITS && code.chars().all(|c| c.is_ascii_digit());
+    if !is_totp_code {
+        return use_recovery_code(db_pool, user_id, code)
+            .await
+            .context("Failed to use recovery code");
+    }
+
+    let totp = build_totp(&secret, "", "")?;
+    match verify_totp_code(&totp, code) {
+        Some(step) => consume_totp_step(db_pool, user_id, step)
+            .await
+            .context("Failed to record TOTP step"),
+        None => Ok(false),
+    }
+}
+
+#[tracing::instrument(name = "Get TOTP secret", skip(db_pool))]
+pub async fn get_totp_secret(
+    db_pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT totp_secret FROM users WHERE user_id = $1"#,
+        user_id
+    )
+    .fetch_one(db_pool)
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(row.totp_secret)
+}
+
+// Until when the second step is refused for this user, `None` when it isn't locked
+#[tracing::instrument(name = "Get two-factor lockout", skip(db_pool))]
+pub async fn get_two_factor_lockout(
+    db_pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT two_factor_locked_until FROM users WHERE user_id = $1"#,
+        user_id
+    )
+    .fetch_one(db_pool)
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(row
+        .two_factor_locked_until
+        .filter(|locked_until| *locked_until > Utc::now()))
+}
+
+// Count an invalid code, the last allowed one locks the second step for `lockout_duration`
+// Return whether the second step is now locked
+#[tracing::instrument(name = "Record failed second factor", skip(db_pool, lockout_duration))]
+pub async fn record_failed_second_factor(
+    db_pool: &PgPool,
+    user_id: Uuid,
+    max_attempts: u32,
+    lockout_duration: Duration,
+) -> Result<bool, sqlx::Error> {
+    // A single statement, so concurrent attempts can't go past the limit
+    let row = sqlx::query!(
+        r#"
+        UPDATE users SET
+            two_factor_failed_attempts = CASE
+                WHEN two_factor_failed_attempts + 1 >= $2 THEN 0
+                ELSE two_factor_failed_attempts + 1
+            END,
+            two_factor_locked_until = CASE
+                WHEN two_factor_failed_attempts + 1 >= $2 THEN $3
+                ELSE two_factor_locked_until
+            END
+        WHERE user_id = $1
+        RETURNING two_factor_locked_until
+        "#,
+        user_id,
+        max_attempts as i32,
+        Utc::now() + lockout_duration,
+    )
+    .fetch_one(db_pool)
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(row
+        .two_factor_locked_until
+        .is_some_and(|locked_until| locked_until > Utc::now()))
+}
+
+#[tracing::instrument(name = "Reset failed second factors", skip(db_pool))]
+pub async fn reset_failed_second_factors(
+    db_pool: &PgPool,
+    user_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE users SET two_factor_failed_attempts = 0, two_factor_locked_until = NULL WHERE user_id = $1"#,
+        user_id
+    )
+    .execute(db_pool)
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(())
+}
+
+// A step can only be used once, so an intercepted code can't be replayed
+#[tracing::instrument(name = "Consume TOTP step", skip(db_pool))]
+async fn consume_totp_step(
+    db_pool: &PgPool,
+    user_id: Uuid,
+    step: i64,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE users SET totp_last_used_step = $1
+        WHERE user_id = $2 AND (totp_last_used_step IS NULL OR totp_last_used_step < $1)
+        "#,
+        step,
+        user_id,
+    )
+    .execute(db_pool)
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+#[tracing::instrument(name = "Use recovery code", skip(db_pool, code))]
+async fn use_recovery_code(
+    db_pool: &PgPool,
+    user_id: Uuid,
+    code: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"DELETE FROM recovery_codes WHERE user_id = $1 AND code_hash = $2"#,
+        user_id,
+        hash_recovery_code(code),
+    )
+    .execute(db_pool)
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+// The step used to verify the enrolment is consumed as well
+// Return false, changing nothing, when a second factor is already enabled
+#[tracing::instrument(
+    name = "Enable two-factor authentication",
+    skip(tx, secret, recovery_codes)
+)]
+pub async fn enable_two_factor(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    secret: &str,
+    step: i64,
+    recovery_codes: &[String],
+) -> Result<bool, sqlx::Error> {
+    // Replacing the secret would let a hijacked session take over the second factor
+    let result = sqlx::query!(
+        r#"
+        UPDATE users SET totp_secret = $1, totp_last_used_step = $2
+        WHERE user_id = $3 AND totp_secret IS NULL
+        "#,
+        secret,
+        step,
+        user_id,
+    )
+    .execute(tx.deref_mut())
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    let code_hashes: Vec<String> = recovery_codes
+        .iter()
+        .map(|code| hash_recovery_code(code))
+        .collect();
+    delete_recovery_codes(tx, user_id).await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO recovery_codes (user_id, code_hash)
+        SELECT $1, * FROM UNNEST($2::TEXT[])
+        "#,
+        user_id,
+        &code_hashes,
+    )
+    .execute(tx.deref_mut())
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(true)
+}
+
+#[tracing::instrument(name = "Disable two-factor authentication", skip(tx))]
+pub async fn disable_two_factor(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE users SET totp_secret = NULL, totp_last_used_step = NULL WHERE user_id = $1"#,
+        user_id,
+    )
+    .execute(tx.deref_mut())
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    delete_recovery_codes(tx, user_id).await
+}
+
+async fn delete_recovery_codes(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(r#"DELETE FROM recovery_codes WHERE user_id = $1"#, user_id)
+        .execute(tx.deref_mut())
+        .await
+        .map_err(|err| {
+            tracing::error!("Failed to execute query: {:?}", err);
+            err
+        })?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_none, assert_ok, assert_some};
+
+    use super::{
+        build_totp, generate_recovery_codes, generate_totp_secret, hash_recovery_code,
+        verify_totp_code, TOTP_STEP,
+    };
+
+    #[test]
+    fn generated_secret_builds_a_valid_totp() {
+        let secret = generate_totp_secret();
+        assert_ok!(build_totp(&secret, "z2p", "admin"));
+    }
+
+    #[test]
+    fn otpauth_uri_contains_issuer_and_secret() {
+        let secret = generate_totp_secret();
+        let totp = build_totp(&secret, "z2p", "admin").unwrap();
+        let uri = totp.get_url();
+        assert!(uri.starts_with("otpauth://totp/z2p:admin?"));
+        assert!(uri.contains(&format!("secret={}", secret)));
+    }
+
+    #[test]
+    fn current_code_is_accepted() {
+        let totp = build_totp(&generate_totp_secret(), "z2p", "admin").unwrap();
+        let code = totp.generate_current().unwrap();
+        assert_some!(verify_totp_code(&totp, &code));
+    }
+
+    #[test]
+    fn code_from_the_next_step_is_accepted() {
+        let totp = build_totp(&generate_totp_secret(), "z2p", "admin").unwrap();
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let code = totp.generate(now + TOTP_STEP);
+        assert_some!(verify_totp_code(&totp, &code));
+    }
+
+    #[test]
+    fn code_from_an_old_step_is_rejected() {
+        let totp = build_totp(&generate_totp_secret(), "z2p", "admin").unwrap();
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let code = totp.generate(now - 10 * TOTP_STEP);
+        // Ignore the rare case where an old code collides with a valid one
+        if code != totp.generate_current().unwrap() {
+            assert_none!(verify_totp_code(&totp, &code));
+        }
+    }
+
+    #[test]
+    fn recovery_codes_are_unique() {
+        let mut codes = generate_recovery_codes();
+        let count = codes.len();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), count);
+    }
+
+    #[test]
+    fn recovery_code_hash_ignores_formatting() {
+        assert_eq!(
+            hash_recovery_code("abcde-12345"),
+            hash_recovery_code(" ABCDE12345 ")
+        );
+    }
+}
@@ -0,0 +1,30 @@
+use anyhow::Context;
+use secrecy::ExposeSecret;
+use z2p::{
+    authentication::bootstrap_owner, configurations, domain::subscriber_email::SubscriberEmail,
+};
+
+// Create the first owner of a fresh install: `z2p-create-owner <username> <email>`
+// Prints a temporary password, which has to be changed at the first login
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    let mut args = std::env::args().skip(1);
+    let (Some(username), Some(email), None) = (args.next(), args.next(), args.next()) else {
+        anyhow::bail!("Usage: z2p-create-owner <username> <email>");
+    };
+    let email = SubscriberEmail::parse(email).map_err(|err| anyhow::anyhow!("{}", err))?;
+
+    let configurations =
+        configurations::read_configuration().expect("Failed to read configurations.");
+    let db_pool = configurations.database.pg_connection_pool();
+
+    let username = username.trim();
+    let password = bootstrap_owner(&db_pool, username, email.as_ref())
+        .await
+        .context("Failed to create the owner")?;
+
+    println!("Created the owner `{}`.", username);
+    println!("Temporary password: {}", password.expose_secret());
+    println!("It has to be changed at the first login.");
+    Ok(())
+}
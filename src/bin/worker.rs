@@ -0,0 +1,18 @@
+use z2p::{
+    configurations,
+    issue_delivery_worker::run_worker_until_stopped,
+    telemetry::{gen_subscriber, init_subscriber},
+};
+
+// Run the issue delivery worker on its own, set `delivery_worker.embedded` to false for the server
+// Every worker is a process sending emails, count it in `email_client.sending_processes`
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    let subscriber = gen_subscriber("z2p-worker".into(), "info".into(), std::io::stdout);
+    init_subscriber(subscriber);
+
+    let configurations =
+        configurations::read_configuration().expect("Failed to read configurations.");
+
+    run_worker_until_stopped(configurations).await
+}
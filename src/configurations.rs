@@ -1,19 +1,48 @@
+use std::collections::HashMap;
+
+use chrono::Duration;
 use secrecy::{ExposeSecret, Secret};
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 
-use crate::domain::subscriber_email::SubscriberEmail;
+use crate::domain::{
+    subscriber_attributes::AttributeSchema,
+    subscriber_email::{SubscriberEmail, SubscriberEmailError},
+};
 
 #[derive(serde::Deserialize)]
 pub struct Settings {
     pub application: ApplicationSettings,
     pub database: DatabaseSettings,
     pub email_client: EmailClientSettings,
+    pub subscriptions: SubscriptionSettings,
+    pub delivery_worker: DeliveryWorkerSettings,
+    pub session: SessionSettings,
+    pub password_reset: PasswordResetSettings,
+    pub two_factor: TwoFactorSettings,
+    pub invites: InviteSettings,
+    pub idempotency: IdempotencySettings,
+    pub metrics: MetricsSettings,
 }
 
 #[derive(serde::Deserialize)]
 pub struct ApplicationSettings {
     pub host: String,
     pub port: u16,
+    // Public URL of the application, used to build links sent to subscribers
+    pub base_url: String,
+    // Signs the session cookies, must be at least 64 bytes long
+    pub hmac_secret: Secret<String>,
+}
+
+// Committed in `config/base.json`, production refuses to start with it
+pub const HMAC_SECRET_PLACEHOLDER: &str =
+    "insecure-placeholder-for-local-development-set-APP_APPLICATION__HMAC_SECRET-in-production";
+
+// `/metrics` has its own listener, keep it off the public network
+#[derive(serde::Deserialize)]
+pub struct MetricsSettings {
+    pub host: String,
+    pub port: u16,
 }
 
 #[derive(serde::Deserialize)]
@@ -27,10 +56,216 @@ pub struct DatabaseSettings {
 
 #[derive(serde::Deserialize)]
 pub struct EmailClientSettings {
-    pub base_url: String,
+    // Picks which of the sections below is used
+    pub provider: EmailProvider,
     pub sender_email: String,
-    pub auth_token: Secret<String>,
+    // In milliseconds, for a single attempt
     pub timeout: u64,
+    // Every email is sent once when unset, the delivery worker always sends once and requeues
+    pub retry: Option<EmailRetrySettings>,
+    pub postmark: Option<PostmarkSettings>,
+    pub smtp: Option<SmtpSettings>,
+    pub json_http: Option<JsonHttpSettings>,
+    pub file: Option<FileSettings>,
+    pub in_memory: Option<InMemorySettings>,
+    // Per provider, a provider without an entry is never throttled on our side
+    #[serde(default)]
+    pub limits: HashMap<EmailProvider, EmailLimitSettings>,
+    // Processes sending emails with these settings: the server, plus every standalone `z2p-worker`
+    // Limits are enforced in each process, so each one gets an equal share of them
+    pub sending_processes: u32,
+    // Tried in order while the circuit of `provider` is open, each needs its section below
+    #[serde(default)]
+    pub fallbacks: Vec<EmailProvider>,
+    // Shared by every provider, circuits never open when unset
+    pub circuit_breaker: Option<CircuitBreakerSettings>,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailProvider {
+    Postmark,
+    Smtp,
+    JsonHttp,
+    // Nothing leaves the machine, for local development and tests
+    File,
+    InMemory,
+}
+
+#[derive(serde::Deserialize, Clone, Debug, Default)]
+pub struct EmailLimitSettings {
+    // Sustained rate of the token bucket, unlimited when unset
+    pub rate_per_second: Option<f64>,
+    // Emails that can go out at once after a quiet period, defaults to one second worth
+    pub burst: Option<u32>,
+    // Emails being sent at the same time, unlimited when unset
+    pub max_in_flight: Option<usize>,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct CircuitBreakerSettings {
+    // Transient failures in a row that open the circuit
+    pub failure_threshold: u32,
+    // How long an open circuit refuses emails before a trial, in milliseconds
+    pub open_duration: u64,
+    // Successful trials in a row that close the circuit again
+    pub success_threshold: u32,
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct EmailRetrySettings {
+    // Including the first one
+    pub max_attempts: u32,
+    // Bounds of the exponential backoff, in milliseconds
+    pub base_delay: u64,
+    pub max_delay: u64,
+    // Time budget for all the attempts at one email, in milliseconds
+    pub deadline: u64,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct FileSettings {
+    // Where `.eml` files and their `index.jsonl` are written, created if missing
+    pub directory: String,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct InMemorySettings {
+    // Transports with the same outbox name share their emails, see `InMemoryTransport::named`
+    pub outbox: String,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct PostmarkSettings {
+    pub base_url: String,
+    pub auth_token: Secret<String>,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    pub port: u16,
+    pub tls: SmtpTls,
+    // AUTH is skipped when the server doesn't need it
+    pub credentials: Option<SmtpCredentials>,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpTls {
+    // TLS from the first byte, usually on port 465
+    Implicit,
+    // Plain connection upgraded with STARTTLS, usually on port 587
+    Starttls,
+    // Only for local test servers such as MailHog
+    Disabled,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SmtpCredentials {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+// Any provider taking the email as a JSON object, e.g. `{ "to": ..., "subject": ... }`
+#[derive(serde::Deserialize, Clone)]
+pub struct JsonHttpSettings {
+    pub url: String,
+    // e.g. `Authorization`, with `auth_token` set to `Bearer <key>`
+    pub auth_header: String,
+    pub auth_token: Secret<String>,
+    #[serde(default)]
+    pub fields: JsonHttpFields,
+}
+
+// Where each part of the email goes in the request body, dots separate nested objects
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct JsonHttpFields {
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+    pub headers: String,
+}
+
+impl Default for JsonHttpFields {
+    fn default() -> Self {
+        Self {
+            from: "from".into(),
+            to: "to".into(),
+            subject: "subject".into(),
+            html_body: "html".into(),
+            text_body: "text".into(),
+            headers: "headers".into(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SubscriptionSettings {
+    // How long a confirmation link stays valid, in seconds
+    pub token_ttl: i64,
+    // Minimum delay between two confirmation emails to the same address, in seconds
+    pub resend_interval: i64,
+    // Extra details subscribers may be given, by name and type
+    #[serde(default)]
+    pub attributes: AttributeSchema,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct DeliveryWorkerSettings {
+    // Run the worker alongside the HTTP server, disable it when the worker runs as its own process
+    pub embedded: bool,
+    pub max_retries: i16,
+    // Delay before the first retry, doubled on every following attempt, in milliseconds
+    pub retry_base_delay: u64,
+    pub retry_max_delay: u64,
+    // How long to wait before polling an empty queue again, in milliseconds
+    pub poll_interval: u64,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SessionSettings {
+    // Idle time after which a session expires, in seconds
+    pub ttl: i64,
+    // How often expired sessions are deleted from the database, in seconds
+    pub cleanup_interval: u64,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct IdempotencySettings {
+    // How long a saved response can be replayed, in seconds
+    pub ttl: i64,
+    // How often expired keys are deleted from the database, in seconds
+    pub cleanup_interval: u64,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct PasswordResetSettings {
+    // How long a password reset link stays valid, in seconds
+    pub token_ttl: i64,
+    // Minimum delay between two reset emails to the same address, in seconds
+    pub resend_interval: i64,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct TwoFactorSettings {
+    // Admins without a second factor can't access anything but the enrolment page
+    pub enforce: bool,
+    // Shown by authenticator apps next to the account name
+    pub issuer: String,
+    // Wrong codes allowed, across logins, before the account is locked out of the second step
+    pub max_attempts: u32,
+    // How long the second step stays locked, in seconds
+    pub lockout_duration: i64,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct InviteSettings {
+    // How long an invite link to create an admin account stays valid, in seconds
+    pub token_ttl: i64,
 }
 
 impl DatabaseSettings {
@@ -99,10 +334,90 @@ impl DatabaseSettings {
     }
 }
 
+impl SubscriptionSettings {
+    pub fn token_ttl(&self) -> Duration {
+        Duration::seconds(self.token_ttl)
+    }
+
+    pub fn resend_interval(&self) -> Duration {
+        Duration::seconds(self.resend_interval)
+    }
+}
+
+impl PasswordResetSettings {
+    pub fn token_ttl(&self) -> Duration {
+        Duration::seconds(self.token_ttl)
+    }
+
+    pub fn resend_interval(&self) -> Duration {
+        Duration::seconds(self.resend_interval)
+    }
+}
+
+impl IdempotencySettings {
+    pub fn ttl(&self) -> Duration {
+        Duration::seconds(self.ttl)
+    }
+}
+
+impl TwoFactorSettings {
+    pub fn lockout_duration(&self) -> Duration {
+        Duration::seconds(self.lockout_duration)
+    }
+}
+
+impl InviteSettings {
+    pub fn token_ttl(&self) -> Duration {
+        Duration::seconds(self.token_ttl)
+    }
+}
+
 impl EmailClientSettings {
-    pub fn sender(&self) -> Result<SubscriberEmail, String> {
+    pub fn sender(&self) -> Result<SubscriberEmail, SubscriberEmailError> {
         SubscriberEmail::parse(self.sender_email.clone())
     }
+
+    pub fn timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.timeout)
+    }
+}
+
+impl EmailProvider {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmailProvider::Postmark => "postmark",
+            EmailProvider::Smtp => "smtp",
+            EmailProvider::JsonHttp => "json_http",
+            EmailProvider::File => "file",
+            EmailProvider::InMemory => "in_memory",
+        }
+    }
+}
+
+impl EmailLimitSettings {
+    // Share of the limits for one of `processes` processes, each keeps at least one email
+    pub fn per_process(&self, processes: u32) -> Self {
+        let processes = processes.max(1);
+        Self {
+            rate_per_second: self.rate_per_second.map(|rate| rate / processes as f64),
+            burst: self.burst.map(|burst| (burst / processes).max(1)),
+            max_in_flight: self
+                .max_in_flight
+                .map(|max_in_flight| (max_in_flight / processes as usize).max(1)),
+        }
+    }
+}
+
+impl CircuitBreakerSettings {
+    pub fn open_duration(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.open_duration)
+    }
+}
+
+impl EmailRetrySettings {
+    pub fn deadline(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.deadline)
+    }
 }
 
 pub fn read_configuration() -> Result<Settings, config::ConfigError> {
@@ -118,10 +433,72 @@ pub fn read_configuration() -> Result<Settings, config::ConfigError> {
     let settings = config::Config::builder()
         .add_source(config::File::from(config_dir.join("base.json")))
         .add_source(config::File::from(config_dir.join(env_file)))
+        // Allow deployments to override settings, e.g. `APP_APPLICATION__BASE_URL`
+        .add_source(
+            config::Environment::with_prefix("APP")
+                .prefix_separator("_")
+                .separator("__"),
+        )
         .build()
         .unwrap_or_else(|err| panic!("Cannot read app configurations with error {:?}", err));
 
-    settings.try_deserialize::<Settings>()
+    let settings = settings.try_deserialize::<Settings>()?;
+    settings
+        .validate(&environment)
+        .map_err(config::ConfigError::Message)?;
+    Ok(settings)
+}
+
+impl Settings {
+    // Mistakes the types can't catch, reported before anything starts
+    fn validate(&self, environment: &Environment) -> Result<(), String> {
+        check_hmac_secret(&self.application.hmac_secret, environment)?;
+        check_email_limits(&self.email_client)
+    }
+}
+
+// The token bucket can't work with a rate of zero, nor the semaphore with no slot
+fn check_email_limits(settings: &EmailClientSettings) -> Result<(), String> {
+    if settings.sending_processes < 1 {
+        return Err("`email_client.sending_processes` must be at least 1".into());
+    }
+    for (provider, limits) in &settings.limits {
+        let provider = provider.as_str();
+        if let Some(rate_per_second) = limits.rate_per_second {
+            if !(rate_per_second.is_finite() && rate_per_second > 0.0) {
+                return Err(format!(
+                    "`email_client.limits.{provider}.rate_per_second` must be greater than 0"
+                ));
+            }
+        }
+        if limits.burst == Some(0) {
+            return Err(format!(
+                "`email_client.limits.{provider}.burst` must be at least 1"
+            ));
+        }
+        if limits.max_in_flight == Some(0) {
+            return Err(format!(
+                "`email_client.limits.{provider}.max_in_flight` must be at least 1"
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn check_hmac_secret(secret: &Secret<String>, environment: &Environment) -> Result<(), String> {
+    if secret.expose_secret().len() < 64 {
+        return Err("`application.hmac_secret` must be at least 64 bytes long".into());
+    }
+    if matches!(environment, Environment::Production)
+        && secret.expose_secret() == HMAC_SECRET_PLACEHOLDER
+    {
+        return Err(
+            "`application.hmac_secret` is still the placeholder from `config/base.json`, \
+            set `APP_APPLICATION__HMAC_SECRET`"
+                .into(),
+        );
+    }
+    Ok(())
 }
 
 pub enum Environment {
@@ -151,3 +528,95 @@ impl TryFrom<String> for Environment {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+    use secrecy::Secret;
+
+    use super::{
+        check_email_limits, check_hmac_secret, EmailLimitSettings, EmailProvider, Environment,
+        HMAC_SECRET_PLACEHOLDER,
+    };
+
+    fn email_client_settings(limits: EmailLimitSettings) -> super::EmailClientSettings {
+        let base = std::fs::read_to_string("config/base.json").unwrap();
+        let base: serde_json::Value = serde_json::from_str(&base).unwrap();
+        let mut settings: super::EmailClientSettings =
+            serde_json::from_value(base["email_client"].clone()).unwrap();
+        settings.limits = [(EmailProvider::Postmark, limits)].into();
+        settings
+    }
+
+    #[test]
+    fn the_placeholder_secret_is_only_accepted_locally() {
+        let placeholder = Secret::new(HMAC_SECRET_PLACEHOLDER.to_string());
+
+        assert_ok!(check_hmac_secret(&placeholder, &Environment::Local));
+        assert_err!(check_hmac_secret(&placeholder, &Environment::Production));
+    }
+
+    #[test]
+    fn short_secrets_are_rejected() {
+        let secret = Secret::new("a".repeat(63));
+
+        assert_err!(check_hmac_secret(&secret, &Environment::Local));
+    }
+
+    #[test]
+    fn a_real_secret_is_accepted_in_production() {
+        let secret = Secret::new("a".repeat(64));
+
+        assert_ok!(check_hmac_secret(&secret, &Environment::Production));
+    }
+
+    #[test]
+    fn base_configuration_ships_the_placeholder_secret() {
+        let base = std::fs::read_to_string("config/base.json").unwrap();
+        let base: serde_json::Value = serde_json::from_str(&base).unwrap();
+
+        assert_eq!(base["application"]["hmac_secret"], HMAC_SECRET_PLACEHOLDER);
+    }
+
+    #[test]
+    fn email_limits_must_let_emails_through() {
+        for limits in [
+            EmailLimitSettings {
+                rate_per_second: Some(0.0),
+                ..Default::default()
+            },
+            EmailLimitSettings {
+                rate_per_second: Some(-1.0),
+                ..Default::default()
+            },
+            EmailLimitSettings {
+                burst: Some(0),
+                ..Default::default()
+            },
+            EmailLimitSettings {
+                max_in_flight: Some(0),
+                ..Default::default()
+            },
+        ] {
+            assert_err!(check_email_limits(&email_client_settings(limits)));
+        }
+        assert_ok!(check_email_limits(&email_client_settings(
+            EmailLimitSettings::default()
+        )));
+    }
+
+    #[test]
+    fn email_limits_are_shared_between_sending_processes() {
+        let limits = EmailLimitSettings {
+            rate_per_second: Some(50.0),
+            burst: Some(50),
+            max_in_flight: Some(1),
+        };
+
+        let share = limits.per_process(2);
+
+        assert_eq!(share.rate_per_second, Some(25.0));
+        assert_eq!(share.burst, Some(25));
+        assert_eq!(share.max_in_flight, Some(1));
+    }
+}
@@ -0,0 +1,95 @@
+use std::{future::Future, pin::Pin};
+
+use actix_web::{
+    dev::Payload,
+    http::header::{Accept, Header},
+    web, FromRequest, HttpMessage, HttpRequest,
+};
+
+// Body sent either by an HTML form or by a JavaScript client, picked from the `Content-Type`
+pub struct FormOrJson<T>(pub T);
+
+impl<T> FromRequest for FormOrJson<T>
+where
+    T: serde::de::DeserializeOwned + 'static,
+{
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        if req.content_type() == "application/json" {
+            let json = web::Json::<T>::from_request(req, payload);
+            Box::pin(async move { Ok(Self(json.await?.into_inner())) })
+        } else {
+            let form = web::Form::<T>::from_request(req, payload);
+            Box::pin(async move { Ok(Self(form.await?.into_inner())) })
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseFormat {
+    Html,
+    Json,
+}
+
+impl ResponseFormat {
+    // Best ranked format of the `Accept` header, JSON unless the client prefers HTML
+    pub fn preferred(req: &HttpRequest) -> Self {
+        let accept = match Accept::parse(req) {
+            Ok(accept) => accept,
+            Err(_) => return Self::Json,
+        };
+        accept
+            .ranked()
+            .iter()
+            .find_map(|mime| match mime.essence_str() {
+                "text/html" => Some(Self::Html),
+                "application/json" | "application/problem+json" | "application/*" | "*/*" => {
+                    Some(Self::Json)
+                }
+                _ => None,
+            })
+            .unwrap_or(Self::Json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::ResponseFormat;
+
+    fn preferred(accept: &str) -> ResponseFormat {
+        ResponseFormat::preferred(
+            &TestRequest::default()
+                .insert_header(("Accept", accept))
+                .to_http_request(),
+        )
+    }
+
+    #[test]
+    fn browsers_get_html() {
+        assert_eq!(
+            preferred("text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8"),
+            ResponseFormat::Html
+        );
+    }
+
+    #[test]
+    fn json_is_the_default() {
+        assert_eq!(preferred("*/*"), ResponseFormat::Json);
+        assert_eq!(
+            ResponseFormat::preferred(&TestRequest::default().to_http_request()),
+            ResponseFormat::Json
+        );
+    }
+
+    #[test]
+    fn quality_values_are_honoured() {
+        assert_eq!(
+            preferred("text/html;q=0.5, application/json"),
+            ResponseFormat::Json
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use std::future::{ready, Ready};
+
+use actix_web::{
+    body::MessageBody,
+    cookie::{Cookie, Key, SameSite},
+    dev::{Payload, ServiceRequest, ServiceResponse},
+    error::InternalError,
+    http::{header::ContentType, Method},
+    middleware::Next,
+    web, FromRequest, HttpMessage, HttpRequest, HttpResponse,
+};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+
+use crate::utils::{add_signed_cookie, e500, get_signed_cookie, MiddlewareFuture};
+
+const CSRF_COOKIE_NAME: &str = "csrf_token";
+const CSRF_FIELD_NAME: &str = "csrf_token";
+// For scripts, which can set headers but don't send forms
+const CSRF_HEADER_NAME: &str = "X-CSRF-Token";
+const TOKEN_LENGTH: usize = 32;
+
+// Requests that can't be forged by a third-party page: bearer API calls don't rely on cookies,
+// and one-click unsubscriptions (RFC 8058) are sent by mailbox providers, authenticated by their token
+const CSRF_EXEMPT_PATHS: [&str; 2] = ["/api/", "/subscriptions/unsubscribe/one-click"];
+
+const INVALID_CSRF_TOKEN_PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Form expired</title>
+</head>
+<body>
+    <p>This form has expired, please go back, reload the page and try again.</p>
+</body>
+</html>"#;
+
+// Token of the current visitor, every HTML form must embed it with `form_field`
+#[derive(Clone, Debug)]
+pub struct CsrfToken(String);
+
+impl CsrfToken {
+    fn generate() -> Self {
+        let mut rng = thread_rng();
+        let token = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+            .map(char::from)
+            .take(TOKEN_LENGTH)
+            .collect();
+        Self(token)
+    }
+
+    pub fn form_field(&self) -> String {
+        format!(
+            r#"<input type="hidden" name="{}" value="{}">"#,
+            CSRF_FIELD_NAME, self.0
+        )
+    }
+
+    // Constant time, so the expected token can't be guessed byte by byte
+    fn matches(&self, candidate: &str) -> bool {
+        self.0.len() == candidate.len()
+            && self
+                .0
+                .bytes()
+                .zip(candidate.bytes())
+                .fold(0, |acc, (a, b)| acc | (a ^ b))
+                == 0
+    }
+}
+
+impl AsRef<str> for CsrfToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromRequest for CsrfToken {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<CsrfToken, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<CsrfToken>()
+                .cloned()
+                .ok_or_else(|| e500("The CSRF middleware is not registered")),
+        )
+    }
+}
+
+// Double-submit cookie: the token is kept in a signed cookie and must be sent back with every
+// state changing request, which a third-party page can't do since it can't read our cookies
+pub fn csrf_protection<B>(
+    key: Key,
+) -> impl Fn(ServiceRequest, Next<B>) -> MiddlewareFuture<B> + 'static
+where
+    B: MessageBody + 'static,
+{
+    move |req, next| Box::pin(check_csrf_token(key.clone(), req, next))
+}
+
+async fn check_csrf_token<B: MessageBody>(
+    key: Key,
+    mut req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<B>, actix_web::Error> {
+    let cookie_token = get_signed_cookie(&req, &key, CSRF_COOKIE_NAME)
+        .filter(|token| token.len() == TOKEN_LENGTH)
+        .map(CsrfToken);
+    let token = cookie_token.clone().unwrap_or_else(CsrfToken::generate);
+    req.extensions_mut().insert(token.clone());
+
+    if requires_csrf_check(&req) {
+        let submitted_token = get_submitted_token(&mut req).await?;
+        let is_valid = match (&cookie_token, submitted_token) {
+            (Some(expected), Some(submitted)) => expected.matches(&submitted),
+            _ => false,
+        };
+        if !is_valid {
+            let response = HttpResponse::Forbidden()
+                .content_type(ContentType::html())
+                .body(INVALID_CSRF_TOKEN_PAGE);
+            let err = anyhow::anyhow!("The CSRF token is missing or invalid");
+            return Err(InternalError::from_response(err, response).into());
+        }
+    }
+
+    let mut response = next.call(req).await?;
+    if cookie_token.is_none() {
+        let cookie = Cookie::build(CSRF_COOKIE_NAME, token.0)
+            .path("/")
+            .secure(true)
+            .http_only(true)
+            .same_site(SameSite::Lax)
+            .finish();
+        add_signed_cookie(&mut response, &key, cookie)?;
+    }
+    Ok(response)
+}
+
+// JSON bodies can't be sent cross-origin without a CORS preflight, which we never allow
+fn requires_csrf_check(req: &ServiceRequest) -> bool {
+    let is_safe_method = matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    let is_exempt = CSRF_EXEMPT_PATHS
+        .iter()
+        .any(|path| req.path().starts_with(path));
+    let is_json = req.content_type() == "application/json";
+
+    !is_safe_method && !is_exempt && !is_json
+}
+
+async fn get_submitted_token(req: &mut ServiceRequest) -> Result<Option<String>, actix_web::Error> {
+    if let Some(header) = req.headers().get(CSRF_HEADER_NAME) {
+        return Ok(header.to_str().ok().map(str::to_string));
+    }
+    if req.content_type() != "application/x-www-form-urlencoded" {
+        return Ok(None);
+    }
+
+    // Read the form, then put the body back for the handler's own extractor
+    let body = req.extract::<web::Bytes>().await?;
+    let fields: Vec<(String, String)> = serde_urlencoded::from_bytes(&body).unwrap_or_default();
+    req.set_payload(body.into());
+
+    Ok(fields
+        .into_iter()
+        .find(|(name, _)| name == CSRF_FIELD_NAME)
+        .map(|(_, value)| value))
+}
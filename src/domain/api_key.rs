@@ -0,0 +1,105 @@
+use sha2::{Digest, Sha256};
+
+use super::random_token::random_alphanumeric;
+
+// Keys look like `z2p_<id>_<secret>`, the `z2p_<id>` part is the public prefix
+const KEY_TAG: &str = "z2p_";
+const ID_LENGTH: usize = 8;
+const SECRET_LENGTH: usize = 32;
+
+pub struct ApiKey(String);
+
+impl ApiKey {
+    pub fn parse(s: String) -> Result<Self, String> {
+        let is_valid = s
+            .strip_prefix(KEY_TAG)
+            .and_then(|rest| rest.split_once('_'))
+            .is_some_and(|(id, secret)| {
+                id.len() == ID_LENGTH
+                    && secret.len() == SECRET_LENGTH
+                    && id.chars().all(|c| c.is_ascii_alphanumeric())
+                    && secret.chars().all(|c| c.is_ascii_alphanumeric())
+            });
+
+        if is_valid {
+            Ok(Self(s))
+        } else {
+            Err("The API key is malformed.".into())
+        }
+    }
+
+    pub fn generate() -> Self {
+        let id = random_alphanumeric(ID_LENGTH);
+        let secret = random_alphanumeric(SECRET_LENGTH);
+        Self(format!("{}{}_{}", KEY_TAG, id, secret))
+    }
+
+    // Safe to store and display, it identifies the key without granting access
+    pub fn prefix(&self) -> &str {
+        &self.0[..KEY_TAG.len() + ID_LENGTH]
+    }
+
+    // Keys are random and high entropy, a fast unsalted hash is enough
+    pub fn hash(&self) -> String {
+        format!("{:x}", Sha256::digest(self.0.as_bytes()))
+    }
+}
+
+impl AsRef<str> for ApiKey {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+// Never print the secret part, keys end up in logs through request spans
+impl std::fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ApiKey({}_...)", self.prefix())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use crate::domain::api_key::ApiKey;
+
+    #[test]
+    fn generated_key_is_valid() {
+        let key = ApiKey::generate();
+        assert_ok!(ApiKey::parse(key.as_ref().to_string()));
+    }
+
+    #[test]
+    fn key_without_tag_is_rejected() {
+        let key = format!("abc_{}_{}", "a".repeat(8), "b".repeat(32));
+        assert_err!(ApiKey::parse(key));
+    }
+
+    #[test]
+    fn key_with_a_short_secret_is_rejected() {
+        let key = format!("z2p_{}_{}", "a".repeat(8), "b".repeat(31));
+        assert_err!(ApiKey::parse(key));
+    }
+
+    #[test]
+    fn key_with_non_alphanumeric_characters_is_rejected() {
+        let key = format!("z2p_{}_{}-", "a".repeat(8), "b".repeat(31));
+        assert_err!(ApiKey::parse(key));
+    }
+
+    #[test]
+    fn prefix_does_not_contain_the_secret() {
+        let key = ApiKey::generate();
+        let (_, secret) = key.as_ref().rsplit_once('_').unwrap();
+        assert!(key.as_ref().starts_with(key.prefix()));
+        assert!(!key.prefix().contains(secret));
+    }
+
+    #[test]
+    fn debug_output_does_not_contain_the_secret() {
+        let key = ApiKey::generate();
+        let (_, secret) = key.as_ref().rsplit_once('_').unwrap();
+        assert!(!format!("{:?}", key).contains(secret));
+    }
+}
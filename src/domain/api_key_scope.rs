@@ -0,0 +1,66 @@
+use crate::domain::user_role::Permission;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiKeyScope {
+    SubscribersRead,
+    SubscribersWrite,
+    NewslettersPublish,
+}
+
+impl ApiKeyScope {
+    pub const ALL: [ApiKeyScope; 3] = [
+        ApiKeyScope::SubscribersRead,
+        ApiKeyScope::SubscribersWrite,
+        ApiKeyScope::NewslettersPublish,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiKeyScope::SubscribersRead => "subscribers:read",
+            ApiKeyScope::SubscribersWrite => "subscribers:write",
+            ApiKeyScope::NewslettersPublish => "newsletters:publish",
+        }
+    }
+
+    // What the owner's role must allow for the scope to be granted, and then used
+    pub fn required_permission(&self) -> Permission {
+        match self {
+            ApiKeyScope::SubscribersRead => Permission::ViewSubscribers,
+            ApiKeyScope::SubscribersWrite => Permission::AddSubscribers,
+            ApiKeyScope::NewslettersPublish => Permission::PublishNewsletters,
+        }
+    }
+}
+
+impl TryFrom<String> for ApiKeyScope {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "subscribers:read" => Ok(ApiKeyScope::SubscribersRead),
+            "subscribers:write" => Ok(ApiKeyScope::SubscribersWrite),
+            "newsletters:publish" => Ok(ApiKeyScope::NewslettersPublish),
+            other => Err(format!("{} is not a valid API key scope.", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok_eq};
+
+    use crate::domain::api_key_scope::ApiKeyScope;
+
+    #[test]
+    fn unknown_scope_is_rejected() {
+        assert_err!(ApiKeyScope::try_from("subscribers:delete".to_string()));
+    }
+
+    #[test]
+    fn scope_roundtrips_through_its_string_form() {
+        for scope in ApiKeyScope::ALL {
+            let raw = scope.as_str().to_string();
+            assert_ok_eq!(ApiKeyScope::try_from(raw), scope);
+        }
+    }
+}
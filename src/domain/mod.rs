@@ -1,3 +1,11 @@
+pub mod api_key;
+pub mod api_key_scope;
+pub mod new_password;
 pub mod new_subscriber;
+pub mod random_token;
+pub mod subscriber_attributes;
 pub mod subscriber_email;
 pub mod subscriber_name;
+pub mod subscription_status;
+pub mod user_role;
+pub mod validation_error;
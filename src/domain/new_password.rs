@@ -0,0 +1,66 @@
+use secrecy::{ExposeSecret, Secret};
+
+// OWASP recommendations: long enough to resist guessing, bounded to keep hashing cheap
+const MIN_LENGTH: usize = 12;
+const MAX_LENGTH: usize = 128;
+
+#[derive(Debug)]
+pub struct NewPassword(Secret<String>);
+
+impl NewPassword {
+    pub fn parse(s: Secret<String>) -> Result<Self, String> {
+        let length = s.expose_secret().chars().count();
+
+        if length < MIN_LENGTH {
+            Err(format!(
+                "The new password must be at least {} characters long.",
+                MIN_LENGTH
+            ))
+        } else if length > MAX_LENGTH {
+            Err(format!(
+                "The new password must be at most {} characters long.",
+                MAX_LENGTH
+            ))
+        } else {
+            Ok(Self(s))
+        }
+    }
+}
+
+impl AsRef<Secret<String>> for NewPassword {
+    fn as_ref(&self) -> &Secret<String> {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+    use secrecy::Secret;
+
+    use crate::domain::new_password::NewPassword;
+
+    #[test]
+    fn too_short_password_is_rejected() {
+        let password = Secret::new("a".repeat(11));
+        assert_err!(NewPassword::parse(password));
+    }
+
+    #[test]
+    fn too_long_password_is_rejected() {
+        let password = Secret::new("a".repeat(129));
+        assert_err!(NewPassword::parse(password));
+    }
+
+    #[test]
+    fn password_within_bounds_is_accepted() {
+        assert_ok!(NewPassword::parse(Secret::new("a".repeat(12))));
+        assert_ok!(NewPassword::parse(Secret::new("a".repeat(128))));
+    }
+
+    #[test]
+    fn length_is_counted_in_characters() {
+        let password = Secret::new("ё".repeat(12));
+        assert_ok!(NewPassword::parse(password));
+    }
+}
@@ -1,18 +1,110 @@
 use crate::routes::FormData;
 
-use super::{subscriber_email::SubscriberEmail, subscriber_name::SubscriberName};
+use super::{
+    subscriber_attributes::{AttributeSchema, SubscriberAttributes},
+    subscriber_email::SubscriberEmail,
+    subscriber_name::SubscriberName,
+    validation_error::{FieldError, ValidationError},
+};
 
+#[derive(Debug)]
 pub struct NewSubscriber {
     pub email: SubscriberEmail,
     pub name: SubscriberName,
+    pub attributes: SubscriberAttributes,
 }
 
-impl TryFrom<FormData> for NewSubscriber {
-    type Error = String;
+impl NewSubscriber {
+    // Every field is checked, so all the errors are reported at once
+    pub fn parse(value: FormData, schema: &AttributeSchema) -> Result<Self, ValidationError> {
+        match (
+            SubscriberName::parse(value.name),
+            SubscriberEmail::parse(value.email),
+            SubscriberAttributes::parse(value.attributes, schema),
+        ) {
+            (Ok(name), Ok(email), Ok(attributes)) => Ok(Self {
+                name,
+                email,
+                attributes,
+            }),
+            (name, email, attributes) => {
+                let mut field_errors = Vec::new();
+                if let Err(err) = name {
+                    field_errors.push(FieldError::new("name", err));
+                }
+                if let Err(err) = email {
+                    field_errors.push(FieldError::new("email", err));
+                }
+                if let Err(err) = attributes {
+                    field_errors.push(FieldError::new("attributes", err));
+                }
+                Err(ValidationError::new(field_errors))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::NewSubscriber;
+    use crate::{
+        domain::subscriber_attributes::{AttributeSchema, AttributeType},
+        routes::FormData,
+    };
+
+    fn form(name: &str, email: &str) -> FormData {
+        FormData {
+            name: name.into(),
+            email: email.into(),
+            attributes: Default::default(),
+        }
+    }
+
+    fn schema() -> AttributeSchema {
+        AttributeSchema::new(HashMap::from([(
+            "company".to_string(),
+            AttributeType::String,
+        )]))
+    }
+
+    #[test]
+    fn valid_form_is_accepted() {
+        assert_ok!(NewSubscriber::parse(
+            form("Ursula Le Guin", "ursula@domain.com"),
+            &schema()
+        ));
+    }
+
+    #[test]
+    fn invalid_name_is_reported_with_its_field() {
+        let err = assert_err!(NewSubscriber::parse(
+            form("<script>", "ursula@domain.com"),
+            &schema()
+        ));
+        assert_eq!(err.to_string(), "name: contains forbidden character '<'");
+    }
+
+    #[test]
+    fn every_invalid_field_is_reported() {
+        let err = assert_err!(NewSubscriber::parse(form("", "not-an-email"), &schema()));
+        let fields: Vec<_> = err.field_errors().iter().map(|err| err.field).collect();
+        assert_eq!(fields, vec!["name", "email"]);
+    }
+
+    #[test]
+    fn invalid_attributes_are_reported_with_the_other_fields() {
+        let mut form = form("", "ursula@domain.com");
+        form.attributes = json!({ "company": 42 }).as_object().unwrap().clone();
 
-    fn try_from(value: FormData) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(value.name)?;
-        let email = SubscriberEmail::parse(value.email)?;
-        Ok(Self { name, email })
+        let err = assert_err!(NewSubscriber::parse(form, &schema()));
+        let fields: Vec<_> = err.field_errors().iter().map(|err| err.field).collect();
+        assert_eq!(fields, vec!["name", "attributes"]);
+        assert_eq!(err.field_errors()[1].message, "'company' must be a string");
     }
 }
@@ -0,0 +1,100 @@
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use sha2::{Digest, Sha256};
+
+// Sent in confirmation links, each confirmation email gets a new one
+pub type SubscriptionToken = RandomToken<25>;
+// Stable per subscriber, sent in every email
+pub type UnsubscribeToken = RandomToken<32>;
+// Only the hash is stored, a leaked table can't be used to reset passwords
+pub type PasswordResetToken = RandomToken<32>;
+// Only the hash is stored, a leaked table can't be used to create accounts
+pub type InviteToken = RandomToken<32>;
+
+// Random alphanumeric string of `LEN` characters, sent in links and forms
+#[derive(Debug)]
+pub struct RandomToken<const LEN: usize>(String);
+
+impl<const LEN: usize> RandomToken<LEN> {
+    // Anything but a random alphanumeric string of the right length
+    // can be rejected before hitting the database
+    pub fn parse(s: String) -> Result<Self, String> {
+        let is_valid_length = s.chars().count() == LEN;
+        let is_alphanumeric = s.chars().all(|c| c.is_ascii_alphanumeric());
+
+        if is_valid_length && is_alphanumeric {
+            Ok(Self(s))
+        } else {
+            Err(format!("{} is not a valid token.", s))
+        }
+    }
+
+    pub fn generate() -> Self {
+        Self(random_alphanumeric(LEN))
+    }
+
+    // Tokens are random and high entropy, a fast unsalted hash is enough
+    pub fn hash(&self) -> String {
+        format!("{:x}", Sha256::digest(self.0.as_bytes()))
+    }
+}
+
+impl<const LEN: usize> AsRef<str> for RandomToken<LEN> {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+pub fn random_alphanumeric(length: usize) -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(length)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use crate::domain::random_token::RandomToken;
+
+    type Token = RandomToken<32>;
+
+    #[test]
+    fn empty_string_is_rejected() {
+        assert_err!(Token::parse("".to_string()));
+    }
+
+    #[test]
+    fn wrong_length_token_is_rejected() {
+        assert_err!(Token::parse("a".repeat(31)));
+        assert_err!(Token::parse("a".repeat(33)));
+    }
+
+    #[test]
+    fn non_alphanumeric_token_is_rejected() {
+        let token = format!("{}-", "a".repeat(31));
+        assert_err!(Token::parse(token));
+    }
+
+    #[test]
+    fn generated_token_is_valid() {
+        let token = Token::generate();
+        assert_eq!(token.as_ref().len(), 32);
+        assert_ok!(Token::parse(token.as_ref().to_string()));
+    }
+
+    #[test]
+    fn length_is_part_of_the_type() {
+        let token = RandomToken::<25>::generate();
+        assert_err!(Token::parse(token.as_ref().to_string()));
+    }
+
+    #[test]
+    fn hash_does_not_contain_the_token() {
+        let token = Token::generate();
+        let hash = token.hash();
+        assert_eq!(hash.len(), 64);
+        assert!(!hash.contains(token.as_ref()));
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+
+use serde_json::{Map, Value};
+use unicode_segmentation::UnicodeSegmentation;
+
+const MAX_STRING_LENGTH: usize = 256;
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AttributeType {
+    String,
+    Integer,
+    Boolean,
+}
+
+impl AttributeType {
+    fn matches(&self, value: &Value) -> bool {
+        match self {
+            AttributeType::String => value.is_string(),
+            AttributeType::Integer => value.is_i64() || value.is_u64(),
+            AttributeType::Boolean => value.is_boolean(),
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            AttributeType::String => "a string",
+            AttributeType::Integer => "an integer",
+            AttributeType::Boolean => "a boolean",
+        }
+    }
+}
+
+// Attributes a subscriber may have and their type, e.g. `{ "company": "string" }`
+#[derive(serde::Deserialize, Clone, Debug, Default)]
+pub struct AttributeSchema(HashMap<String, AttributeType>);
+
+impl AttributeSchema {
+    pub fn new(attributes: HashMap<String, AttributeType>) -> Self {
+        Self(attributes)
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum SubscriberAttributesError {
+    #[error("'{0}' is not a known attribute")]
+    Unknown(String),
+    #[error("'{0}' must be {1}")]
+    WrongType(String, &'static str),
+    #[error("'{0}' must be at most {MAX_STRING_LENGTH} characters long")]
+    TooLong(String),
+}
+
+// Extra details about a subscriber, stored as JSONB
+#[derive(Debug, Clone, Default)]
+pub struct SubscriberAttributes(Map<String, Value>);
+
+impl SubscriberAttributes {
+    // Null values are dropped, as if the attribute had not been sent
+    pub fn parse(
+        values: Map<String, Value>,
+        schema: &AttributeSchema,
+    ) -> Result<SubscriberAttributes, SubscriberAttributesError> {
+        let mut attributes = Map::new();
+        for (name, value) in values {
+            let attribute_type = match schema.0.get(&name) {
+                Some(attribute_type) => attribute_type,
+                None => return Err(SubscriberAttributesError::Unknown(name)),
+            };
+            if value.is_null() {
+                continue;
+            }
+            if !attribute_type.matches(&value) {
+                let description = attribute_type.description();
+                return Err(SubscriberAttributesError::WrongType(name, description));
+            }
+            if let Value::String(text) = &value {
+                if text.graphemes(true).count() > MAX_STRING_LENGTH {
+                    return Err(SubscriberAttributesError::TooLong(name));
+                }
+            }
+            attributes.insert(name, value);
+        }
+        Ok(Self(attributes))
+    }
+
+    pub fn to_json(&self) -> Value {
+        Value::Object(self.0.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use claims::{assert_err_eq, assert_ok};
+    use serde_json::{json, Map, Value};
+
+    use super::{AttributeSchema, AttributeType, SubscriberAttributes, SubscriberAttributesError};
+
+    fn schema() -> AttributeSchema {
+        AttributeSchema::new(HashMap::from([
+            ("company".to_string(), AttributeType::String),
+            ("seats".to_string(), AttributeType::Integer),
+            ("trial".to_string(), AttributeType::Boolean),
+        ]))
+    }
+
+    fn values(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn attributes_matching_the_schema_are_accepted() {
+        let values = values(json!({ "company": "Acme", "seats": 12, "trial": false }));
+
+        let attributes = assert_ok!(SubscriberAttributes::parse(values.clone(), &schema()));
+        assert_eq!(attributes.to_json(), Value::Object(values));
+    }
+
+    #[test]
+    fn unknown_attribute_is_rejected() {
+        assert_err_eq!(
+            SubscriberAttributes::parse(values(json!({ "plan": "pro" })), &schema()),
+            SubscriberAttributesError::Unknown("plan".into())
+        );
+    }
+
+    #[test]
+    fn attribute_of_the_wrong_type_is_rejected() {
+        for value in [json!({ "seats": "12" }), json!({ "seats": 1.5 })] {
+            assert_err_eq!(
+                SubscriberAttributes::parse(values(value), &schema()),
+                SubscriberAttributesError::WrongType("seats".into(), "an integer")
+            );
+        }
+    }
+
+    #[test]
+    fn too_long_string_is_rejected() {
+        let company = "a".repeat(257);
+        assert_err_eq!(
+            SubscriberAttributes::parse(values(json!({ "company": company })), &schema()),
+            SubscriberAttributesError::TooLong("company".into())
+        );
+    }
+
+    #[test]
+    fn null_attributes_are_dropped() {
+        let attributes = assert_ok!(SubscriberAttributes::parse(
+            values(json!({ "company": null })),
+            &schema()
+        ));
+        assert_eq!(attributes.to_json(), json!({}));
+    }
+}
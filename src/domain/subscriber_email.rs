@@ -1,14 +1,18 @@
 use validator::validate_email;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SubscriberEmail(String);
 
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+#[error("is not a valid email address")]
+pub struct SubscriberEmailError;
+
 impl SubscriberEmail {
-    pub fn parse(s: String) -> Result<Self, String> {
+    pub fn parse(s: String) -> Result<Self, SubscriberEmailError> {
         if validate_email(&s) {
             Ok(Self(s))
         } else {
-            Err(format!("{} is not a valid subscriber email.", s))
+            Err(SubscriberEmailError)
         }
     }
 }
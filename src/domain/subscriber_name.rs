@@ -1,13 +1,29 @@
 use unicode_segmentation::UnicodeSegmentation;
 
+const MAX_LENGTH: usize = 256;
+const FORBIDDEN_CHARS: [char; 10] = ['/', '(', ')', '<', '>', '[', ']', '\\', '{', '}'];
+
 #[derive(Debug)]
 pub struct SubscriberName(String);
 
+// Worded to follow the field name, e.g. `name: contains forbidden character '<'`
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum SubscriberNameError {
+    #[error("must not be empty")]
+    Empty,
+    #[error("must be at most {} characters long", MAX_LENGTH)]
+    TooLong,
+    #[error("contains forbidden character '{0}'")]
+    ForbiddenCharacter(char),
+}
+
 impl SubscriberName {
     // parse is the only way to build an instance of SubscriberName outside of domain module
     // Therefore, any instance of SubscriberName will satisfy our constrains
-    pub fn parse(s: String) -> Result<SubscriberName, String> {
-        let is_empty = s.trim().is_empty();
+    pub fn parse(s: String) -> Result<SubscriberName, SubscriberNameError> {
+        if s.trim().is_empty() {
+            return Err(SubscriberNameError::Empty);
+        }
 
         // A grapheme is defined by the Unicode standard as a "user-perceived"
         // character: `å` is a single grapheme, but it is composed of two characters
@@ -16,14 +32,12 @@ impl SubscriberName {
         // `graphemes` returns an iterator over the graphemes in the input `s`.
         // `true` specifies that we want to use the extended grapheme definition set,
         // the recommended one.
-        let is_too_long = s.graphemes(true).count() > 256;
-
-        let forbidden_chars = ['/', '(', ')', '<', '>', '[', ']', '\\', '{', '}'];
-        let contains_forbidden_chars = s.chars().any(|g| forbidden_chars.contains(&g));
+        if s.graphemes(true).count() > MAX_LENGTH {
+            return Err(SubscriberNameError::TooLong);
+        }
 
-        if is_empty || is_too_long || contains_forbidden_chars {
-            // panic!("{} is not a valid subscriber name", s);
-            return Err(format!("{} is not a valid subscriber name", s));
+        if let Some(forbidden_char) = s.chars().find(|c| FORBIDDEN_CHARS.contains(c)) {
+            return Err(SubscriberNameError::ForbiddenCharacter(forbidden_char));
         }
 
         Ok(Self(s))
@@ -40,7 +54,7 @@ impl AsRef<str> for SubscriberName {
 mod tests {
     use claims::{assert_err, assert_ok};
 
-    use crate::domain::subscriber_name::SubscriberName;
+    use crate::domain::subscriber_name::{SubscriberName, SubscriberNameError};
 
     #[test]
     fn empty_string_is_rejected() {
@@ -74,6 +88,13 @@ mod tests {
         }
     }
 
+    #[test]
+    fn the_first_forbidden_char_is_reported() {
+        let err = assert_err!(SubscriberName::parse("Ursula <Le> Guin".to_string()));
+        assert_eq!(err, SubscriberNameError::ForbiddenCharacter('<'));
+        assert_eq!(err.to_string(), "contains forbidden character '<'");
+    }
+
     #[test]
     fn valid_name_parse_successfully() {
         let name = "Minh Hoang Tien".to_string();
@@ -0,0 +1,53 @@
+#[derive(Debug, PartialEq, Eq)]
+pub enum SubscriptionStatus {
+    Pending,
+    Confirmed,
+    Unsubscribed,
+}
+
+impl SubscriptionStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubscriptionStatus::Pending => "pending",
+            SubscriptionStatus::Confirmed => "confirmed",
+            SubscriptionStatus::Unsubscribed => "unsubscribed",
+        }
+    }
+}
+
+impl TryFrom<String> for SubscriptionStatus {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "pending" => Ok(SubscriptionStatus::Pending),
+            "confirmed" => Ok(SubscriptionStatus::Confirmed),
+            "unsubscribed" => Ok(SubscriptionStatus::Unsubscribed),
+            other => Err(format!("{} is not a valid subscription status.", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok_eq};
+
+    use crate::domain::subscription_status::SubscriptionStatus;
+
+    #[test]
+    fn unknown_status_is_rejected() {
+        assert_err!(SubscriptionStatus::try_from("deleted".to_string()));
+    }
+
+    #[test]
+    fn status_roundtrips_through_its_string_form() {
+        for status in [
+            SubscriptionStatus::Pending,
+            SubscriptionStatus::Confirmed,
+            SubscriptionStatus::Unsubscribed,
+        ] {
+            let raw = status.as_str().to_string();
+            assert_ok_eq!(SubscriptionStatus::try_from(raw), status);
+        }
+    }
+}
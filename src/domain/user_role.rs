@@ -0,0 +1,90 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserRole {
+    Owner,
+    Editor,
+    Viewer,
+}
+
+// Actions an admin route can require, granted through the user's role
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    ViewSubscribers,
+    AddSubscribers,
+    DeleteSubscribers,
+    PublishNewsletters,
+    ManageApiKeys,
+    ManageUsers,
+}
+
+impl UserRole {
+    pub const ALL: [UserRole; 3] = [UserRole::Owner, UserRole::Editor, UserRole::Viewer];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserRole::Owner => "owner",
+            UserRole::Editor => "editor",
+            UserRole::Viewer => "viewer",
+        }
+    }
+
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        match self {
+            UserRole::Owner => true,
+            UserRole::Editor => matches!(
+                permission,
+                Permission::ViewSubscribers
+                    | Permission::PublishNewsletters
+                    | Permission::ManageApiKeys
+            ),
+            UserRole::Viewer => matches!(permission, Permission::ViewSubscribers),
+        }
+    }
+}
+
+impl TryFrom<String> for UserRole {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "owner" => Ok(UserRole::Owner),
+            "editor" => Ok(UserRole::Editor),
+            "viewer" => Ok(UserRole::Viewer),
+            other => Err(format!("{} is not a valid role.", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok_eq};
+
+    use crate::domain::user_role::{Permission, UserRole};
+
+    #[test]
+    fn unknown_role_is_rejected() {
+        assert_err!(UserRole::try_from("admin".to_string()));
+    }
+
+    #[test]
+    fn role_roundtrips_through_its_string_form() {
+        for role in UserRole::ALL {
+            let raw = role.as_str().to_string();
+            assert_ok_eq!(UserRole::try_from(raw), role);
+        }
+    }
+
+    #[test]
+    fn editors_can_publish_but_not_delete_subscribers() {
+        assert!(UserRole::Editor.has_permission(Permission::PublishNewsletters));
+        assert!(!UserRole::Editor.has_permission(Permission::AddSubscribers));
+        assert!(!UserRole::Editor.has_permission(Permission::DeleteSubscribers));
+        assert!(!UserRole::Editor.has_permission(Permission::ManageUsers));
+    }
+
+    #[test]
+    fn viewers_can_only_view_subscribers() {
+        assert!(UserRole::Viewer.has_permission(Permission::ViewSubscribers));
+        assert!(!UserRole::Viewer.has_permission(Permission::PublishNewsletters));
+        assert!(!UserRole::Viewer.has_permission(Permission::ManageApiKeys));
+    }
+}
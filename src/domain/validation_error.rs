@@ -0,0 +1,52 @@
+use std::fmt::Display;
+
+// Rejected value of a single input field, e.g. `name: contains forbidden character '<'`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &'static str, message: impl Display) -> Self {
+        Self {
+            field,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Display for FieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+// Every rejected field of an input, so clients can fix them all at once
+#[derive(Debug)]
+pub struct ValidationError(Vec<FieldError>);
+
+impl ValidationError {
+    pub fn new(field_errors: Vec<FieldError>) -> Self {
+        Self(field_errors)
+    }
+
+    pub fn field_errors(&self) -> &[FieldError] {
+        &self.0
+    }
+}
+
+impl From<FieldError> for ValidationError {
+    fn from(field_error: FieldError) -> Self {
+        Self(vec![field_error])
+    }
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let field_errors: Vec<String> = self.0.iter().map(ToString::to_string).collect();
+        write!(f, "{}", field_errors.join("; "))
+    }
+}
+
+impl std::error::Error for ValidationError {}
@@ -0,0 +1,261 @@
+use std::{fmt, sync::Mutex};
+
+use tokio::time::Instant;
+
+use crate::configurations::CircuitBreakerSettings;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    // Emails go through, failures are counted
+    Closed,
+    // Emails are refused right away until `open_duration` is over
+    Open,
+    // One trial email at a time decides whether to close or open again
+    HalfOpen,
+}
+
+impl fmt::Display for CircuitState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self {
+            CircuitState::Closed => "closed",
+            CircuitState::Open => "open",
+            CircuitState::HalfOpen => "half_open",
+        };
+        f.write_str(state)
+    }
+}
+
+// Stops calling a provider that keeps failing, instead of waiting for its timeout on every email
+pub struct CircuitBreaker {
+    provider: String,
+    // Never opens when unset
+    settings: Option<CircuitBreakerSettings>,
+    inner: Mutex<BreakerState>,
+}
+
+struct BreakerState {
+    state: CircuitState,
+    consecutive_failures: u32,
+    consecutive_successes: u32,
+    opened_at: Instant,
+    trial_in_flight: bool,
+}
+
+// A call let through by the breaker, dropping it without an outcome counts as a failure
+// e.g. when the attempt is cancelled by the retry deadline
+pub struct CircuitCall<'a> {
+    breaker: &'a CircuitBreaker,
+    recorded: bool,
+}
+
+impl CircuitBreaker {
+    pub fn new(provider: impl Into<String>, settings: Option<CircuitBreakerSettings>) -> Self {
+        Self {
+            provider: provider.into(),
+            settings,
+            inner: Mutex::new(BreakerState {
+                state: CircuitState::Closed,
+                consecutive_failures: 0,
+                consecutive_successes: 0,
+                opened_at: Instant::now(),
+                trial_in_flight: false,
+            }),
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        self.inner.lock().unwrap().state
+    }
+
+    // `None` when the provider shouldn't be called right now
+    pub fn try_call(&self) -> Option<CircuitCall<'_>> {
+        let Some(settings) = &self.settings else {
+            return Some(self.call());
+        };
+        let mut inner = self.inner.lock().unwrap();
+        match inner.state {
+            CircuitState::Closed => {}
+            CircuitState::Open if inner.opened_at.elapsed() >= settings.open_duration() => {
+                inner.state = CircuitState::HalfOpen;
+                inner.consecutive_successes = 0;
+                inner.trial_in_flight = true;
+                tracing::info!(
+                    provider = %self.provider,
+                    circuit.state = %CircuitState::HalfOpen,
+                    "Email provider circuit is half-open, letting a trial email through"
+                );
+            }
+            CircuitState::HalfOpen if !inner.trial_in_flight => inner.trial_in_flight = true,
+            CircuitState::Open | CircuitState::HalfOpen => return None,
+        }
+        Some(self.call())
+    }
+
+    fn call(&self) -> CircuitCall<'_> {
+        CircuitCall {
+            breaker: self,
+            recorded: false,
+        }
+    }
+
+    fn on_success(&self) {
+        let Some(settings) = &self.settings else {
+            return;
+        };
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures = 0;
+        if inner.state == CircuitState::HalfOpen {
+            inner.trial_in_flight = false;
+            inner.consecutive_successes += 1;
+            if inner.consecutive_successes >= settings.success_threshold {
+                inner.state = CircuitState::Closed;
+                tracing::info!(
+                    provider = %self.provider,
+                    circuit.state = %CircuitState::Closed,
+                    "Email provider circuit is closed again"
+                );
+            }
+        }
+    }
+
+    fn on_failure(&self) {
+        let Some(settings) = &self.settings else {
+            return;
+        };
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures += 1;
+        let should_open = match inner.state {
+            CircuitState::Closed => inner.consecutive_failures >= settings.failure_threshold,
+            CircuitState::HalfOpen => true,
+            // Calls started before the circuit opened
+            CircuitState::Open => false,
+        };
+        if should_open {
+            inner.state = CircuitState::Open;
+            inner.opened_at = Instant::now();
+            inner.trial_in_flight = false;
+            tracing::warn!(
+                provider = %self.provider,
+                circuit.state = %CircuitState::Open,
+                consecutive_failures = inner.consecutive_failures,
+                "Email provider circuit is open, emails are refused for {:?}",
+                settings.open_duration()
+            );
+        }
+    }
+}
+
+impl CircuitCall<'_> {
+    // Only a provider that can't be reached, or is overloaded, is a failure
+    // A rejected email says nothing about the provider's health
+    pub fn record(mut self, transient_failure: bool) {
+        self.recorded = true;
+        if transient_failure {
+            self.breaker.on_failure();
+        } else {
+            self.breaker.on_success();
+        }
+    }
+}
+
+impl Drop for CircuitCall<'_> {
+    fn drop(&mut self) {
+        if !self.recorded {
+            self.breaker.on_failure();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use claims::assert_some;
+
+    use super::{CircuitBreaker, CircuitState};
+    use crate::configurations::CircuitBreakerSettings;
+
+    fn breaker(open_duration: u64) -> CircuitBreaker {
+        CircuitBreaker::new(
+            "test",
+            Some(CircuitBreakerSettings {
+                failure_threshold: 2,
+                open_duration,
+                success_threshold: 1,
+            }),
+        )
+    }
+
+    #[test]
+    fn opens_after_consecutive_failures_and_refuses_calls() {
+        let breaker = breaker(60_000);
+
+        breaker.try_call().unwrap().record(true);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        breaker.try_call().unwrap().record(true);
+
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(breaker.try_call().is_none());
+    }
+
+    #[test]
+    fn a_success_resets_the_failure_count() {
+        let breaker = breaker(60_000);
+
+        breaker.try_call().unwrap().record(true);
+        breaker.try_call().unwrap().record(false);
+        breaker.try_call().unwrap().record(true);
+
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn lets_a_single_trial_through_once_open_duration_is_over() {
+        let breaker = breaker(20);
+        breaker.try_call().unwrap().record(true);
+        breaker.try_call().unwrap().record(true);
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        let trial = assert_some!(breaker.try_call());
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert!(breaker.try_call().is_none());
+
+        trial.record(false);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    async fn a_failed_trial_opens_the_circuit_again() {
+        let breaker = breaker(20);
+        breaker.try_call().unwrap().record(true);
+        breaker.try_call().unwrap().record(true);
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        breaker.try_call().unwrap().record(true);
+
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(breaker.try_call().is_none());
+    }
+
+    #[test]
+    fn dropped_calls_are_failures() {
+        let breaker = breaker(60_000);
+
+        drop(breaker.try_call());
+        drop(breaker.try_call());
+
+        assert_eq!(breaker.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn never_opens_without_settings() {
+        let breaker = CircuitBreaker::new("test", None);
+
+        for _ in 0..10 {
+            breaker.try_call().unwrap().record(true);
+        }
+
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+}
@@ -0,0 +1,168 @@
+use std::path::PathBuf;
+
+use chrono::Utc;
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+use super::{Email, EmailError, EmailTransport};
+use crate::configurations::FileSettings;
+
+// One JSON entry per line
+const INDEX_FILE_NAME: &str = "index.jsonl";
+
+// Summary of a written email, the full message is in `file`
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct FileIndexEntry {
+    pub file: String,
+    pub sent_at: String,
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+}
+
+// Writes every email as an `.eml` file, which any mail client can open, for local development
+pub struct FileTransport {
+    directory: PathBuf,
+}
+
+impl FileTransport {
+    pub fn new(settings: FileSettings) -> Self {
+        Self {
+            directory: PathBuf::from(settings.directory),
+        }
+    }
+
+    fn index_path(&self) -> PathBuf {
+        self.directory.join(INDEX_FILE_NAME)
+    }
+
+    // Appended with a single write, so processes sharing the directory (e.g. the server
+    // and a standalone worker) never overwrite each other's entries
+    async fn add_to_index(&self, entry: FileIndexEntry) -> Result<(), EmailError> {
+        let mut line = serde_json::to_vec(&entry).map_err(std::io::Error::other)?;
+        line.push(b'\n');
+
+        let mut index = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.index_path())
+            .await?;
+        index.write_all(&line).await?;
+        index.flush().await?;
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for FileTransport {
+    async fn send(&self, email: &Email) -> Result<(), EmailError> {
+        let message = email.to_message()?;
+        tokio::fs::create_dir_all(&self.directory).await?;
+
+        let file = format!("{}.eml", Uuid::new_v4());
+        tokio::fs::write(self.directory.join(&file), message.formatted()).await?;
+
+        self.add_to_index(FileIndexEntry {
+            file,
+            sent_at: Utc::now().to_rfc3339(),
+            from: email.sender.as_ref().to_string(),
+            to: email.recipient.as_ref().to_string(),
+            subject: email.subject.clone(),
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use claims::assert_ok;
+    use uuid::Uuid;
+
+    use super::{FileIndexEntry, FileTransport};
+    use crate::{
+        configurations::FileSettings,
+        domain::subscriber_email::SubscriberEmail,
+        email_client::{EmailClient, EmailHeader},
+    };
+
+    fn email(address: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(address.into()).unwrap()
+    }
+
+    fn file_email_client(directory: &Path) -> EmailClient {
+        let settings = FileSettings {
+            directory: directory.to_string_lossy().into_owned(),
+        };
+        EmailClient::new(email("sender@example.com"), FileTransport::new(settings))
+    }
+
+    #[tokio::test]
+    async fn every_email_is_written_and_indexed() {
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let email_client = file_email_client(&directory);
+
+        for subject in ["First", "Second"] {
+            assert_ok!(
+                email_client
+                    .send_email_with_headers(
+                        email("ursula@example.com"),
+                        subject,
+                        "<p>Hi</p>",
+                        "Hi",
+                        &[EmailHeader::new("X-Custom", "custom-value")],
+                    )
+                    .await
+            );
+        }
+
+        let index = std::fs::read_to_string(directory.join("index.jsonl")).unwrap();
+        let index: Vec<FileIndexEntry> = index
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let subjects: Vec<_> = index.iter().map(|entry| entry.subject.as_str()).collect();
+        assert_eq!(subjects, vec!["First", "Second"]);
+        assert_eq!(index[0].to, "ursula@example.com");
+
+        let message = std::fs::read_to_string(directory.join(&index[0].file)).unwrap();
+        assert!(message.contains("Subject: First"));
+        assert!(message.contains("To: ursula@example.com"));
+        assert!(message.contains("X-Custom: custom-value"));
+        assert!(message.contains("<p>Hi</p>"));
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn transports_sharing_a_directory_keep_every_entry() {
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&directory).unwrap();
+        // Entries already there are never rewritten, even when they can't be read
+        std::fs::write(directory.join("index.jsonl"), "not json\n").unwrap();
+        // Separate transports, as in the server and a standalone worker
+        let (server, worker) = (file_email_client(&directory), file_email_client(&directory));
+
+        let send_all = |email_client: EmailClient| async move {
+            for _ in 0..20 {
+                assert_ok!(
+                    email_client
+                        .send_email(email("ursula@example.com"), "Hello", "<p>Hi</p>", "Hi")
+                        .await
+                );
+            }
+        };
+        tokio::join!(send_all(server), send_all(worker));
+
+        let index = std::fs::read_to_string(directory.join("index.jsonl")).unwrap();
+        let lines: Vec<_> = index.lines().collect();
+        assert_eq!(lines.len(), 41);
+        assert_eq!(lines[0], "not json");
+        for line in &lines[1..] {
+            assert_ok!(serde_json::from_str::<FileIndexEntry>(line));
+        }
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
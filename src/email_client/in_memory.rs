@@ -0,0 +1,84 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use once_cell::sync::Lazy;
+
+use super::{Email, EmailError, EmailTransport};
+
+type Outbox = Arc<Mutex<Vec<Email>>>;
+
+// Outboxes by name, so the application and a delivery worker built from the same settings
+// end up sharing their emails
+static OUTBOXES: Lazy<Mutex<HashMap<String, Outbox>>> = Lazy::new(Default::default);
+
+// Keeps every email in memory for tests to inspect, nothing is ever delivered
+#[derive(Clone)]
+pub struct InMemoryTransport {
+    outbox: Outbox,
+}
+
+impl InMemoryTransport {
+    pub fn named(outbox: &str) -> Self {
+        let mut outboxes = OUTBOXES.lock().unwrap();
+        let outbox = outboxes.entry(outbox.to_string()).or_default().clone();
+        Self { outbox }
+    }
+
+    pub fn emails(&self) -> Vec<Email> {
+        self.outbox.lock().unwrap().clone()
+    }
+
+    pub fn sent_to(&self, recipient: &str) -> Vec<Email> {
+        self.emails()
+            .into_iter()
+            .filter(|email| email.recipient.as_ref() == recipient)
+            .collect()
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for InMemoryTransport {
+    async fn send(&self, email: &Email) -> Result<(), EmailError> {
+        self.outbox.lock().unwrap().push(email.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::assert_ok;
+    use uuid::Uuid;
+
+    use super::InMemoryTransport;
+    use crate::{domain::subscriber_email::SubscriberEmail, email_client::EmailClient};
+
+    fn email(address: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(address.into()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn emails_are_shared_by_transports_with_the_same_name() {
+        let name = Uuid::new_v4().to_string();
+        let email_client =
+            EmailClient::new(email("sender@example.com"), InMemoryTransport::named(&name));
+
+        for recipient in ["ursula@example.com", "octavia@example.com"] {
+            assert_ok!(
+                email_client
+                    .send_email(email(recipient), "Hello", "<p>Hi</p>", "Hi")
+                    .await
+            );
+        }
+
+        let outbox = InMemoryTransport::named(&name);
+        assert_eq!(outbox.emails().len(), 2);
+        let sent = outbox.sent_to("ursula@example.com");
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].subject, "Hello");
+        assert!(InMemoryTransport::named("another-outbox")
+            .sent_to("ursula@example.com")
+            .is_empty());
+    }
+}
@@ -0,0 +1,200 @@
+use std::time::Duration;
+
+use reqwest::Client;
+use secrecy::{ExposeSecret, Secret};
+use serde_json::{Map, Value};
+
+use super::{check_http_status, Email, EmailError, EmailTransport};
+use crate::configurations::{JsonHttpFields, JsonHttpSettings};
+
+// Providers we don't have a dedicated transport for, the body layout comes from the settings
+pub struct JsonHttpTransport {
+    http_client: Client,
+    url: String,
+    auth_header: String,
+
+    // We don't want to get this into log by accident
+    auth_token: Secret<String>,
+    fields: JsonHttpFields,
+}
+
+impl JsonHttpTransport {
+    pub fn new(settings: JsonHttpSettings, timeout: Duration) -> Self {
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+        Self {
+            http_client,
+            url: settings.url,
+            auth_header: settings.auth_header,
+            auth_token: settings.auth_token,
+            fields: settings.fields,
+        }
+    }
+
+    fn payload(&self, email: &Email) -> Value {
+        let mut payload = Map::new();
+        insert_at(
+            &mut payload,
+            &self.fields.from,
+            email.sender.as_ref().into(),
+        );
+        insert_at(
+            &mut payload,
+            &self.fields.to,
+            email.recipient.as_ref().into(),
+        );
+        insert_at(
+            &mut payload,
+            &self.fields.subject,
+            email.subject.clone().into(),
+        );
+        insert_at(
+            &mut payload,
+            &self.fields.html_body,
+            email.html_body.clone().into(),
+        );
+        insert_at(
+            &mut payload,
+            &self.fields.text_body,
+            email.text_body.clone().into(),
+        );
+        if !email.headers.is_empty() {
+            let headers = email
+                .headers
+                .iter()
+                .map(|header| (header.name.clone(), header.value.clone().into()))
+                .collect();
+            insert_at(&mut payload, &self.fields.headers, Value::Object(headers));
+        }
+        Value::Object(payload)
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for JsonHttpTransport {
+    async fn send(&self, email: &Email) -> Result<(), EmailError> {
+        let response = self
+            .http_client
+            .post(&self.url)
+            .header(&self.auth_header, self.auth_token.expose_secret())
+            .json(&self.payload(email))
+            .send()
+            .await?;
+
+        check_http_status(response)
+    }
+}
+
+// `content.html` ends up in `{ "content": { "html": ... } }`
+fn insert_at(object: &mut Map<String, Value>, path: &str, value: Value) {
+    match path.split_once('.') {
+        Some((key, rest)) => {
+            let child = object
+                .entry(key)
+                .or_insert_with(|| Value::Object(Map::new()));
+            if !child.is_object() {
+                *child = Value::Object(Map::new());
+            }
+            if let Value::Object(child) = child {
+                insert_at(child, rest, value);
+            }
+        }
+        None => {
+            object.insert(path.to_string(), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+    use fake::{faker::internet::en::SafeEmail, Fake};
+    use secrecy::Secret;
+    use serde_json::json;
+    use wiremock::{
+        matchers::{any, body_json, header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::JsonHttpTransport;
+    use crate::{
+        configurations::{JsonHttpFields, JsonHttpSettings},
+        domain::subscriber_email::SubscriberEmail,
+        email_client::{EmailClient, EmailHeader},
+    };
+
+    fn subscriber_email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn email_client(url: String, fields: JsonHttpFields) -> (EmailClient, SubscriberEmail) {
+        let sender = subscriber_email();
+        let settings = JsonHttpSettings {
+            url,
+            auth_header: "Authorization".into(),
+            auth_token: Secret::new("Bearer secret-key".into()),
+            fields,
+        };
+        let transport = JsonHttpTransport::new(settings, std::time::Duration::from_secs(1));
+        (EmailClient::new(sender.clone(), transport), sender)
+    }
+
+    #[tokio::test]
+    async fn send_email_posts_the_configured_body_layout() {
+        let mock_server = MockServer::start().await;
+        let fields = JsonHttpFields {
+            from: "sender.email".into(),
+            to: "recipient".into(),
+            html_body: "content.html".into(),
+            text_body: "content.text".into(),
+            ..Default::default()
+        };
+        let (email_client, sender) = email_client(format!("{}/v1/send", mock_server.uri()), fields);
+        let recipient = subscriber_email();
+
+        Mock::given(path("/v1/send"))
+            .and(method("POST"))
+            .and(header("Authorization", "Bearer secret-key"))
+            .and(body_json(json!({
+                "sender": { "email": sender.as_ref() },
+                "recipient": recipient.as_ref(),
+                "subject": "Hello",
+                "content": { "html": "<p>Hi</p>", "text": "Hi" },
+                "headers": { "X-Custom": "custom-value" },
+            })))
+            .respond_with(ResponseTemplate::new(202))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let result = email_client
+            .send_email_with_headers(
+                recipient.clone(),
+                "Hello",
+                "<p>Hi</p>",
+                "Hi",
+                &[EmailHeader::new("X-Custom", "custom-value")],
+            )
+            .await;
+
+        assert_ok!(result);
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_server_response_not_ok() {
+        let mock_server = MockServer::start().await;
+        let (email_client, _) = email_client(mock_server.uri(), JsonHttpFields::default());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(400))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let result = email_client
+            .send_email(subscriber_email(), "Hello", "<p>Hi</p>", "Hi")
+            .await;
+
+        let err = assert_err!(result);
+        assert!(!err.is_transient());
+    }
+}
@@ -0,0 +1,224 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::Instant,
+};
+
+use crate::configurations::EmailLimitSettings;
+
+// Client-side limits of one provider, so it doesn't have to throttle us
+pub struct SendLimiter {
+    provider: String,
+    bucket: Option<Mutex<TokenBucket>>,
+    in_flight: Option<Arc<Semaphore>>,
+    wait_metrics: PermitWaitMetrics,
+}
+
+// Holding it counts as one email in flight
+#[derive(Debug)]
+pub struct SendPermit {
+    _in_flight: Option<OwnedSemaphorePermit>,
+}
+
+impl SendLimiter {
+    pub fn new(provider: impl Into<String>, settings: &EmailLimitSettings) -> Self {
+        let bucket = settings.rate_per_second.map(|rate| {
+            let burst = settings.burst.unwrap_or_else(|| rate.ceil() as u32).max(1);
+            Mutex::new(TokenBucket::new(rate, burst))
+        });
+        let in_flight = settings
+            .max_in_flight
+            .map(|max_in_flight| Arc::new(Semaphore::new(max_in_flight.max(1))));
+
+        Self {
+            provider: provider.into(),
+            bucket,
+            in_flight,
+            wait_metrics: PermitWaitMetrics::default(),
+        }
+    }
+
+    pub fn provider(&self) -> &str {
+        &self.provider
+    }
+
+    pub fn wait_metrics(&self) -> PermitWaitSnapshot {
+        self.wait_metrics.snapshot()
+    }
+
+    // Wait for a free slot, then for a token, the time spent here ends up in the metrics
+    #[tracing::instrument(name = "Waiting for an email send permit", skip(self), fields(provider = %self.provider))]
+    pub async fn acquire(&self) -> SendPermit {
+        let start = Instant::now();
+        let in_flight = match &self.in_flight {
+            Some(semaphore) => Some(
+                semaphore
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("The in-flight semaphore is never closed"),
+            ),
+            None => None,
+        };
+        if let Some(bucket) = &self.bucket {
+            // Sleep outside of the lock, another task may take the token first and we wait again
+            loop {
+                let taken = bucket.lock().unwrap().try_take();
+                match taken {
+                    Ok(()) => break,
+                    Err(wait) => tokio::time::sleep(wait).await,
+                }
+            }
+        }
+        self.wait_metrics.record(start.elapsed());
+
+        SendPermit {
+            _in_flight: in_flight,
+        }
+    }
+}
+
+struct TokenBucket {
+    rate_per_second: f64,
+    capacity: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate_per_second: f64, burst: u32) -> Self {
+        Self {
+            rate_per_second,
+            capacity: burst as f64,
+            tokens: burst as f64,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    // Take a token, or tell how long until the next one
+    fn try_take(&mut self) -> Result<(), Duration> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate_per_second).min(self.capacity);
+        self.refilled_at = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - self.tokens) / self.rate_per_second,
+            ))
+        }
+    }
+}
+
+#[derive(Default)]
+struct PermitWaitMetrics {
+    count: AtomicU64,
+    total_micros: AtomicU64,
+    max_micros: AtomicU64,
+}
+
+impl PermitWaitMetrics {
+    fn record(&self, wait: Duration) {
+        let micros = wait.as_micros() as u64;
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total_micros.fetch_add(micros, Ordering::Relaxed);
+        self.max_micros.fetch_max(micros, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> PermitWaitSnapshot {
+        PermitWaitSnapshot {
+            count: self.count.load(Ordering::Relaxed),
+            total: Duration::from_micros(self.total_micros.load(Ordering::Relaxed)),
+            max: Duration::from_micros(self.max_micros.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+// Time spent waiting for permits since the application started
+#[derive(Debug, Clone, Copy)]
+pub struct PermitWaitSnapshot {
+    pub count: u64,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use claims::{assert_err, assert_ok};
+    use tokio::time::Instant;
+
+    use super::SendLimiter;
+    use crate::configurations::EmailLimitSettings;
+
+    #[tokio::test]
+    async fn permits_beyond_the_burst_are_spread_at_the_configured_rate() {
+        let limiter = SendLimiter::new(
+            "test",
+            &EmailLimitSettings {
+                rate_per_second: Some(20.0),
+                burst: Some(2),
+                max_in_flight: None,
+            },
+        );
+
+        let start = Instant::now();
+        for _ in 0..4 {
+            limiter.acquire().await;
+        }
+
+        // Two from the burst, then one every 50ms
+        assert!(start.elapsed() >= Duration::from_millis(90));
+        let metrics = limiter.wait_metrics();
+        assert_eq!(metrics.count, 4);
+        assert!(metrics.max >= Duration::from_millis(40));
+    }
+
+    #[tokio::test]
+    async fn in_flight_emails_are_capped() {
+        let limiter = SendLimiter::new(
+            "test",
+            &EmailLimitSettings {
+                rate_per_second: None,
+                burst: None,
+                max_in_flight: Some(1),
+            },
+        );
+
+        let permit = limiter.acquire().await;
+        assert_err!(tokio::time::timeout(Duration::from_millis(50), limiter.acquire()).await);
+
+        drop(permit);
+        assert_ok!(tokio::time::timeout(Duration::from_millis(50), limiter.acquire()).await);
+    }
+
+    #[tokio::test]
+    async fn unlimited_by_default() {
+        let limiter = SendLimiter::new("test", &EmailLimitSettings::default());
+
+        let start = Instant::now();
+        let _permits: Vec<_> = acquire_many(&limiter, 100).await;
+
+        assert!(start.elapsed() < Duration::from_millis(50));
+        assert_eq!(limiter.wait_metrics().count, 100);
+    }
+
+    async fn acquire_many(limiter: &SendLimiter, n: usize) -> Vec<super::SendPermit> {
+        let mut permits = Vec::with_capacity(n);
+        for _ in 0..n {
+            permits.push(limiter.acquire().await);
+        }
+        permits
+    }
+}
@@ -0,0 +1,668 @@
+mod circuit_breaker;
+mod file;
+mod in_memory;
+mod json_http;
+mod limiter;
+mod postmark;
+mod smtp;
+
+pub use circuit_breaker::*;
+pub use file::*;
+pub use in_memory::*;
+pub use json_http::*;
+pub use limiter::*;
+pub use postmark::*;
+pub use smtp::*;
+
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use lettre::{
+    message::{
+        header::{HeaderName, HeaderValue},
+        Mailbox, MultiPart,
+    },
+    Message,
+};
+
+use rand::{thread_rng, Rng};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use tokio::time::Instant;
+
+use crate::{
+    configurations::{CircuitBreakerSettings, EmailLimitSettings, EmailRetrySettings},
+    domain::subscriber_email::SubscriberEmail,
+};
+
+// Extra header added to an outgoing email, e.g. `List-Unsubscribe`
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct EmailHeader {
+    pub name: String,
+    pub value: String,
+}
+
+impl EmailHeader {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+        }
+    }
+}
+
+// A single email, as handed over to the transport
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub sender: SubscriberEmail,
+    pub recipient: SubscriberEmail,
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+    pub headers: Vec<EmailHeader>,
+}
+
+impl Email {
+    // RFC 5322 message with both bodies, mail clients pick the one they can display
+    fn to_message(&self) -> Result<Message, EmailError> {
+        let invalid_message = |err: &dyn std::fmt::Display| {
+            EmailError::Permanent(DeliveryError::InvalidMessage(err.to_string()))
+        };
+
+        let from: Mailbox = self
+            .sender
+            .as_ref()
+            .parse()
+            .map_err(|err| invalid_message(&err))?;
+        let to: Mailbox = self
+            .recipient
+            .as_ref()
+            .parse()
+            .map_err(|err| invalid_message(&err))?;
+        let mut builder = Message::builder().from(from).to(to).subject(&self.subject);
+        for header in &self.headers {
+            let name = HeaderName::new_from_ascii(header.name.clone())
+                .map_err(|err| invalid_message(&err))?;
+            builder = builder.raw_header(HeaderValue::new(name, header.value.clone()));
+        }
+
+        builder
+            .multipart(MultiPart::alternative_plain_html(
+                self.text_body.clone(),
+                self.html_body.clone(),
+            ))
+            .map_err(|err| invalid_message(&err))
+    }
+}
+
+// What went wrong, whatever the transport
+#[derive(thiserror::Error, Debug)]
+pub enum DeliveryError {
+    #[error(transparent)]
+    Http(reqwest::Error),
+    #[error(transparent)]
+    Smtp(lettre::transport::smtp::Error),
+    #[error("Failed to build the email: {0}")]
+    InvalidMessage(String),
+    #[error(transparent)]
+    Io(std::io::Error),
+    #[error("No attempt went through before the deadline")]
+    DeadlineExceeded,
+    #[error("The circuit of every email provider is open")]
+    CircuitOpen,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum EmailError {
+    // Timeouts, connection issues, throttling and server errors may go away on their own
+    #[error("Temporary failure while sending the email: {cause}")]
+    Transient {
+        cause: DeliveryError,
+        // Delay asked by the provider, from `Retry-After`
+        retry_after: Option<Duration>,
+    },
+    // Sending the same email again would fail the same way
+    #[error("Failed to send the email: {0}")]
+    Permanent(DeliveryError),
+}
+
+impl EmailError {
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Self::Transient { .. })
+    }
+
+    fn transient(cause: DeliveryError) -> Self {
+        Self::Transient {
+            cause,
+            retry_after: None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for EmailError {
+    fn from(err: reqwest::Error) -> Self {
+        let is_transient = err.is_timeout()
+            || err.is_connect()
+            || err
+                .status()
+                .is_some_and(|status| status.is_server_error() || status.as_u16() == 429);
+        if is_transient {
+            Self::transient(DeliveryError::Http(err))
+        } else {
+            Self::Permanent(DeliveryError::Http(err))
+        }
+    }
+}
+
+impl From<lettre::transport::smtp::Error> for EmailError {
+    // 4xx replies and dropped connections, anything but a 5xx rejection, are worth retrying
+    fn from(err: lettre::transport::smtp::Error) -> Self {
+        if err.is_permanent() {
+            Self::Permanent(DeliveryError::Smtp(err))
+        } else {
+            Self::transient(DeliveryError::Smtp(err))
+        }
+    }
+}
+
+impl From<std::io::Error> for EmailError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Permanent(DeliveryError::Io(err))
+    }
+}
+
+// Same as `Response::error_for_status`, keeping the `Retry-After` of throttling and server errors
+fn check_http_status(response: reqwest::Response) -> Result<(), EmailError> {
+    let retry_after = parse_retry_after(response.headers());
+    match response.error_for_status() {
+        Ok(_) => Ok(()),
+        Err(err) => match EmailError::from(err) {
+            EmailError::Transient { cause, .. } => {
+                Err(EmailError::Transient { cause, retry_after })
+            }
+            err => Err(err),
+        },
+    }
+}
+
+// Either a number of seconds or an HTTP date
+fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    // A date in the past means right away
+    Some(
+        (date.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+// How emails leave the application, see `EmailClientSettings::provider`
+#[async_trait::async_trait]
+pub trait EmailTransport: Send + Sync {
+    async fn send(&self, email: &Email) -> Result<(), EmailError>;
+}
+
+// One provider the client can send through, with its own limits and circuit breaker
+#[derive(Clone)]
+pub struct EmailProviderClient {
+    name: String,
+    transport: Arc<dyn EmailTransport>,
+    limiter: Arc<SendLimiter>,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl EmailProviderClient {
+    // Never throttled, and its circuit never opens
+    pub fn new(name: impl Into<String>, transport: impl EmailTransport + 'static) -> Self {
+        let name = name.into();
+        Self {
+            transport: Arc::new(transport),
+            limiter: Arc::new(SendLimiter::new(
+                name.clone(),
+                &EmailLimitSettings::default(),
+            )),
+            breaker: Arc::new(CircuitBreaker::new(name.clone(), None)),
+            name,
+        }
+    }
+
+    pub fn with_limits(mut self, limits: &EmailLimitSettings) -> Self {
+        self.limiter = Arc::new(SendLimiter::new(self.name.clone(), limits));
+        self
+    }
+
+    pub fn with_circuit_breaker(mut self, settings: Option<CircuitBreakerSettings>) -> Self {
+        self.breaker = Arc::new(CircuitBreaker::new(self.name.clone(), settings));
+        self
+    }
+}
+
+// What the health and metrics endpoints report about a provider
+#[derive(Debug, Clone)]
+pub struct EmailProviderStatus {
+    pub provider: String,
+    pub circuit: CircuitState,
+    pub permit_wait: PermitWaitSnapshot,
+}
+
+// Cheap to clone, clones share the transports, the limits and the circuit breakers
+#[derive(Clone)]
+pub struct EmailClient {
+    // The primary first, then the fallbacks in order
+    providers: Vec<EmailProviderClient>,
+    sender: SubscriberEmail,
+    // A single attempt when unset
+    retry: Option<EmailRetrySettings>,
+}
+
+impl EmailClient {
+    pub fn new(sender: SubscriberEmail, transport: impl EmailTransport + 'static) -> Self {
+        Self::with_provider(sender, EmailProviderClient::new("default", transport))
+    }
+
+    pub fn with_provider(sender: SubscriberEmail, primary: EmailProviderClient) -> Self {
+        Self {
+            providers: vec![primary],
+            sender,
+            retry: None,
+        }
+    }
+
+    // Only used while the circuits of the providers before it are open
+    pub fn with_fallback(mut self, fallback: EmailProviderClient) -> Self {
+        self.providers.push(fallback);
+        self
+    }
+
+    pub fn with_retry(mut self, retry: Option<EmailRetrySettings>) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn provider_statuses(&self) -> Vec<EmailProviderStatus> {
+        self.providers
+            .iter()
+            .map(|provider| EmailProviderStatus {
+                provider: provider.name.clone(),
+                circuit: provider.breaker.state(),
+                permit_wait: provider.limiter.wait_metrics(),
+            })
+            .collect()
+    }
+
+    pub async fn send_email(
+        &self,
+        recipient: SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), EmailError> {
+        self.send_email_with_headers(recipient, subject, html_content, text_content, &[])
+            .await
+    }
+
+    pub async fn send_email_with_headers(
+        &self,
+        recipient: SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader],
+    ) -> Result<(), EmailError> {
+        let email = Email {
+            sender: self.sender.clone(),
+            recipient,
+            subject: subject.to_owned(),
+            html_body: html_content.to_owned(),
+            text_body: text_content.to_owned(),
+            headers: headers.to_vec(),
+        };
+        match &self.retry {
+            Some(retry) => self.send_with_retries(&email, retry).await,
+            None => self.send_once(&email).await,
+        }
+    }
+
+    // Through the first provider whose circuit lets it, every attempt waits for its own permit
+    async fn send_once(&self, email: &Email) -> Result<(), EmailError> {
+        for (position, provider) in self.providers.iter().enumerate() {
+            // Permit first, so time spent waiting on our own limits is not counted
+            // against the provider, nor holds the half-open trial slot
+            let permit = provider.limiter.acquire().await;
+            let Some(call) = provider.breaker.try_call() else {
+                drop(permit);
+                continue;
+            };
+            if position > 0 {
+                tracing::info!(
+                    provider = %provider.name,
+                    "Failing over to a fallback email provider"
+                );
+            }
+
+            let result = provider.transport.send(email).await;
+            drop(permit);
+            call.record(matches!(&result, Err(err) if err.is_transient()));
+            return result;
+        }
+        Err(EmailError::transient(DeliveryError::CircuitOpen))
+    }
+
+    // Transient failures are retried until `max_attempts` or the deadline is reached
+    async fn send_with_retries(
+        &self,
+        email: &Email,
+        retry: &EmailRetrySettings,
+    ) -> Result<(), EmailError> {
+        let deadline = Instant::now() + retry.deadline();
+        let mut attempt = 1;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let err = match tokio::time::timeout(remaining, self.send_once(email)).await {
+                Ok(Ok(())) => return Ok(()),
+                Ok(Err(err)) => err,
+                Err(_) => return Err(EmailError::transient(DeliveryError::DeadlineExceeded)),
+            };
+
+            let delay = match &err {
+                EmailError::Transient { retry_after, .. } if attempt < retry.max_attempts => {
+                    backoff_delay(retry, attempt, *retry_after)
+                }
+                _ => return Err(err),
+            };
+            // No point in waiting if the next attempt can't start in time
+            if Instant::now() + delay >= deadline {
+                return Err(err);
+            }
+
+            tracing::warn!(
+                error.cause_chain = ?err,
+                attempt,
+                "Failed to send an email, retrying in {:?}",
+                delay
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+// Full jitter: anywhere between zero and the exponential backoff, so clients failing together
+// don't retry together, but never sooner than the provider asked for
+fn backoff_delay(
+    retry: &EmailRetrySettings,
+    attempt: u32,
+    retry_after: Option<Duration>,
+) -> Duration {
+    let factor = 2u64.saturating_pow(attempt.saturating_sub(1));
+    let cap = retry.base_delay.saturating_mul(factor).min(retry.max_delay);
+    let delay = Duration::from_millis(thread_rng().gen_range(0..=cap));
+    delay.max(retry_after.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use claims::{assert_err, assert_ok};
+    use secrecy::Secret;
+    use wiremock::{matchers::any, Mock, MockServer, ResponseTemplate};
+
+    use super::{
+        CircuitState, DeliveryError, EmailClient, EmailError, EmailProviderClient,
+        InMemoryTransport, PostmarkTransport,
+    };
+    use crate::{
+        configurations::{
+            CircuitBreakerSettings, EmailLimitSettings, EmailRetrySettings, PostmarkSettings,
+        },
+        domain::subscriber_email::SubscriberEmail,
+    };
+
+    fn email(address: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(address.into()).unwrap()
+    }
+
+    fn email_client(base_url: String, deadline: u64) -> EmailClient {
+        let settings = PostmarkSettings {
+            base_url,
+            auth_token: Secret::new("token".into()),
+        };
+        EmailClient::new(
+            email("sender@example.com"),
+            PostmarkTransport::new(settings, Duration::from_secs(1)),
+        )
+        .with_retry(Some(EmailRetrySettings {
+            max_attempts: 3,
+            base_delay: 10,
+            max_delay: 50,
+            deadline,
+        }))
+    }
+
+    async fn send(email_client: &EmailClient) -> Result<(), EmailError> {
+        email_client
+            .send_email(email("ursula@example.com"), "Hello", "<p>Hi</p>", "Hi")
+            .await
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_retried_until_the_email_goes_through() {
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        assert_ok!(send(&email_client(mock_server.uri(), 5000)).await);
+    }
+
+    #[tokio::test]
+    async fn transient_failures_are_reported_after_max_attempts() {
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(3)
+            .mount(&mock_server)
+            .await;
+
+        let err = assert_err!(send(&email_client(mock_server.uri(), 5000)).await);
+        assert!(err.is_transient());
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let err = assert_err!(send(&email_client(mock_server.uri(), 5000)).await);
+        assert!(!err.is_transient());
+    }
+
+    #[tokio::test]
+    async fn throttled_requests_wait_for_retry_after() {
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let start = Instant::now();
+        assert_ok!(send(&email_client(mock_server.uri(), 5000)).await);
+        assert!(start.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn retries_stop_at_the_deadline() {
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "60"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let start = Instant::now();
+        let err = assert_err!(send(&email_client(mock_server.uri(), 2000)).await);
+        assert!(err.is_transient());
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn slow_attempts_are_cut_at_the_deadline() {
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(10)))
+            .mount(&mock_server)
+            .await;
+
+        let start = Instant::now();
+        let err = assert_err!(send(&email_client(mock_server.uri(), 300)).await);
+        assert!(err.is_transient());
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    fn postmark_provider(base_url: String) -> EmailProviderClient {
+        let settings = PostmarkSettings {
+            base_url,
+            auth_token: Secret::new("token".into()),
+        };
+        EmailProviderClient::new(
+            "postmark",
+            PostmarkTransport::new(settings, Duration::from_secs(1)),
+        )
+        .with_circuit_breaker(Some(CircuitBreakerSettings {
+            failure_threshold: 2,
+            open_duration: 60_000,
+            success_threshold: 1,
+        }))
+    }
+
+    #[tokio::test]
+    async fn an_open_circuit_fails_fast_without_calling_the_provider() {
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+        let email_client = EmailClient::with_provider(
+            email("sender@example.com"),
+            postmark_provider(mock_server.uri()),
+        );
+
+        for _ in 0..2 {
+            assert_err!(send(&email_client).await);
+        }
+        let err = assert_err!(send(&email_client).await);
+
+        assert!(err.is_transient());
+        assert!(matches!(
+            err,
+            EmailError::Transient {
+                cause: DeliveryError::CircuitOpen,
+                ..
+            }
+        ));
+        assert_eq!(
+            email_client.provider_statuses()[0].circuit,
+            CircuitState::Open
+        );
+    }
+
+    #[tokio::test]
+    async fn waiting_for_a_permit_does_not_count_against_the_circuit() {
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // A single email every ten seconds, the circuit opens on the first failure
+        let provider = postmark_provider(mock_server.uri())
+            .with_limits(&EmailLimitSettings {
+                rate_per_second: Some(0.1),
+                burst: Some(1),
+                max_in_flight: None,
+            })
+            .with_circuit_breaker(Some(CircuitBreakerSettings {
+                failure_threshold: 1,
+                open_duration: 60_000,
+                success_threshold: 1,
+            }));
+        let email_client = EmailClient::with_provider(email("sender@example.com"), provider)
+            .with_retry(Some(EmailRetrySettings {
+                max_attempts: 3,
+                base_delay: 10,
+                max_delay: 50,
+                deadline: 300,
+            }));
+
+        assert_ok!(send(&email_client).await);
+        // Cut by the deadline while waiting for the next permit
+        let err = assert_err!(send(&email_client).await);
+
+        assert!(matches!(
+            err,
+            EmailError::Transient {
+                cause: DeliveryError::DeadlineExceeded,
+                ..
+            }
+        ));
+        assert_eq!(
+            email_client.provider_statuses()[0].circuit,
+            CircuitState::Closed
+        );
+    }
+
+    #[tokio::test]
+    async fn retries_fail_over_to_the_fallback_once_the_circuit_opens() {
+        let mock_server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+        let outbox = uuid::Uuid::new_v4().to_string();
+        let email_client = EmailClient::with_provider(
+            email("sender@example.com"),
+            postmark_provider(mock_server.uri()),
+        )
+        .with_fallback(EmailProviderClient::new(
+            "in_memory",
+            InMemoryTransport::named(&outbox),
+        ))
+        .with_retry(Some(EmailRetrySettings {
+            max_attempts: 3,
+            base_delay: 10,
+            max_delay: 50,
+            deadline: 5000,
+        }));
+
+        assert_ok!(send(&email_client).await);
+
+        let sent = InMemoryTransport::named(&outbox).sent_to("ursula@example.com");
+        assert_eq!(sent.len(), 1);
+    }
+}
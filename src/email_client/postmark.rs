@@ -1,13 +1,16 @@
+use std::time::Duration;
+
 use reqwest::{Client, Url};
 use secrecy::{ExposeSecret, Secret};
 use serde::Serialize;
 
-use crate::domain::subscriber_email::SubscriberEmail;
+use super::{check_http_status, Email, EmailError, EmailHeader, EmailTransport};
+use crate::configurations::PostmarkSettings;
 
-pub struct EmailClient {
+// Postmark's `/email` API
+pub struct PostmarkTransport {
     http_client: Client,
     base_url: String,
-    sender: SubscriberEmail,
 
     // We don't want to get this into log by accident
     email_service_auth_token: Secret<String>,
@@ -21,43 +24,38 @@ struct SendEmailPayload<'a> {
     subject: &'a str,
     html_body: &'a str,
     text_body: &'a str,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    headers: &'a [EmailHeader],
 }
 
-impl EmailClient {
-    pub fn new(
-        base_url: String,
-        sender: SubscriberEmail,
-        auth_token: Secret<String>,
-        timeout: std::time::Duration,
-    ) -> Self {
+impl PostmarkTransport {
+    pub fn new(settings: PostmarkSettings, timeout: Duration) -> Self {
         let http_client = Client::builder().timeout(timeout).build().unwrap();
         Self {
             http_client,
-            base_url,
-            sender,
-            email_service_auth_token: auth_token,
+            base_url: settings.base_url,
+            email_service_auth_token: settings.auth_token,
         }
     }
+}
 
-    pub async fn send_email(
-        &self,
-        recipient: SubscriberEmail,
-        subject: &str,
-        html_content: &str,
-        text_content: &str,
-    ) -> Result<(), reqwest::Error> {
+#[async_trait::async_trait]
+impl EmailTransport for PostmarkTransport {
+    async fn send(&self, email: &Email) -> Result<(), EmailError> {
         let base_url = Url::parse(&self.base_url).expect("Invalid email client's base url");
         let email_api = base_url.join("/email").expect("Invalid email request API");
 
         let payload = SendEmailPayload {
-            from: self.sender.as_ref(),
-            to: recipient.as_ref(),
-            subject,
-            html_body: html_content,
-            text_body: text_content,
+            from: email.sender.as_ref(),
+            to: email.recipient.as_ref(),
+            subject: &email.subject,
+            html_body: &email.html_body,
+            text_body: &email.text_body,
+            headers: &email.headers,
         };
 
-        self.http_client
+        let response = self
+            .http_client
             .post(email_api)
             .header(
                 "X-Some-Server-Token",
@@ -65,10 +63,9 @@ impl EmailClient {
             )
             .json(&payload)
             .send()
-            .await?
-            .error_for_status()?;
+            .await?;
 
-        Ok(())
+        check_http_status(response)
     }
 }
 
@@ -88,8 +85,12 @@ mod tests {
         Mock, MockServer, ResponseTemplate,
     };
 
-    use crate::domain::subscriber_email::SubscriberEmail;
-    use crate::email_client::EmailClient;
+    use super::PostmarkTransport;
+    use crate::{
+        configurations::PostmarkSettings,
+        domain::subscriber_email::SubscriberEmail,
+        email_client::{EmailClient, EmailHeader},
+    };
 
     struct SendEmailPayloadMatcher;
 
@@ -108,6 +109,21 @@ mod tests {
         }
     }
 
+    struct EmailHeaderMatcher(&'static str, &'static str);
+
+    impl wiremock::Match for EmailHeaderMatcher {
+        fn matches(&self, request: &wiremock::Request) -> bool {
+            match serde_json::from_slice::<serde_json::Value>(&request.body) {
+                Ok(body) => body["Headers"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .any(|h| h["Name"] == self.0 && h["Value"] == self.1),
+                Err(_) => false,
+            }
+        }
+    }
+
     fn subject() -> String {
         Sentence(1..2).fake()
     }
@@ -121,11 +137,13 @@ mod tests {
     }
 
     fn email_client(base_url: String) -> EmailClient {
-        EmailClient::new(
+        let settings = PostmarkSettings {
             base_url,
+            auth_token: Secret::new(Word().fake()),
+        };
+        EmailClient::new(
             subscriber_email(),
-            Secret::new(Word().fake()),
-            std::time::Duration::from_secs(1),
+            PostmarkTransport::new(settings, std::time::Duration::from_secs(1)),
         )
     }
 
@@ -152,6 +170,34 @@ mod tests {
         assert_ok!(result);
     }
 
+    #[tokio::test]
+    async fn send_email_with_headers_includes_custom_headers() {
+        // Create a new HTTP server with wiremock
+        let mock_server = MockServer::start().await;
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailPayloadMatcher)
+            .and(EmailHeaderMatcher("X-Custom", "custom-value"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let result = email_client(mock_server.uri())
+            .send_email_with_headers(
+                subscriber_email(),
+                &subject(),
+                &content(),
+                &content(),
+                &[EmailHeader::new("X-Custom", "custom-value")],
+            )
+            .await;
+
+        // Assert
+        assert_ok!(result);
+    }
+
     #[tokio::test]
     async fn send_email_fails_if_server_response_not_ok() {
         // Create a new HTTP server with wiremock
@@ -0,0 +1,169 @@
+use std::time::Duration;
+
+use lettre::{
+    transport::smtp::authentication::Credentials, AsyncSmtpTransport, AsyncTransport,
+    Tokio1Executor,
+};
+use secrecy::ExposeSecret;
+
+use super::{Email, EmailError, EmailTransport};
+use crate::configurations::{SmtpSettings, SmtpTls};
+
+pub struct SmtpTransport {
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpTransport {
+    pub fn new(settings: SmtpSettings, timeout: Duration) -> Result<Self, EmailError> {
+        let builder = match settings.tls {
+            SmtpTls::Implicit => AsyncSmtpTransport::<Tokio1Executor>::relay(&settings.host)?,
+            SmtpTls::Starttls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.host)?
+            }
+            SmtpTls::Disabled => {
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(settings.host.as_str())
+            }
+        };
+        let mut builder = builder.port(settings.port).timeout(Some(timeout));
+        if let Some(credentials) = settings.credentials {
+            builder = builder.credentials(Credentials::new(
+                credentials.username,
+                credentials.password.expose_secret().to_owned(),
+            ));
+        }
+
+        Ok(Self {
+            mailer: builder.build(),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for SmtpTransport {
+    async fn send(&self, email: &Email) -> Result<(), EmailError> {
+        self.mailer.send(email.to_message()?).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+    use secrecy::Secret;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+        task::JoinHandle,
+    };
+
+    use super::SmtpTransport;
+    use crate::{
+        configurations::{SmtpCredentials, SmtpSettings, SmtpTls},
+        domain::subscriber_email::SubscriberEmail,
+        email_client::{EmailClient, EmailHeader},
+    };
+
+    // Accept a single session and return everything the client sent
+    // `rejected_command` gets a permanent 550 instead of the usual reply
+    async fn fake_smtp_server(rejected_command: &'static str) -> (u16, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            let mut transcript = String::new();
+            let mut in_data = false;
+
+            writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+            while let Ok(Some(line)) = lines.next_line().await {
+                transcript.push_str(&line);
+                transcript.push('\n');
+
+                let reply: &[u8] = if in_data {
+                    if line != "." {
+                        continue;
+                    }
+                    in_data = false;
+                    b"250 2.0.0 Queued\r\n"
+                } else if line.starts_with(rejected_command) {
+                    b"550 5.1.1 Rejected\r\n"
+                } else if line.starts_with("EHLO") {
+                    b"250-localhost\r\n250-AUTH PLAIN LOGIN\r\n250 8BITMIME\r\n"
+                } else if line.starts_with("AUTH") {
+                    b"235 2.7.0 Authentication successful\r\n"
+                } else if line.starts_with("DATA") {
+                    in_data = true;
+                    b"354 End data with <CR><LF>.<CR><LF>\r\n"
+                } else if line.starts_with("QUIT") {
+                    writer.write_all(b"221 2.0.0 Bye\r\n").await.unwrap();
+                    break;
+                } else {
+                    b"250 2.0.0 OK\r\n"
+                };
+                writer.write_all(reply).await.unwrap();
+            }
+            transcript
+        });
+
+        (port, handle)
+    }
+
+    fn email_client(port: u16) -> EmailClient {
+        let settings = SmtpSettings {
+            host: "127.0.0.1".into(),
+            port,
+            tls: SmtpTls::Disabled,
+            credentials: Some(SmtpCredentials {
+                username: "user".into(),
+                password: Secret::new("password".into()),
+            }),
+        };
+        let transport = SmtpTransport::new(settings, std::time::Duration::from_secs(1)).unwrap();
+        EmailClient::new(
+            SubscriberEmail::parse("sender@example.com".into()).unwrap(),
+            transport,
+        )
+    }
+
+    fn recipient() -> SubscriberEmail {
+        SubscriberEmail::parse("recipient@example.com".into()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn send_email_authenticates_and_sends_the_message() {
+        let (port, server) = fake_smtp_server("NOTHING").await;
+
+        let result = email_client(port)
+            .send_email_with_headers(
+                recipient(),
+                "Hello",
+                "<p>Hi</p>",
+                "Hi",
+                &[EmailHeader::new("X-Custom", "custom-value")],
+            )
+            .await;
+
+        assert_ok!(result);
+        let transcript = server.await.unwrap();
+        assert!(transcript.contains("AUTH PLAIN"));
+        assert!(transcript.contains("MAIL FROM:<sender@example.com>"));
+        assert!(transcript.contains("RCPT TO:<recipient@example.com>"));
+        assert!(transcript.contains("Subject: Hello"));
+        assert!(transcript.contains("X-Custom: custom-value"));
+        assert!(transcript.contains("<p>Hi</p>"));
+    }
+
+    #[tokio::test]
+    async fn rejected_recipient_is_a_permanent_failure() {
+        let (port, _server) = fake_smtp_server("RCPT TO").await;
+
+        let result = email_client(port)
+            .send_email(recipient(), "Hello", "<p>Hi</p>", "Hi")
+            .await;
+
+        let err = assert_err!(result);
+        assert!(!err.is_transient());
+    }
+}
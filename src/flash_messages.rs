@@ -0,0 +1,188 @@
+use std::{
+    cell::{Cell, RefCell},
+    future::{ready, Ready},
+    rc::Rc,
+};
+
+use actix_web::{
+    body::MessageBody,
+    cookie::{time, Cookie, Key, SameSite},
+    dev::{Payload, ServiceRequest, ServiceResponse},
+    middleware::Next,
+    FromRequest, HttpMessage, HttpRequest,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+
+use crate::utils::{add_signed_cookie, e500, get_signed_cookie, MiddlewareFuture};
+
+const FLASH_COOKIE_NAME: &str = "_flash";
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlashLevel {
+    Info,
+    Error,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct FlashMessage {
+    level: FlashLevel,
+    content: String,
+}
+
+impl FlashMessage {
+    pub fn level(&self) -> FlashLevel {
+        self.level
+    }
+
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+}
+
+// Messages shown once on the page a redirect leads to, e.g. after a form submission
+// Those received with the request are dropped once a page has read them
+#[derive(Clone)]
+pub struct FlashMessages {
+    incoming: Rc<Vec<FlashMessage>>,
+    outgoing: Rc<RefCell<Vec<FlashMessage>>>,
+    is_read: Rc<Cell<bool>>,
+}
+
+impl FlashMessages {
+    pub fn iter(&self) -> impl Iterator<Item = &FlashMessage> {
+        self.is_read.set(true);
+        self.incoming.iter()
+    }
+
+    pub fn info(&self, content: impl Into<String>) {
+        self.send(FlashLevel::Info, content.into());
+    }
+
+    pub fn error(&self, content: impl Into<String>) {
+        self.send(FlashLevel::Error, content.into());
+    }
+
+    fn send(&self, level: FlashLevel, content: String) {
+        self.outgoing
+            .borrow_mut()
+            .push(FlashMessage { level, content });
+    }
+
+    // Same markup as the inline messages of the HTML pages
+    pub fn to_html(&self) -> String {
+        self.iter()
+            .map(|message| {
+                format!(
+                    "<p><i>{}</i></p>",
+                    htmlescape::encode_minimal(message.content())
+                )
+            })
+            .collect()
+    }
+}
+
+impl FromRequest for FlashMessages {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<FlashMessages, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<FlashMessages>()
+                .cloned()
+                .ok_or_else(|| e500("The flash messages middleware is not registered")),
+        )
+    }
+}
+
+// Messages are kept in a signed cookie, so they survive the redirect and even a logout
+pub fn flash_messages<B>(
+    key: Key,
+) -> impl Fn(ServiceRequest, Next<B>) -> MiddlewareFuture<B> + 'static
+where
+    B: MessageBody + 'static,
+{
+    move |req, next| Box::pin(handle_flash_messages(key.clone(), req, next))
+}
+
+async fn handle_flash_messages<B: MessageBody>(
+    key: Key,
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<B>, actix_web::Error> {
+    let incoming = get_signed_cookie(&req, &key, FLASH_COOKIE_NAME)
+        .and_then(|value| decode(&value))
+        .unwrap_or_default();
+    let has_incoming = !incoming.is_empty();
+    let outgoing = Rc::new(RefCell::new(Vec::new()));
+    let is_read = Rc::new(Cell::new(false));
+    req.extensions_mut().insert(FlashMessages {
+        incoming: Rc::new(incoming),
+        outgoing: outgoing.clone(),
+        is_read: is_read.clone(),
+    });
+
+    let mut response = next.call(req).await?;
+    let outgoing = outgoing.take();
+    if !outgoing.is_empty() {
+        let cookie = flash_cookie(encode(&outgoing)?);
+        add_signed_cookie(&mut response, &key, cookie)?;
+    } else if has_incoming && is_read.get() {
+        let mut cookie = flash_cookie(String::new());
+        cookie.make_removal();
+        response.response_mut().add_cookie(&cookie).map_err(e500)?;
+    }
+    Ok(response)
+}
+
+fn flash_cookie(value: String) -> Cookie<'static> {
+    Cookie::build(FLASH_COOKIE_NAME, value)
+        .path("/")
+        .secure(true)
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        // Messages nobody came back to read are not worth keeping
+        .max_age(time::Duration::minutes(5))
+        .finish()
+}
+
+// Cookie values can't hold JSON as is
+fn encode(messages: &[FlashMessage]) -> Result<String, actix_web::Error> {
+    let json = serde_json::to_vec(messages).map_err(e500)?;
+    Ok(URL_SAFE_NO_PAD.encode(json))
+}
+
+fn decode(value: &str) -> Option<Vec<FlashMessage>> {
+    let json = URL_SAFE_NO_PAD.decode(value).ok()?;
+    serde_json::from_slice(&json).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::assert_some;
+
+    use super::{decode, encode, FlashLevel, FlashMessage};
+
+    #[test]
+    fn messages_roundtrip_through_the_cookie_encoding() {
+        let messages = vec![FlashMessage {
+            level: FlashLevel::Error,
+            content: "Something went wrong; \"quotes\", commas and spaces".into(),
+        }];
+
+        let encoded = encode(&messages).unwrap();
+        assert!(encoded
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+
+        let decoded = assert_some!(decode(&encoded));
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(decoded[0].level(), FlashLevel::Error);
+        assert_eq!(decoded[0].content(), messages[0].content());
+    }
+
+    #[test]
+    fn garbage_is_ignored() {
+        assert!(decode("not base64 !").is_none());
+    }
+}
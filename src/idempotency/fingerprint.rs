@@ -0,0 +1,62 @@
+use actix_web::HttpRequest;
+use sha2::{Digest, Sha256};
+
+// What a key was first used for, the same key can't be replayed for another request
+#[derive(Debug)]
+pub struct RequestFingerprint {
+    endpoint: String,
+    request_hash: String,
+}
+
+impl RequestFingerprint {
+    // The parsed payload is hashed rather than the raw body,
+    // so a retry sent as a form or with its JSON fields reordered still matches
+    pub fn new(request: &HttpRequest, payload: &impl serde::Serialize) -> Self {
+        let payload =
+            serde_json::to_vec(payload).expect("Request payloads always serialize to JSON");
+        Self {
+            endpoint: format!("{} {}", request.method(), request.path()),
+            request_hash: format!("{:x}", Sha256::digest(payload)),
+        }
+    }
+
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    pub fn request_hash(&self) -> &str {
+        &self.request_hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::RequestFingerprint;
+
+    #[test]
+    fn same_payload_on_the_same_endpoint_has_the_same_fingerprint() {
+        let request = TestRequest::post()
+            .uri("/api/newsletters")
+            .to_http_request();
+
+        let first = RequestFingerprint::new(&request, &serde_json::json!({ "title": "a" }));
+        let second = RequestFingerprint::new(&request, &serde_json::json!({ "title": "a" }));
+
+        assert_eq!(first.endpoint(), "POST /api/newsletters");
+        assert_eq!(first.request_hash(), second.request_hash());
+    }
+
+    #[test]
+    fn another_payload_has_another_hash() {
+        let request = TestRequest::post()
+            .uri("/api/newsletters")
+            .to_http_request();
+
+        let first = RequestFingerprint::new(&request, &serde_json::json!({ "title": "a" }));
+        let second = RequestFingerprint::new(&request, &serde_json::json!({ "title": "b" }));
+
+        assert_ne!(first.request_hash(), second.request_hash());
+    }
+}
@@ -0,0 +1,66 @@
+use actix_web::HttpRequest;
+
+const MAX_KEY_LENGTH: usize = 50;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+#[derive(Debug)]
+pub struct IdempotencyKey(String);
+
+impl TryFrom<String> for IdempotencyKey {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        if value.is_empty() {
+            return Err("The idempotency key cannot be empty.".into());
+        }
+        if value.len() > MAX_KEY_LENGTH {
+            return Err(format!(
+                "The idempotency key must be shorter than {} characters.",
+                MAX_KEY_LENGTH
+            ));
+        }
+        Ok(Self(value))
+    }
+}
+
+impl AsRef<str> for IdempotencyKey {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+// The header is optional, clients that don't retry don't need to send it
+pub fn get_idempotency_key(request: &HttpRequest) -> Result<Option<IdempotencyKey>, String> {
+    match request.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        None => Ok(None),
+        Some(value) => {
+            let value = value
+                .to_str()
+                .map_err(|_| "The idempotency key must be a valid string.".to_string())?;
+            IdempotencyKey::try_from(value.to_string()).map(Some)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use crate::idempotency::IdempotencyKey;
+
+    #[test]
+    fn empty_key_is_rejected() {
+        assert_err!(IdempotencyKey::try_from("".to_string()));
+    }
+
+    #[test]
+    fn a_50_characters_key_is_valid() {
+        assert_ok!(IdempotencyKey::try_from("a".repeat(50)));
+    }
+
+    #[test]
+    fn a_51_characters_key_is_rejected() {
+        assert_err!(IdempotencyKey::try_from("a".repeat(51)));
+    }
+}
@@ -0,0 +1,7 @@
+mod fingerprint;
+mod key;
+mod persistence;
+
+pub use fingerprint::*;
+pub use key::*;
+pub use persistence::*;
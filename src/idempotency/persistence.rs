@@ -0,0 +1,204 @@
+use std::ops::DerefMut;
+
+use actix_web::{body::to_bytes, http::StatusCode, HttpResponse};
+use chrono::Duration;
+use sqlx::{types::chrono::Utc, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use super::{IdempotencyKey, RequestFingerprint};
+
+// Callers without an account share this namespace, the fingerprint keeps a key from replaying
+// the response of another request
+pub const ANONYMOUS_USER_ID: Uuid = Uuid::nil();
+
+#[derive(Debug, sqlx::Type)]
+#[sqlx(type_name = "header_pair")]
+struct HeaderPairRecord {
+    name: String,
+    value: Vec<u8>,
+}
+
+#[allow(clippy::large_enum_variant)]
+pub enum NextAction {
+    // The transaction holds the idempotency row lock, it must be passed to `save_response`
+    StartProcessing(Transaction<'static, Postgres>),
+    ReturnSavedResponse(HttpResponse),
+    // The key was first used for another endpoint or another payload
+    RejectKeyReuse,
+}
+
+#[tracing::instrument(
+    name = "Try processing idempotent request",
+    skip(db_pool, idempotency_key, fingerprint, ttl)
+)]
+pub async fn try_processing(
+    db_pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+    fingerprint: &RequestFingerprint,
+    ttl: Duration,
+) -> Result<NextAction, anyhow::Error> {
+    let mut tx = db_pool.begin().await?;
+    // An expired key is free to be used again, even before the cleanup gets to it
+    sqlx::query!(
+        r#"
+        DELETE FROM idempotency
+        WHERE user_id = $1 AND idempotency_key = $2 AND created_at <= $3
+        "#,
+        user_id,
+        idempotency_key.as_ref(),
+        Utc::now() - ttl,
+    )
+    .execute(tx.deref_mut())
+    .await?;
+    // A concurrent request with the same key blocks here until the first one commits or rolls back
+    let n_inserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO idempotency (user_id, idempotency_key, endpoint, request_hash, created_at)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT DO NOTHING
+        "#,
+        user_id,
+        idempotency_key.as_ref(),
+        fingerprint.endpoint(),
+        fingerprint.request_hash(),
+        Utc::now(),
+    )
+    .execute(tx.deref_mut())
+    .await?
+    .rows_affected();
+
+    if n_inserted_rows > 0 {
+        return Ok(NextAction::StartProcessing(tx));
+    }
+
+    let saved_response = get_saved_response(db_pool, idempotency_key, user_id, fingerprint)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("We expected a saved response, we didn't find it"))?;
+    Ok(saved_response)
+}
+
+// `RejectKeyReuse` when the saved response was for another request
+#[tracing::instrument(
+    name = "Get saved response",
+    skip(db_pool, idempotency_key, fingerprint)
+)]
+pub async fn get_saved_response(
+    db_pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+    fingerprint: &RequestFingerprint,
+) -> Result<Option<NextAction>, anyhow::Error> {
+    let saved_response = sqlx::query!(
+        r#"
+        SELECT
+            endpoint,
+            request_hash,
+            response_status_code AS "response_status_code!",
+            response_headers AS "response_headers!: Vec<HeaderPairRecord>",
+            response_body AS "response_body!"
+        FROM idempotency
+        WHERE user_id = $1 AND idempotency_key = $2
+        "#,
+        user_id,
+        idempotency_key.as_ref(),
+    )
+    .fetch_optional(db_pool)
+    .await?;
+
+    match saved_response {
+        Some(r)
+            if r.endpoint != fingerprint.endpoint()
+                || r.request_hash != fingerprint.request_hash() =>
+        {
+            Ok(Some(NextAction::RejectKeyReuse))
+        }
+        Some(r) => {
+            let status_code = StatusCode::from_u16(r.response_status_code.try_into()?)?;
+            let mut response = HttpResponse::build(status_code);
+            for HeaderPairRecord { name, value } in r.response_headers {
+                response.append_header((name, value));
+            }
+            Ok(Some(NextAction::ReturnSavedResponse(
+                response.body(r.response_body),
+            )))
+        }
+        None => Ok(None),
+    }
+}
+
+// Store the response and commit the transaction, the response is handed back to be returned
+#[tracing::instrument(name = "Save response", skip(tx, idempotency_key, http_response))]
+pub async fn save_response(
+    mut tx: Transaction<'static, Postgres>,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+    http_response: HttpResponse,
+) -> Result<HttpResponse, anyhow::Error> {
+    let (response_head, body) = http_response.into_parts();
+    // The body has to be buffered in memory to be stored, our responses are small
+    let body = to_bytes(body)
+        .await
+        .map_err(|err| anyhow::anyhow!("{}", err))?;
+    let status_code = response_head.status().as_u16() as i16;
+    let headers = response_head
+        .headers()
+        .iter()
+        .map(|(name, value)| HeaderPairRecord {
+            name: name.as_str().to_owned(),
+            value: value.as_bytes().to_owned(),
+        })
+        .collect::<Vec<_>>();
+
+    sqlx::query_unchecked!(
+        r#"
+        UPDATE idempotency
+        SET response_status_code = $3, response_headers = $4, response_body = $5
+        WHERE user_id = $1 AND idempotency_key = $2
+        "#,
+        user_id,
+        idempotency_key.as_ref(),
+        status_code,
+        headers,
+        body.as_ref(),
+    )
+    .execute(tx.deref_mut())
+    .await?;
+    tx.commit().await?;
+
+    // Rebuild the response, the body was consumed above
+    let http_response = response_head.set_body(body).map_into_boxed_body();
+    Ok(http_response)
+}
+
+// Saved responses are only replayed within the ttl, this only keeps the table small
+#[tracing::instrument(name = "Delete expired idempotency keys", skip(db_pool))]
+pub async fn delete_expired_idempotency_keys(
+    db_pool: &PgPool,
+    ttl: Duration,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"DELETE FROM idempotency WHERE created_at <= $1"#,
+        Utc::now() - ttl
+    )
+    .execute(db_pool)
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn run_idempotency_cleanup_until_stopped(
+    db_pool: PgPool,
+    ttl: Duration,
+    cleanup_interval: std::time::Duration,
+) -> Result<(), std::io::Error> {
+    loop {
+        tokio::time::sleep(cleanup_interval).await;
+        // Errors are already logged, the next run will try again
+        let _ = delete_expired_idempotency_keys(&db_pool, ttl).await;
+    }
+}
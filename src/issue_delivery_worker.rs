@@ -0,0 +1,288 @@
+use std::{ops::DerefMut, time::Duration};
+
+use sqlx::{types::chrono::Utc, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    configurations::{DeliveryWorkerSettings, Settings},
+    domain::{
+        random_token::UnsubscribeToken, subscriber_email::SubscriberEmail,
+        subscription_status::SubscriptionStatus,
+    },
+    email_client::EmailClient,
+    routes::list_unsubscribe_headers,
+    startup::{build_connection_pool, build_email_client},
+};
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+type PgTransaction = Transaction<'static, Postgres>;
+
+struct Task {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    n_retries: i16,
+}
+
+struct NewsletterIssue {
+    title: String,
+    text_content: String,
+    html_content: String,
+}
+
+pub struct IssueDeliveryWorker {
+    db_pool: PgPool,
+    email_client: EmailClient,
+    base_url: String,
+    settings: DeliveryWorkerSettings,
+}
+
+impl IssueDeliveryWorker {
+    pub fn build(config: &Settings) -> Self {
+        Self::new(
+            build_connection_pool(config),
+            build_email_client(config),
+            config,
+        )
+    }
+
+    pub fn new(db_pool: PgPool, email_client: EmailClient, config: &Settings) -> Self {
+        Self {
+            db_pool,
+            // A single attempt per task, the queue schedules the retries instead of
+            // backing off here while holding the task's row lock
+            email_client: email_client.with_retry(None),
+            base_url: config.application.base_url.to_owned(),
+            settings: config.delivery_worker.to_owned(),
+        }
+    }
+
+    // Consume self
+    pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
+        loop {
+            match self.try_execute_task().await {
+                Ok(ExecutionOutcome::EmptyQueue) => {
+                    tokio::time::sleep(Duration::from_millis(self.settings.poll_interval)).await;
+                }
+                // Most likely the database is unavailable, give it some time
+                Err(_) => {
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+                Ok(ExecutionOutcome::TaskCompleted) => {}
+            }
+        }
+    }
+
+    #[tracing::instrument(
+        name = "Executing an issue delivery task",
+        skip_all,
+        fields(newsletter_issue_id = tracing::field::Empty, subscriber_email = tracing::field::Empty),
+        err
+    )]
+    pub async fn try_execute_task(&self) -> Result<ExecutionOutcome, sqlx::Error> {
+        let (mut tx, task) = match dequeue_task(&self.db_pool).await? {
+            Some(task) => task,
+            None => return Ok(ExecutionOutcome::EmptyQueue),
+        };
+        tracing::Span::current()
+            .record(
+                "newsletter_issue_id",
+                tracing::field::display(task.newsletter_issue_id),
+            )
+            .record(
+                "subscriber_email",
+                tracing::field::display(&task.subscriber_email),
+            );
+
+        let unsubscribe_token = get_confirmed_unsubscribe_token(&mut tx, &task).await?;
+        let recipient = SubscriberEmail::parse(task.subscriber_email.clone());
+
+        match (recipient, unsubscribe_token) {
+            (Ok(recipient), Some(unsubscribe_token)) => {
+                let issue = get_issue(&mut tx, task.newsletter_issue_id).await?;
+                let headers = list_unsubscribe_headers(&self.base_url, &unsubscribe_token);
+                let result = self
+                    .email_client
+                    .send_email_with_headers(
+                        recipient,
+                        &issue.title,
+                        &issue.html_content,
+                        &issue.text_content,
+                        &headers,
+                    )
+                    .await;
+
+                if let Err(err) = result {
+                    if err.is_transient() && task.n_retries < self.settings.max_retries {
+                        tracing::warn!(
+                            error.cause_chain = ?err,
+                            "Failed to deliver issue to a confirmed subscriber, retrying later"
+                        );
+                        let delay = self.retry_delay(task.n_retries);
+                        reschedule_task(&mut tx, &task, delay).await?;
+                        tx.commit().await?;
+                        return Ok(ExecutionOutcome::TaskCompleted);
+                    }
+
+                    tracing::error!(
+                        error.cause_chain = ?err,
+                        "Failed to deliver issue to a confirmed subscriber, giving up"
+                    );
+                    record_failure(&mut tx, &task, &err.to_string()).await?;
+                }
+            }
+            // Unsubscribed since the issue was published, nothing to deliver
+            (_, None) => {}
+            // Stored data may have been written before our validation rules changed
+            (Err(err), _) => {
+                tracing::warn!(
+                    error = %err,
+                    "Skipping a confirmed subscriber, their stored contact details are invalid"
+                );
+            }
+        }
+
+        delete_task(&mut tx, &task).await?;
+        tx.commit().await?;
+        Ok(ExecutionOutcome::TaskCompleted)
+    }
+
+    // Exponential backoff, capped to the configured maximum
+    fn retry_delay(&self, n_retries: i16) -> chrono::Duration {
+        let factor = 2u64.saturating_pow(n_retries.max(0) as u32);
+        let delay = self.settings.retry_base_delay.saturating_mul(factor);
+        chrono::Duration::milliseconds(delay.min(self.settings.retry_max_delay) as i64)
+    }
+}
+
+pub async fn run_worker_until_stopped(config: Settings) -> Result<(), std::io::Error> {
+    IssueDeliveryWorker::build(&config)
+        .run_until_stopped()
+        .await
+}
+
+#[tracing::instrument(skip_all)]
+async fn dequeue_task(db_pool: &PgPool) -> Result<Option<(PgTransaction, Task)>, sqlx::Error> {
+    let mut tx = db_pool.begin().await?;
+    // Rows locked by other workers are skipped, so workers never deliver the same task twice
+    let task = sqlx::query_as!(
+        Task,
+        r#"
+        SELECT newsletter_issue_id, subscriber_email, n_retries
+        FROM issue_delivery_queue
+        WHERE execute_after <= NOW()
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(tx.deref_mut())
+    .await?;
+
+    Ok(task.map(|task| (tx, task)))
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_confirmed_unsubscribe_token(
+    tx: &mut PgTransaction,
+    task: &Task,
+) -> Result<Option<UnsubscribeToken>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"SELECT unsubscribe_token FROM subscriptions WHERE email = $1 AND status = $2"#,
+        task.subscriber_email,
+        SubscriptionStatus::Confirmed.as_str(),
+    )
+    .fetch_optional(tx.deref_mut())
+    .await?;
+
+    result
+        .map(|r| {
+            UnsubscribeToken::parse(r.unsubscribe_token)
+                .map_err(|err| sqlx::Error::Decode(err.into()))
+        })
+        .transpose()
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_issue(
+    tx: &mut PgTransaction,
+    newsletter_issue_id: Uuid,
+) -> Result<NewsletterIssue, sqlx::Error> {
+    sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT title, text_content, html_content
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_one(tx.deref_mut())
+    .await
+}
+
+#[tracing::instrument(skip_all)]
+async fn reschedule_task(
+    tx: &mut PgTransaction,
+    task: &Task,
+    delay: chrono::Duration,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET n_retries = n_retries + 1, execute_after = $1
+        WHERE newsletter_issue_id = $2 AND subscriber_email = $3
+        "#,
+        Utc::now() + delay,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+    )
+    .execute(tx.deref_mut())
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn record_failure(
+    tx: &mut PgTransaction,
+    task: &Task,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_failures (
+            newsletter_issue_id, subscriber_email, n_retries, error, failed_at
+        )
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        task.n_retries,
+        error,
+        Utc::now(),
+    )
+    .execute(tx.deref_mut())
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_task(tx: &mut PgTransaction, task: &Task) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE newsletter_issue_id = $1 AND subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+    )
+    .execute(tx.deref_mut())
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use actix_web::{http::StatusCode, HttpResponse};
+
+use crate::domain::validation_error::ValidationError;
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+// Problem types, documented for API clients
+pub const VALIDATION_PROBLEM: &str = "/problems/validation-error";
+pub const INVALID_IDEMPOTENCY_KEY_PROBLEM: &str = "/problems/invalid-idempotency-key";
+pub const IDEMPOTENCY_KEY_REUSED_PROBLEM: &str = "/problems/idempotency-key-reused";
+// No further semantics than the status code (RFC 7807, section 4.2)
+pub const BLANK_PROBLEM: &str = "about:blank";
+
+#[derive(serde::Serialize, Debug)]
+pub struct InvalidField {
+    field: &'static str,
+    message: String,
+}
+
+// RFC 7807 body, shared by every JSON error response
+#[derive(serde::Serialize, Debug)]
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    problem_type: &'static str,
+    title: &'static str,
+    #[serde(serialize_with = "serialize_status")]
+    status: StatusCode,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<InvalidField>,
+}
+
+impl ProblemDetails {
+    pub fn new(problem_type: &'static str, status: StatusCode, title: &'static str) -> Self {
+        Self {
+            problem_type,
+            title,
+            status,
+            detail: None,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn validation(err: &ValidationError) -> Self {
+        let mut problem = Self::new(
+            VALIDATION_PROBLEM,
+            StatusCode::BAD_REQUEST,
+            "Your request contains invalid fields.",
+        )
+        .with_detail(err.to_string());
+        problem.errors = err
+            .field_errors()
+            .iter()
+            .map(|field_error| InvalidField {
+                field: field_error.field,
+                message: field_error.message.clone(),
+            })
+            .collect();
+        problem
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    pub fn to_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status)
+            .content_type(PROBLEM_JSON)
+            .json(self)
+    }
+}
+
+fn serialize_status<S: serde::Serializer>(
+    status: &StatusCode,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u16(status.as_u16())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::validation_error::{FieldError, ValidationError};
+
+    use super::ProblemDetails;
+
+    #[test]
+    fn validation_problems_list_every_field() {
+        let err = ValidationError::new(vec![
+            FieldError::new("name", "contains forbidden character '<'"),
+            FieldError::new("email", "is not a valid email address"),
+        ]);
+
+        let body = serde_json::to_value(ProblemDetails::validation(&err)).unwrap();
+
+        assert_eq!(body["type"], "/problems/validation-error");
+        assert_eq!(body["status"], 400);
+        assert_eq!(body["errors"][0]["field"], "name");
+        assert_eq!(
+            body["errors"][0]["message"],
+            "contains forbidden character '<'"
+        );
+        assert_eq!(body["errors"][1]["field"], "email");
+        assert_eq!(
+            body["detail"],
+            "name: contains forbidden character '<'; email: is not a valid email address"
+        );
+    }
+}
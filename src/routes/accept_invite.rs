@@ -0,0 +1,266 @@
+use std::ops::DerefMut;
+
+use actix_web::{http::header::ContentType, web, HttpResponse, Responder};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{types::chrono::Utc, PgPool, Postgres, Transaction};
+
+use crate::{
+    authentication::create_user,
+    csrf::CsrfToken,
+    domain::{new_password::NewPassword, random_token::InviteToken, user_role::UserRole},
+    flash_messages::FlashMessages,
+    utils::see_other,
+};
+
+const MAX_USERNAME_LENGTH: usize = 64;
+
+const INVALID_INVITE_PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Invite</title>
+</head>
+<body>
+    <p>This invite link is invalid or has expired, ask an owner to send you a new one.</p>
+</body>
+</html>"#;
+
+#[derive(serde::Deserialize)]
+pub struct AcceptInviteParameters {
+    token: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct AcceptInviteFormData {
+    token: String,
+    username: String,
+    password: Secret<String>,
+    password_check: Secret<String>,
+}
+
+struct Invite {
+    email: String,
+    role: UserRole,
+}
+
+#[tracing::instrument(
+    name = "Show accept invite page",
+    skip(parameters, csrf_token, db_pool)
+)]
+pub async fn accept_invite_form(
+    parameters: web::Query<AcceptInviteParameters>,
+    csrf_token: CsrfToken,
+    db_pool: web::Data<PgPool>,
+) -> impl Responder {
+    let invite_token = match InviteToken::parse(parameters.0.token) {
+        Ok(token) => token,
+        Err(_) => return HttpResponse::BadRequest().finish(),
+    };
+
+    let mut tx = match db_pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    match get_valid_invite(&mut tx, &invite_token).await {
+        Ok(Some(_)) => HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .body(accept_invite_page(&invite_token, "", &csrf_token)),
+        Ok(None) => HttpResponse::Unauthorized()
+            .content_type(ContentType::html())
+            .body(INVALID_INVITE_PAGE),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(
+    name = "Accept an invite",
+    skip(form, csrf_token, flash_messages, db_pool),
+    fields(username = %form.username)
+)]
+pub async fn accept_invite(
+    form: web::Form<AcceptInviteFormData>,
+    csrf_token: CsrfToken,
+    flash_messages: FlashMessages,
+    db_pool: web::Data<PgPool>,
+) -> impl Responder {
+    let form = form.0;
+    let invite_token = match InviteToken::parse(form.token) {
+        Ok(token) => token,
+        Err(_) => return HttpResponse::BadRequest().finish(),
+    };
+
+    let username = form.username.trim();
+    if username.is_empty() || username.chars().count() > MAX_USERNAME_LENGTH {
+        return HttpResponse::BadRequest()
+            .content_type(ContentType::html())
+            .body(accept_invite_page(
+                &invite_token,
+                &format!(
+                    "<p><i>The username must be between 1 and {} characters long.</i></p>",
+                    MAX_USERNAME_LENGTH
+                ),
+                &csrf_token,
+            ));
+    }
+
+    if form.password.expose_secret() != form.password_check.expose_secret() {
+        return HttpResponse::BadRequest()
+            .content_type(ContentType::html())
+            .body(accept_invite_page(
+                &invite_token,
+                "<p><i>You entered two different passwords - the field values must match.</i></p>",
+                &csrf_token,
+            ));
+    }
+
+    let password = match NewPassword::parse(form.password) {
+        Ok(password) => password,
+        Err(err) => {
+            return HttpResponse::BadRequest()
+                .content_type(ContentType::html())
+                .body(accept_invite_page(
+                    &invite_token,
+                    &format!("<p><i>{}</i></p>", err),
+                    &csrf_token,
+                ))
+        }
+    };
+
+    let mut tx = match db_pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let invite = match get_valid_invite(&mut tx, &invite_token).await {
+        Ok(Some(invite)) => invite,
+        Ok(None) => {
+            return HttpResponse::Unauthorized()
+                .content_type(ContentType::html())
+                .body(INVALID_INVITE_PAGE)
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    match create_user(
+        &mut tx,
+        username,
+        &invite.email,
+        invite.role,
+        password.as_ref().clone(),
+    )
+    .await
+    {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return HttpResponse::BadRequest()
+                .content_type(ContentType::html())
+                .body(accept_invite_page(
+                    &invite_token,
+                    "<p><i>This username is already taken.</i></p>",
+                    &csrf_token,
+                ))
+        }
+        Err(err) => {
+            tracing::error!("Failed to create user: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    // Invites are single-use
+    if delete_invite(&mut tx, &invite_token).await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    if tx.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    flash_messages.info("Your account has been created, you can now log in.");
+    see_other("/login")
+}
+
+// Locks the invite, so it can't be accepted twice concurrently
+#[tracing::instrument(name = "Get valid invite", skip(tx, invite_token))]
+async fn get_valid_invite(
+    tx: &mut Transaction<'_, Postgres>,
+    invite_token: &InviteToken,
+) -> Result<Option<Invite>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        SELECT email, role FROM user_invites
+        WHERE token_hash = $1 AND expires_at > $2
+        FOR UPDATE
+        "#,
+        invite_token.hash(),
+        Utc::now(),
+    )
+    .fetch_optional(tx.deref_mut())
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    result
+        .map(|r| {
+            let role = UserRole::try_from(r.role).map_err(|err| sqlx::Error::Decode(err.into()))?;
+            Ok(Invite {
+                email: r.email,
+                role,
+            })
+        })
+        .transpose()
+}
+
+#[tracing::instrument(name = "Delete invite", skip(tx, invite_token))]
+async fn delete_invite(
+    tx: &mut Transaction<'_, Postgres>,
+    invite_token: &InviteToken,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM user_invites WHERE token_hash = $1"#,
+        invite_token.hash(),
+    )
+    .execute(tx.deref_mut())
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(())
+}
+
+fn accept_invite_page(invite_token: &InviteToken, message: &str, csrf_token: &CsrfToken) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Create your account</title>
+</head>
+<body>
+    {message}
+    <form action="/invite" method="post">
+        {csrf_field}
+        <input type="hidden" name="token" value="{token}">
+        <label>Username
+            <input type="text" placeholder="Choose a username" name="username">
+        </label>
+        <br>
+        <label>Password
+            <input type="password" placeholder="Choose a password" name="password">
+        </label>
+        <br>
+        <label>Confirm password
+            <input type="password" placeholder="Type the password again" name="password_check">
+        </label>
+        <br>
+        <button type="submit">Create account</button>
+    </form>
+</body>
+</html>"#,
+        token = invite_token.as_ref(),
+        csrf_field = csrf_token.form_field(),
+    )
+}
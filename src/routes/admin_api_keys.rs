@@ -0,0 +1,227 @@
+use actix_web::{http::header::ContentType, web, HttpResponse, Responder};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authentication::{get_api_keys, insert_api_key, revoke_api_key, ApiKeySummary, UserId},
+    csrf::CsrfToken,
+    domain::{api_key::ApiKey, api_key_scope::ApiKeyScope, user_role::UserRole},
+    flash_messages::FlashMessages,
+    utils::see_other,
+};
+
+const MAX_NAME_LENGTH: usize = 100;
+
+#[tracing::instrument(
+    name = "Show API keys page",
+    skip(user_id, role, csrf_token, flash_messages, db_pool)
+)]
+pub async fn api_keys_form(
+    user_id: web::ReqData<UserId>,
+    role: web::ReqData<UserRole>,
+    csrf_token: CsrfToken,
+    flash_messages: FlashMessages,
+    db_pool: web::Data<PgPool>,
+) -> impl Responder {
+    api_keys_page_response(
+        HttpResponse::Ok(),
+        &db_pool,
+        *user_id.into_inner(),
+        role.into_inner(),
+        &csrf_token,
+        &flash_messages.to_html(),
+    )
+    .await
+}
+
+// Scopes are checkboxes sharing the same name, so the form is read as a list of pairs
+#[tracing::instrument(
+    name = "Create API key",
+    skip(form, user_id, role, csrf_token, db_pool)
+)]
+pub async fn create_api_key(
+    form: web::Form<Vec<(String, String)>>,
+    user_id: web::ReqData<UserId>,
+    role: web::ReqData<UserRole>,
+    csrf_token: CsrfToken,
+    db_pool: web::Data<PgPool>,
+) -> impl Responder {
+    let user_id = *user_id.into_inner();
+    let role = role.into_inner();
+
+    let (name, scopes) = match parse_new_api_key_form(form.into_inner(), role) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            return api_keys_page_response(
+                HttpResponse::BadRequest(),
+                &db_pool,
+                user_id,
+                role,
+                &csrf_token,
+                &format!("<p><i>{}</i></p>", htmlescape::encode_minimal(&err)),
+            )
+            .await
+        }
+    };
+
+    let api_key = ApiKey::generate();
+    if insert_api_key(&db_pool, user_id, &name, &api_key, &scopes)
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    // Only the hash is stored, this is the only time the key is shown
+    api_keys_page_response(
+        HttpResponse::Ok(),
+        &db_pool,
+        user_id,
+        role,
+        &csrf_token,
+        &format!(
+            "<p>Your new API key, copy it now as it won't be shown again:</p>\
+            <p><code id=\"api-key\">{}</code></p>",
+            api_key.as_ref()
+        ),
+    )
+    .await
+}
+
+#[tracing::instrument(
+    name = "Revoke API key for user",
+    skip(user_id, flash_messages, db_pool)
+)]
+pub async fn revoke_api_key_for_user(
+    path: web::Path<Uuid>,
+    user_id: web::ReqData<UserId>,
+    flash_messages: FlashMessages,
+    db_pool: web::Data<PgPool>,
+) -> impl Responder {
+    match revoke_api_key(&db_pool, *user_id.into_inner(), path.into_inner()).await {
+        Ok(true) => {
+            flash_messages.info("The API key has been revoked.");
+            see_other("/admin/api-keys")
+        }
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+// A key can't do more than its creator's role allows
+fn parse_new_api_key_form(
+    pairs: Vec<(String, String)>,
+    role: UserRole,
+) -> Result<(String, Vec<ApiKeyScope>), String> {
+    let mut name = String::new();
+    let mut scopes = Vec::new();
+    for (key, value) in pairs {
+        match key.as_str() {
+            "name" => name = value.trim().to_string(),
+            "scopes" => {
+                let scope = ApiKeyScope::try_from(value)?;
+                if !role.has_permission(scope.required_permission()) {
+                    return Err(format!(
+                        "Your role does not allow the {} scope.",
+                        scope.as_str()
+                    ));
+                }
+                if !scopes.contains(&scope) {
+                    scopes.push(scope);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Err(format!(
+            "The key name must be between 1 and {} characters long.",
+            MAX_NAME_LENGTH
+        ));
+    }
+    if scopes.is_empty() {
+        return Err("Select at least one scope.".into());
+    }
+    Ok((name, scopes))
+}
+
+async fn api_keys_page_response(
+    mut response: actix_web::HttpResponseBuilder,
+    db_pool: &PgPool,
+    user_id: Uuid,
+    role: UserRole,
+    csrf_token: &CsrfToken,
+    message: &str,
+) -> HttpResponse {
+    let api_keys = match get_api_keys(db_pool, user_id).await {
+        Ok(api_keys) => api_keys,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let csrf_field = csrf_token.form_field();
+    let rows: String = api_keys
+        .iter()
+        .map(|api_key| api_key_row(api_key, &csrf_field))
+        .collect();
+    let scope_checkboxes: String = ApiKeyScope::ALL
+        .iter()
+        .filter(|scope| role.has_permission(scope.required_permission()))
+        .map(|scope| {
+            format!(
+                r#"<label><input type="checkbox" name="scopes" value="{scope}"> {scope}</label><br>"#,
+                scope = scope.as_str()
+            )
+        })
+        .collect();
+
+    response.content_type(ContentType::html()).body(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>API keys</title>
+</head>
+<body>
+    {message}
+    <table>
+        <tr><th>Name</th><th>Prefix</th><th>Scopes</th><th>Created</th><th>Last used</th><th>Status</th></tr>
+        {rows}
+    </table>
+    <form action="/admin/api-keys" method="post">
+        {csrf_field}
+        <label>Name
+            <input type="text" placeholder="What the key is used for" name="name">
+        </label>
+        <br>
+        {scope_checkboxes}
+        <button type="submit">Create API key</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#
+    ))
+}
+
+fn api_key_row(api_key: &ApiKeySummary, csrf_field: &str) -> String {
+    let last_used_at = api_key
+        .last_used_at
+        .map(|at| at.to_rfc3339())
+        .unwrap_or_else(|| "Never".into());
+    let status = match api_key.revoked_at {
+        Some(at) => format!("Revoked on {}", at.to_rfc3339()),
+        None => format!(
+            r#"<form action="/admin/api-keys/{}/revoke" method="post">{}<button type="submit">Revoke</button></form>"#,
+            api_key.api_key_id, csrf_field
+        ),
+    };
+
+    format!(
+        "<tr><td>{}</td><td><code>{}</code></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+        htmlescape::encode_minimal(&api_key.name),
+        api_key.prefix,
+        api_key.scopes.join(", "),
+        api_key.created_at.to_rfc3339(),
+        last_used_at,
+        status,
+    )
+}
@@ -0,0 +1,85 @@
+use actix_web::{http::header::ContentType, web, HttpResponse, Responder};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authentication::{get_user_role, UserId},
+    csrf::CsrfToken,
+    domain::user_role::Permission,
+    flash_messages::FlashMessages,
+};
+
+#[tracing::instrument(
+    name = "Show admin dashboard",
+    skip(user_id, csrf_token, flash_messages, db_pool)
+)]
+pub async fn admin_dashboard(
+    user_id: web::ReqData<UserId>,
+    csrf_token: CsrfToken,
+    flash_messages: FlashMessages,
+    db_pool: web::Data<PgPool>,
+) -> impl Responder {
+    let user_id = *user_id.into_inner();
+    let (username, role) = match (
+        get_username(user_id, &db_pool).await,
+        get_user_role(&db_pool, user_id).await,
+    ) {
+        (Ok(username), Ok(role)) => (username, role),
+        _ => return HttpResponse::InternalServerError().finish(),
+    };
+
+    // Only link the pages the role gives access to
+    let links: String = [
+        (
+            Permission::ViewSubscribers,
+            "/admin/subscribers",
+            "Subscribers",
+        ),
+        (Permission::ManageApiKeys, "/admin/api-keys", "API keys"),
+        (Permission::ManageUsers, "/admin/users", "Users"),
+    ]
+    .iter()
+    .filter(|(permission, _, _)| role.has_permission(*permission))
+    .map(|(_, href, label)| format!(r#"<p><a href="{}">{}</a></p>"#, href, label))
+    .collect();
+
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Admin dashboard</title>
+</head>
+<body>
+    {messages}
+    <p>Welcome {username}! You are signed in as {role}.</p>
+    {links}
+    <p><a href="/admin/password">Change password</a></p>
+    <p><a href="/admin/2fa">Two-factor authentication</a></p>
+    <form action="/admin/logout" method="post">
+        {csrf_field}
+        <button type="submit">Logout</button>
+    </form>
+</body>
+</html>"#,
+            messages = flash_messages.to_html(),
+            csrf_field = csrf_token.form_field(),
+            username = htmlescape::encode_minimal(&username),
+            role = role.as_str(),
+        ))
+}
+
+#[tracing::instrument(name = "Get username", skip(db_pool))]
+pub async fn get_username(user_id: Uuid, db_pool: &PgPool) -> Result<String, sqlx::Error> {
+    let row = sqlx::query!(r#"SELECT username FROM users WHERE user_id = $1"#, user_id)
+        .fetch_one(db_pool)
+        .await
+        .map_err(|err| {
+            tracing::error!("Failed to execute query: {:?}", err);
+            err
+        })?;
+
+    Ok(row.username)
+}
@@ -0,0 +1,9 @@
+use actix_web::Responder;
+
+use crate::{flash_messages::FlashMessages, session_state::TypedSession, utils::see_other};
+
+pub async fn log_out(session: TypedSession, flash_messages: FlashMessages) -> impl Responder {
+    session.log_out();
+    flash_messages.info("You have successfully logged out.");
+    see_other("/login")
+}
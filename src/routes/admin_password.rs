@@ -0,0 +1,142 @@
+use actix_web::{http::header::ContentType, web, HttpResponse, Responder};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+
+use crate::{
+    authentication::{change_password, validate_credentials, AuthError, Credentials, UserId},
+    csrf::CsrfToken,
+    domain::new_password::NewPassword,
+    routes::get_username,
+    session_state::TypedSession,
+    session_store::delete_user_sessions,
+};
+
+#[derive(serde::Deserialize)]
+pub struct ChangePasswordFormData {
+    current_password: Secret<String>,
+    new_password: Secret<String>,
+    new_password_check: Secret<String>,
+}
+
+pub async fn change_password_form(csrf_token: CsrfToken) -> impl Responder {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(change_password_page("", &csrf_token))
+}
+
+#[tracing::instrument(
+    name = "Change admin password",
+    skip(form, csrf_token, user_id, session, db_pool)
+)]
+pub async fn change_password_for_user(
+    form: web::Form<ChangePasswordFormData>,
+    csrf_token: CsrfToken,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+) -> impl Responder {
+    let user_id = *user_id.into_inner();
+
+    if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
+        return HttpResponse::BadRequest()
+            .content_type(ContentType::html())
+            .body(change_password_page(
+                "<p><i>You entered two different new passwords - the field values must match.</i></p>",
+                &csrf_token,
+            ));
+    }
+
+    let new_password = match NewPassword::parse(form.0.new_password) {
+        Ok(password) => password,
+        Err(err) => {
+            return HttpResponse::BadRequest()
+                .content_type(ContentType::html())
+                .body(change_password_page(
+                    &format!("<p><i>{}</i></p>", err),
+                    &csrf_token,
+                ))
+        }
+    };
+
+    let username = match get_username(user_id, &db_pool).await {
+        Ok(username) => username,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let credentials = Credentials {
+        username,
+        password: form.0.current_password,
+    };
+    match validate_credentials(credentials, &db_pool).await {
+        Ok(_) => {}
+        Err(AuthError::InvalidCredentials(_)) => {
+            return HttpResponse::Unauthorized()
+                .content_type(ContentType::html())
+                .body(change_password_page(
+                    "<p><i>The current password is incorrect.</i></p>",
+                    &csrf_token,
+                ))
+        }
+        Err(AuthError::UnexpectedError(err)) => {
+            tracing::error!("Failed to validate credentials: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    let mut tx = match db_pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if let Err(err) = change_password(&mut tx, user_id, new_password.as_ref().clone()).await {
+        tracing::error!("Failed to change password: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+    // Whoever else knew the old password gets logged out
+    if delete_user_sessions(&mut tx, user_id).await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    if tx.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    session.renew();
+
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(change_password_page(
+            "<p><i>Your password has been changed.</i></p>",
+            &csrf_token,
+        ))
+}
+
+fn change_password_page(message: &str, csrf_token: &CsrfToken) -> String {
+    let csrf_field = csrf_token.form_field();
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Change Password</title>
+</head>
+<body>
+    {message}
+    <form action="/admin/password" method="post">
+        {csrf_field}
+        <label>Current password
+            <input type="password" placeholder="Enter current password" name="current_password">
+        </label>
+        <br>
+        <label>New password
+            <input type="password" placeholder="Enter new password" name="new_password">
+        </label>
+        <br>
+        <label>Confirm new password
+            <input type="password" placeholder="Type the new password again" name="new_password_check">
+        </label>
+        <br>
+        <button type="submit">Change password</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#
+    )
+}
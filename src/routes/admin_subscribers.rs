@@ -0,0 +1,294 @@
+use std::ops::DerefMut;
+
+use actix_web::{http::header::ContentType, web, HttpResponse, Responder};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    authentication::AuthenticatedApiKey,
+    csrf::CsrfToken,
+    domain::{
+        api_key_scope::ApiKeyScope,
+        user_role::{Permission, UserRole},
+    },
+    flash_messages::FlashMessages,
+    routes::delete_subscription_tokens,
+    utils::see_other,
+};
+
+// Page size of the subscribers API, larger requests get the maximum
+const DEFAULT_PAGE_SIZE: i64 = 100;
+const MAX_PAGE_SIZE: i64 = 500;
+
+#[derive(serde::Deserialize)]
+pub struct SubscribersPageParameters {
+    limit: Option<i64>,
+    // `next_cursor` of the previous page
+    cursor: Option<String>,
+}
+
+// Position after the last subscriber of a page, opaque to clients
+struct SubscribersCursor {
+    subscribed_at: DateTime<Utc>,
+    id: Uuid,
+}
+
+impl SubscribersCursor {
+    // Microseconds, the precision Postgres keeps
+    fn encode(&self) -> String {
+        let json = serde_json::json!([self.subscribed_at.timestamp_micros(), self.id]);
+        URL_SAFE_NO_PAD.encode(json.to_string())
+    }
+
+    fn decode(value: &str) -> Option<Self> {
+        let json = URL_SAFE_NO_PAD.decode(value).ok()?;
+        let (subscribed_at, id): (i64, Uuid) = serde_json::from_slice(&json).ok()?;
+        Some(Self {
+            subscribed_at: DateTime::from_timestamp_micros(subscribed_at)?,
+            id,
+        })
+    }
+}
+
+struct SubscriberSummary {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    attributes: serde_json::Value,
+}
+
+impl SubscriberSummary {
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "id": self.id,
+            "email": self.email,
+            "name": self.name,
+            "status": self.status,
+            "subscribed_at": self.subscribed_at.to_rfc3339(),
+            "attributes": self.attributes,
+        })
+    }
+
+    // One `name: value` line per attribute, values as JSON so strings stand out from numbers
+    fn attributes_html(&self) -> String {
+        match self.attributes.as_object() {
+            Some(attributes) => attributes
+                .iter()
+                .map(|(name, value)| htmlescape::encode_minimal(&format!("{}: {}", name, value)))
+                .collect::<Vec<_>>()
+                .join("<br>"),
+            None => String::new(),
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Show subscribers page",
+    skip(role, csrf_token, flash_messages, db_pool)
+)]
+pub async fn subscribers_list(
+    role: web::ReqData<UserRole>,
+    csrf_token: CsrfToken,
+    flash_messages: FlashMessages,
+    db_pool: web::Data<PgPool>,
+) -> impl Responder {
+    let subscribers = match get_subscribers(&db_pool).await {
+        Ok(subscribers) => subscribers,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    // Only show the delete buttons to the roles allowed to use them
+    let can_delete = role.has_permission(Permission::DeleteSubscribers);
+    let csrf_field = csrf_token.form_field();
+    let rows: String = subscribers
+        .iter()
+        .map(|subscriber| {
+            let delete = if can_delete {
+                format!(
+                    r#"<form action="/admin/subscribers/{}/delete" method="post">{}<button type="submit">Delete</button></form>"#,
+                    subscriber.id, csrf_field
+                )
+            } else {
+                String::new()
+            };
+            format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                htmlescape::encode_minimal(&subscriber.email),
+                htmlescape::encode_minimal(&subscriber.name),
+                subscriber.status,
+                subscriber.subscribed_at.to_rfc3339(),
+                subscriber.attributes_html(),
+                delete
+            )
+        })
+        .collect();
+
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscribers</title>
+</head>
+<body>
+    {messages}
+    <table>
+        <tr><th>Email</th><th>Name</th><th>Status</th><th>Subscribed</th><th>Attributes</th><th></th></tr>
+        {rows}
+    </table>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+            messages = flash_messages.to_html(),
+        ))
+}
+
+// Same list as `subscribers_list`, for machine clients such as a CRM
+// Paginated newest first, `next_cursor` is null on the last page
+#[tracing::instrument(
+    name = "List subscribers with an API key",
+    skip(api_key, parameters, db_pool),
+    fields(api_key_id = %api_key.api_key_id)
+)]
+pub async fn list_subscribers_with_api_key(
+    api_key: AuthenticatedApiKey,
+    parameters: web::Query<SubscribersPageParameters>,
+    db_pool: web::Data<PgPool>,
+) -> impl Responder {
+    if !api_key.has_scope(ApiKeyScope::SubscribersRead) {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    let limit = parameters
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let cursor = match parameters.cursor.as_deref().map(SubscribersCursor::decode) {
+        Some(Some(cursor)) => Some(cursor),
+        Some(None) => return HttpResponse::BadRequest().finish(),
+        None => None,
+    };
+
+    // One more than asked, to tell whether there is a next page
+    let mut subscribers = match get_subscribers_page(&db_pool, cursor.as_ref(), limit + 1).await {
+        Ok(subscribers) => subscribers,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let next_cursor = if subscribers.len() as i64 > limit {
+        subscribers.truncate(limit as usize);
+        subscribers.last().map(|last| {
+            SubscribersCursor {
+                subscribed_at: last.subscribed_at,
+                id: last.id,
+            }
+            .encode()
+        })
+    } else {
+        None
+    };
+
+    let subscribers: Vec<_> = subscribers.iter().map(SubscriberSummary::to_json).collect();
+    HttpResponse::Ok().json(serde_json::json!({
+        "subscribers": subscribers,
+        "next_cursor": next_cursor,
+    }))
+}
+
+#[tracing::instrument(name = "Delete a subscriber", skip(flash_messages, db_pool))]
+pub async fn delete_subscriber(
+    path: web::Path<Uuid>,
+    flash_messages: FlashMessages,
+    db_pool: web::Data<PgPool>,
+) -> impl Responder {
+    let subscriber_id = path.into_inner();
+
+    let mut tx = match db_pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if delete_subscription_tokens(&mut tx, subscriber_id)
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+    let deleted = match delete_subscription(&mut tx, subscriber_id).await {
+        Ok(deleted) => deleted,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if tx.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    if deleted {
+        flash_messages.info("The subscriber has been deleted.");
+        see_other("/admin/subscribers")
+    } else {
+        HttpResponse::NotFound().finish()
+    }
+}
+
+#[tracing::instrument(name = "Get subscribers", skip(db_pool))]
+async fn get_subscribers(db_pool: &PgPool) -> Result<Vec<SubscriberSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        SubscriberSummary,
+        r#"
+        SELECT id, email, name, status, subscribed_at, attributes FROM subscriptions
+        ORDER BY subscribed_at DESC
+        "#
+    )
+    .fetch_all(db_pool)
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })
+}
+
+#[tracing::instrument(name = "Get subscribers page", skip(db_pool, cursor))]
+async fn get_subscribers_page(
+    db_pool: &PgPool,
+    cursor: Option<&SubscribersCursor>,
+    limit: i64,
+) -> Result<Vec<SubscriberSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        SubscriberSummary,
+        r#"
+        SELECT id, email, name, status, subscribed_at, attributes FROM subscriptions
+        WHERE $1::TIMESTAMPTZ IS NULL OR (subscribed_at, id) < ($1, $2)
+        ORDER BY subscribed_at DESC, id DESC
+        LIMIT $3
+        "#,
+        cursor.map(|cursor| cursor.subscribed_at),
+        cursor.map(|cursor| cursor.id),
+        limit,
+    )
+    .fetch_all(db_pool)
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })
+}
+
+#[tracing::instrument(name = "Delete subscription", skip(tx))]
+async fn delete_subscription(
+    tx: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(r#"DELETE FROM subscriptions WHERE id = $1"#, subscriber_id)
+        .execute(tx.deref_mut())
+        .await
+        .map_err(|err| {
+            tracing::error!("Failed to execute query: {:?}", err);
+            err
+        })?;
+
+    Ok(result.rows_affected() == 1)
+}
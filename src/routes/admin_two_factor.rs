@@ -0,0 +1,305 @@
+use actix_web::{http::header::ContentType, web, HttpResponse, Responder};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+
+use crate::{
+    authentication::{
+        build_totp, disable_two_factor, enable_two_factor, generate_recovery_codes,
+        generate_totp_secret, get_totp_secret, validate_credentials, verify_totp_code, AuthError,
+        Credentials, UserId,
+    },
+    configurations::TwoFactorSettings,
+    csrf::CsrfToken,
+    routes::get_username,
+    session_state::TypedSession,
+    utils::see_other,
+};
+
+const TWO_FACTOR_DISABLED_PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Two-factor authentication</title>
+</head>
+<body>
+    <p>Two-factor authentication has been disabled.</p>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#;
+
+#[derive(serde::Deserialize)]
+pub struct EnableTwoFactorFormData {
+    code: Secret<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DisableTwoFactorFormData {
+    current_password: Secret<String>,
+}
+
+#[tracing::instrument(
+    name = "Show two-factor authentication page",
+    skip(user_id, csrf_token, session, db_pool, two_factor_settings)
+)]
+pub async fn two_factor_setup_form(
+    user_id: web::ReqData<UserId>,
+    csrf_token: CsrfToken,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+    two_factor_settings: web::Data<TwoFactorSettings>,
+) -> impl Responder {
+    let user_id = *user_id.into_inner();
+
+    match get_totp_secret(&db_pool, user_id).await {
+        Ok(Some(_)) => {
+            return HttpResponse::Ok()
+                .content_type(ContentType::html())
+                .body(two_factor_enabled_page("", &csrf_token))
+        }
+        Ok(None) => {}
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    // Keep the same secret across reloads, so a scanned QR code stays valid
+    let secret = match session.get_totp_setup_secret() {
+        Ok(Some(secret)) => secret,
+        Ok(None) => {
+            let secret = generate_totp_secret();
+            if session.insert_totp_setup_secret(&secret).is_err() {
+                return HttpResponse::InternalServerError().finish();
+            }
+            secret
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    setup_page_response(
+        HttpResponse::Ok(),
+        &db_pool,
+        user_id,
+        &secret,
+        &two_factor_settings,
+        &csrf_token,
+        "",
+    )
+    .await
+}
+
+// Verify-before-enable, so nobody gets locked out by a badly scanned secret
+#[tracing::instrument(
+    name = "Enable two-factor authentication",
+    skip(form, csrf_token, user_id, session, db_pool, two_factor_settings)
+)]
+pub async fn two_factor_setup(
+    form: web::Form<EnableTwoFactorFormData>,
+    csrf_token: CsrfToken,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+    two_factor_settings: web::Data<TwoFactorSettings>,
+) -> impl Responder {
+    let user_id = *user_id.into_inner();
+
+    let secret = match session.get_totp_setup_secret() {
+        Ok(Some(secret)) => secret,
+        Ok(None) => return see_other("/admin/2fa"),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let totp = match build_totp(&secret, "", "") {
+        Ok(totp) => totp,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let step = match verify_totp_code(&totp, form.code.expose_secret().trim()) {
+        Some(step) => step,
+        None => {
+            return setup_page_response(
+                HttpResponse::BadRequest(),
+                &db_pool,
+                user_id,
+                &secret,
+                &two_factor_settings,
+                &csrf_token,
+                "<p><i>Invalid authentication code, please try again.</i></p>",
+            )
+            .await
+        }
+    };
+
+    let recovery_codes = generate_recovery_codes();
+    let mut tx = match db_pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    match enable_two_factor(&mut tx, user_id, &secret, step, &recovery_codes).await {
+        Ok(true) => {}
+        // Another device has to go through `two_factor_disable` first, which asks for the password
+        Ok(false) => {
+            session.remove_totp_setup_secret();
+            return HttpResponse::Conflict()
+                .content_type(ContentType::html())
+                .body(two_factor_enabled_page(
+                    "<p><i>Two-factor authentication is already enabled, \
+                    disable it first to enrol another device.</i></p>",
+                    &csrf_token,
+                ));
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+    if tx.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    session.remove_totp_setup_secret();
+
+    // Recovery codes are only stored hashed, this is the only time they are shown
+    let recovery_codes: String = recovery_codes
+        .iter()
+        .map(|code| format!("<li><code>{}</code></li>", code))
+        .collect();
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(two_factor_enabled_page(
+            &format!(
+                "<p>Two-factor authentication is now enabled. \
+                Store these recovery codes somewhere safe, each of them can be used once \
+                if you lose access to your authenticator app:</p><ul>{}</ul>",
+                recovery_codes
+            ),
+            &csrf_token,
+        ))
+}
+
+#[tracing::instrument(
+    name = "Disable two-factor authentication",
+    skip(form, csrf_token, user_id, db_pool, two_factor_settings)
+)]
+pub async fn two_factor_disable(
+    form: web::Form<DisableTwoFactorFormData>,
+    csrf_token: CsrfToken,
+    user_id: web::ReqData<UserId>,
+    db_pool: web::Data<PgPool>,
+    two_factor_settings: web::Data<TwoFactorSettings>,
+) -> impl Responder {
+    let user_id = *user_id.into_inner();
+
+    if two_factor_settings.enforce {
+        return HttpResponse::Forbidden()
+            .content_type(ContentType::html())
+            .body(two_factor_enabled_page(
+                "<p><i>Two-factor authentication is mandatory and can't be disabled.</i></p>",
+                &csrf_token,
+            ));
+    }
+
+    let username = match get_username(user_id, &db_pool).await {
+        Ok(username) => username,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let credentials = Credentials {
+        username,
+        password: form.0.current_password,
+    };
+    match validate_credentials(credentials, &db_pool).await {
+        Ok(_) => {}
+        Err(AuthError::InvalidCredentials(_)) => {
+            return HttpResponse::Unauthorized()
+                .content_type(ContentType::html())
+                .body(two_factor_enabled_page(
+                    "<p><i>The current password is incorrect.</i></p>",
+                    &csrf_token,
+                ))
+        }
+        Err(AuthError::UnexpectedError(err)) => {
+            tracing::error!("Failed to validate credentials: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    let mut tx = match db_pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if disable_two_factor(&mut tx, user_id).await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    if tx.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(TWO_FACTOR_DISABLED_PAGE)
+}
+
+async fn setup_page_response(
+    mut response: actix_web::HttpResponseBuilder,
+    db_pool: &PgPool,
+    user_id: uuid::Uuid,
+    secret: &str,
+    two_factor_settings: &TwoFactorSettings,
+    csrf_token: &CsrfToken,
+    message: &str,
+) -> HttpResponse {
+    let username = match get_username(user_id, db_pool).await {
+        Ok(username) => username,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let totp = match build_totp(secret, &two_factor_settings.issuer, &username) {
+        Ok(totp) => totp,
+        Err(err) => {
+            tracing::error!("Failed to build TOTP: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    response.content_type(ContentType::html()).body(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Two-factor authentication</title>
+</head>
+<body>
+    {message}
+    <p>Add this account to your authenticator app, by opening or scanning this link:</p>
+    <p><a id="totp-uri" href="{uri}">{uri}</a></p>
+    <p>Or by entering this key manually: <code id="totp-secret">{secret}</code></p>
+    <form action="/admin/2fa" method="post">
+        {csrf_field}
+        <label>Authentication code
+            <input type="text" inputmode="numeric" autocomplete="one-time-code" placeholder="123456" name="code">
+        </label>
+        <button type="submit">Enable</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        uri = htmlescape::encode_minimal(&totp.get_url()),
+        csrf_field = csrf_token.form_field(),
+    ))
+}
+
+fn two_factor_enabled_page(message: &str, csrf_token: &CsrfToken) -> String {
+    let csrf_field = csrf_token.form_field();
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Two-factor authentication</title>
+</head>
+<body>
+    {message}
+    <p>Two-factor authentication is enabled on your account.</p>
+    <form action="/admin/2fa/disable" method="post">
+        {csrf_field}
+        <label>Current password
+            <input type="password" placeholder="Enter current password" name="current_password">
+        </label>
+        <button type="submit">Disable two-factor authentication</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#
+    )
+}
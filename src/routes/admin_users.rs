@@ -0,0 +1,474 @@
+use std::ops::DerefMut;
+
+use actix_web::{http::header::ContentType, web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    authentication::{revoke_api_keys_beyond_role, UserId},
+    configurations::InviteSettings,
+    csrf::CsrfToken,
+    domain::{random_token::InviteToken, subscriber_email::SubscriberEmail, user_role::UserRole},
+    email_client::{EmailClient, EmailError},
+    flash_messages::FlashMessages,
+    startup::ApplicationBaseUrl,
+    utils::see_other,
+};
+
+#[derive(serde::Deserialize)]
+pub struct InviteUserFormData {
+    email: String,
+    role: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ChangeRoleFormData {
+    role: String,
+}
+
+struct UserSummary {
+    user_id: Uuid,
+    username: String,
+    email: Option<String>,
+    role: String,
+}
+
+struct PendingInvite {
+    email: String,
+    role: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(
+    name = "Show users page",
+    skip(user_id, csrf_token, flash_messages, db_pool)
+)]
+pub async fn users_form(
+    user_id: web::ReqData<UserId>,
+    csrf_token: CsrfToken,
+    flash_messages: FlashMessages,
+    db_pool: web::Data<PgPool>,
+) -> impl Responder {
+    users_page_response(
+        HttpResponse::Ok(),
+        &db_pool,
+        *user_id.into_inner(),
+        &csrf_token,
+        &flash_messages.to_html(),
+    )
+    .await
+}
+
+#[tracing::instrument(
+    name = "Invite a new admin user",
+    skip(form, user_id, csrf_token, db_pool, email_client, base_url, invite_settings),
+    fields(invited_email = %form.email, invited_role = %form.role)
+)]
+pub async fn invite_user(
+    form: web::Form<InviteUserFormData>,
+    user_id: web::ReqData<UserId>,
+    csrf_token: CsrfToken,
+    db_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    invite_settings: web::Data<InviteSettings>,
+) -> impl Responder {
+    let user_id = *user_id.into_inner();
+    let form = form.0;
+
+    let (email, role) = match (
+        SubscriberEmail::parse(form.email),
+        UserRole::try_from(form.role),
+    ) {
+        (Ok(email), Ok(role)) => (email, role),
+        (Err(_), _) | (_, Err(_)) => {
+            return users_page_response(
+                HttpResponse::BadRequest(),
+                &db_pool,
+                user_id,
+                &csrf_token,
+                "<p><i>Please enter a valid email address and role.</i></p>",
+            )
+            .await
+        }
+    };
+
+    let mut tx = match db_pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    match email_has_account(&mut tx, &email).await {
+        Ok(false) => {}
+        Ok(true) => {
+            return users_page_response(
+                HttpResponse::BadRequest(),
+                &db_pool,
+                user_id,
+                &csrf_token,
+                "<p><i>This email address already has an account.</i></p>",
+            )
+            .await
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    // Only the latest invite sent to an address should work
+    if delete_invites(&mut tx, &email).await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let invite_token = InviteToken::generate();
+    if store_invite(
+        &mut tx,
+        &invite_token,
+        &email,
+        role,
+        user_id,
+        invite_settings.token_ttl(),
+    )
+    .await
+    .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let message = format!(
+        "<p><i>An invite has been sent to {}.</i></p>",
+        htmlescape::encode_minimal(email.as_ref())
+    );
+    if send_invite_email(&email_client, email, role, &base_url.0, &invite_token)
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    if tx.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    users_page_response(HttpResponse::Ok(), &db_pool, user_id, &csrf_token, &message).await
+}
+
+#[tracing::instrument(
+    name = "Change user role",
+    skip(form, user_id, csrf_token, flash_messages, db_pool)
+)]
+pub async fn change_user_role(
+    path: web::Path<Uuid>,
+    form: web::Form<ChangeRoleFormData>,
+    user_id: web::ReqData<UserId>,
+    csrf_token: CsrfToken,
+    flash_messages: FlashMessages,
+    db_pool: web::Data<PgPool>,
+) -> impl Responder {
+    let user_id = *user_id.into_inner();
+    let target_user_id = path.into_inner();
+
+    // Owners can't demote themselves, so there is always at least one owner left
+    if target_user_id == user_id {
+        return users_page_response(
+            HttpResponse::BadRequest(),
+            &db_pool,
+            user_id,
+            &csrf_token,
+            "<p><i>You can't change your own role.</i></p>",
+        )
+        .await;
+    }
+
+    let role = match UserRole::try_from(form.0.role) {
+        Ok(role) => role,
+        Err(_) => {
+            return users_page_response(
+                HttpResponse::BadRequest(),
+                &db_pool,
+                user_id,
+                &csrf_token,
+                "<p><i>Please select a valid role.</i></p>",
+            )
+            .await
+        }
+    };
+
+    let mut tx = match db_pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    match update_user_role(&mut tx, target_user_id, role).await {
+        Ok(true) => {}
+        Ok(false) => return HttpResponse::NotFound().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+    // Keys are checked against the role on every request too, revoking makes it visible
+    let revoked = match revoke_api_keys_beyond_role(&mut tx, target_user_id, role).await {
+        Ok(revoked) => revoked,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if tx.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    flash_messages.info(format!("The role has been changed to {}.", role.as_str()));
+    if revoked > 0 {
+        flash_messages.info(format!(
+            "{} API key(s) with scopes beyond the new role have been revoked.",
+            revoked
+        ));
+    }
+    see_other("/admin/users")
+}
+
+#[tracing::instrument(name = "Check if an email has an account", skip(tx, email))]
+async fn email_has_account(
+    tx: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"SELECT user_id FROM users WHERE email = $1"#,
+        email.as_ref(),
+    )
+    .fetch_optional(tx.deref_mut())
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(result.is_some())
+}
+
+#[tracing::instrument(name = "Delete pending invites", skip(tx, email))]
+async fn delete_invites(
+    tx: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM user_invites WHERE email = $1"#,
+        email.as_ref()
+    )
+    .execute(tx.deref_mut())
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Store invite", skip(tx, invite_token, email))]
+async fn store_invite(
+    tx: &mut Transaction<'_, Postgres>,
+    invite_token: &InviteToken,
+    email: &SubscriberEmail,
+    role: UserRole,
+    invited_by: Uuid,
+    token_ttl: chrono::Duration,
+) -> Result<(), sqlx::Error> {
+    let created_at = Utc::now();
+    sqlx::query!(
+        r#"
+        INSERT INTO user_invites (token_hash, email, role, invited_by, created_at, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        invite_token.hash(),
+        email.as_ref(),
+        role.as_str(),
+        invited_by,
+        created_at,
+        created_at + token_ttl,
+    )
+    .execute(tx.deref_mut())
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Send an invite email", skip_all)]
+async fn send_invite_email(
+    email_client: &EmailClient,
+    recipient: SubscriberEmail,
+    role: UserRole,
+    base_url: &str,
+    invite_token: &InviteToken,
+) -> Result<(), EmailError> {
+    let invite_link = format!("{}/invite?token={}", base_url, invite_token.as_ref());
+    let html_body = format!(
+        "You have been invited to join the newsletter admin as {}.<br />\
+        Click <a href=\"{}\">here</a> to create your account.",
+        role.as_str(),
+        invite_link
+    );
+    let plain_body = format!(
+        "You have been invited to join the newsletter admin as {}.\n\
+        Visit {} to create your account.",
+        role.as_str(),
+        invite_link
+    );
+
+    email_client
+        .send_email(recipient, "You have been invited", &html_body, &plain_body)
+        .await
+        .map_err(|err| {
+            tracing::error!("Failed to send invite email: {:?}", err);
+            err
+        })
+}
+
+#[tracing::instrument(name = "Update user role", skip(tx))]
+async fn update_user_role(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    role: UserRole,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"UPDATE users SET role = $1 WHERE user_id = $2"#,
+        role.as_str(),
+        user_id,
+    )
+    .execute(tx.deref_mut())
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+#[tracing::instrument(name = "Get users", skip(db_pool))]
+async fn get_users(db_pool: &PgPool) -> Result<Vec<UserSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        UserSummary,
+        r#"SELECT user_id, username, email, role FROM users ORDER BY username"#
+    )
+    .fetch_all(db_pool)
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })
+}
+
+#[tracing::instrument(name = "Get pending invites", skip(db_pool))]
+async fn get_pending_invites(db_pool: &PgPool) -> Result<Vec<PendingInvite>, sqlx::Error> {
+    sqlx::query_as!(
+        PendingInvite,
+        r#"
+        SELECT email, role, expires_at FROM user_invites
+        WHERE expires_at > $1
+        ORDER BY created_at DESC
+        "#,
+        Utc::now(),
+    )
+    .fetch_all(db_pool)
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })
+}
+
+async fn users_page_response(
+    mut response: actix_web::HttpResponseBuilder,
+    db_pool: &PgPool,
+    user_id: Uuid,
+    csrf_token: &CsrfToken,
+    message: &str,
+) -> HttpResponse {
+    let csrf_field = csrf_token.form_field();
+    let (users, invites) = match (get_users(db_pool).await, get_pending_invites(db_pool).await) {
+        (Ok(users), Ok(invites)) => (users, invites),
+        _ => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let user_rows: String = users
+        .iter()
+        .map(|user| {
+            let role = if user.user_id == user_id {
+                user.role.clone()
+            } else {
+                format!(
+                    r#"<form action="/admin/users/{}/role" method="post">{}{}<button type="submit">Change</button></form>"#,
+                    user.user_id,
+                    csrf_field,
+                    role_select(&user.role)
+                )
+            };
+            format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                htmlescape::encode_minimal(&user.username),
+                htmlescape::encode_minimal(user.email.as_deref().unwrap_or("")),
+                role
+            )
+        })
+        .collect();
+    let invite_rows: String = invites
+        .iter()
+        .map(|invite| {
+            format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                htmlescape::encode_minimal(&invite.email),
+                invite.role,
+                invite.expires_at.to_rfc3339()
+            )
+        })
+        .collect();
+
+    response.content_type(ContentType::html()).body(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Users</title>
+</head>
+<body>
+    {message}
+    <table>
+        <tr><th>Username</th><th>Email</th><th>Role</th></tr>
+        {user_rows}
+    </table>
+    <p>Pending invites:</p>
+    <table>
+        <tr><th>Email</th><th>Role</th><th>Expires</th></tr>
+        {invite_rows}
+    </table>
+    <form action="/admin/users/invite" method="post">
+        {csrf_field}
+        <label>Email
+            <input type="text" placeholder="Enter the email to invite" name="email">
+        </label>
+        {role_select}
+        <button type="submit">Send invite</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        role_select = role_select(UserRole::Viewer.as_str()),
+    ))
+}
+
+fn role_select(selected: &str) -> String {
+    let options: String = UserRole::ALL
+        .iter()
+        .map(|role| {
+            let selected = if role.as_str() == selected {
+                " selected"
+            } else {
+                ""
+            };
+            format!(
+                r#"<option value="{role}"{selected}>{role}</option>"#,
+                role = role.as_str()
+            )
+        })
+        .collect();
+    format!(r#"<select name="role">{}</select>"#, options)
+}
@@ -1,5 +1,35 @@
-use actix_web::{HttpResponse, Responder};
+use actix_web::{web, HttpResponse, Responder};
 
-pub async fn health_check() -> impl Responder {
-    HttpResponse::Ok()
+use crate::email_client::{CircuitState, EmailClient};
+
+#[derive(serde::Serialize)]
+struct HealthReport {
+    // `degraded` while any email provider's circuit isn't closed, the app itself is up
+    status: &'static str,
+    email_providers: Vec<EmailProviderHealth>,
+}
+
+#[derive(serde::Serialize)]
+struct EmailProviderHealth {
+    provider: String,
+    circuit: CircuitState,
+}
+
+pub async fn health_check(email_client: web::Data<EmailClient>) -> impl Responder {
+    let email_providers: Vec<_> = email_client
+        .provider_statuses()
+        .into_iter()
+        .map(|status| EmailProviderHealth {
+            provider: status.provider,
+            circuit: status.circuit,
+        })
+        .collect();
+    let degraded = email_providers
+        .iter()
+        .any(|provider| provider.circuit != CircuitState::Closed);
+
+    HttpResponse::Ok().json(HealthReport {
+        status: if degraded { "degraded" } else { "ok" },
+        email_providers,
+    })
 }
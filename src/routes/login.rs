@@ -0,0 +1,108 @@
+use actix_web::{http::header::ContentType, web, HttpResponse, Responder};
+use secrecy::Secret;
+use sqlx::PgPool;
+
+use crate::{
+    authentication::{get_totp_secret, validate_credentials, AuthError, Credentials},
+    csrf::CsrfToken,
+    flash_messages::FlashMessages,
+    session_state::TypedSession,
+    utils::see_other,
+};
+
+#[derive(serde::Deserialize)]
+pub struct LoginFormData {
+    username: String,
+    password: Secret<String>,
+}
+
+pub async fn login_form(csrf_token: CsrfToken, flash_messages: FlashMessages) -> impl Responder {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(login_page(&flash_messages.to_html(), &csrf_token))
+}
+
+#[tracing::instrument(
+    name = "Log in an admin user",
+    skip(form, csrf_token, db_pool, session),
+    fields(username = tracing::field::Empty, user_id = tracing::field::Empty)
+)]
+pub async fn login(
+    form: web::Form<LoginFormData>,
+    csrf_token: CsrfToken,
+    db_pool: web::Data<PgPool>,
+    session: TypedSession,
+) -> impl Responder {
+    let credentials = Credentials {
+        username: form.0.username,
+        password: form.0.password,
+    };
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
+
+    let user_id = match validate_credentials(credentials, &db_pool).await {
+        Ok(user_id) => user_id,
+        // Same answer for unknown usernames and wrong passwords
+        Err(AuthError::InvalidCredentials(_)) => {
+            return HttpResponse::Unauthorized()
+                .content_type(ContentType::html())
+                .body(login_page(
+                    "<p><i>Authentication failed.</i></p>",
+                    &csrf_token,
+                ))
+        }
+        Err(AuthError::UnexpectedError(err)) => {
+            tracing::error!("Failed to validate credentials: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    let totp_secret = match get_totp_secret(&db_pool, user_id).await {
+        Ok(secret) => secret,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    session.renew();
+    // Users with a second factor are only logged in once it has been verified
+    if totp_secret.is_some() {
+        if let Err(err) = session.insert_pending_user_id(user_id) {
+            tracing::error!("Failed to save the user id into the session: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+        return see_other("/login/2fa");
+    }
+
+    if let Err(err) = session.insert_user_id(user_id) {
+        tracing::error!("Failed to save the user id into the session: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    see_other("/admin/dashboard")
+}
+
+fn login_page(message: &str, csrf_token: &CsrfToken) -> String {
+    let csrf_field = csrf_token.form_field();
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Login</title>
+</head>
+<body>
+    {message}
+    <form action="/login" method="post">
+        {csrf_field}
+        <label>Username
+            <input type="text" placeholder="Enter Username" name="username">
+        </label>
+        <label>Password
+            <input type="password" placeholder="Enter Password" name="password">
+        </label>
+        <button type="submit">Login</button>
+    </form>
+    <p><a href="/password/forgot">Forgot your password?</a></p>
+</body>
+</html>"#
+    )
+}
@@ -0,0 +1,138 @@
+use actix_web::{http::header::ContentType, web, HttpResponse, Responder};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+
+use crate::{
+    authentication::{
+        get_two_factor_lockout, record_failed_second_factor, reset_failed_second_factors,
+        verify_second_factor,
+    },
+    configurations::TwoFactorSettings,
+    csrf::CsrfToken,
+    flash_messages::FlashMessages,
+    session_state::TypedSession,
+    utils::see_other,
+};
+
+const LOCKED_OUT: &str = "Too many invalid authentication codes, please try again later.";
+
+#[derive(serde::Deserialize)]
+pub struct TwoFactorFormData {
+    code: Secret<String>,
+}
+
+pub async fn two_factor_form(session: TypedSession, csrf_token: CsrfToken) -> impl Responder {
+    match session.get_pending_user_id() {
+        Ok(Some(_)) => HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .body(two_factor_page("", &csrf_token)),
+        Ok(None) => see_other("/login"),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(
+    name = "Verify the second login step",
+    skip(form, csrf_token, flash_messages, session, db_pool, two_factor_settings),
+    fields(user_id = tracing::field::Empty)
+)]
+pub async fn two_factor_login(
+    form: web::Form<TwoFactorFormData>,
+    csrf_token: CsrfToken,
+    flash_messages: FlashMessages,
+    session: TypedSession,
+    db_pool: web::Data<PgPool>,
+    two_factor_settings: web::Data<TwoFactorSettings>,
+) -> impl Responder {
+    let user_id = match session.get_pending_user_id() {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => return see_other("/login"),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    // Codes are short, the lockout is kept on the user so logging in again doesn't reset it
+    match get_two_factor_lockout(&db_pool, user_id).await {
+        Ok(None) => {}
+        Ok(Some(_)) => return restart_login(session, &flash_messages),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    let code = form.code.expose_secret().trim();
+    let is_valid = match verify_second_factor(&db_pool, user_id, code).await {
+        Ok(is_valid) => is_valid,
+        Err(err) => {
+            tracing::error!("Failed to verify second factor: {:?}", err);
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    if !is_valid {
+        let is_locked = match record_failed_second_factor(
+            &db_pool,
+            user_id,
+            two_factor_settings.max_attempts,
+            two_factor_settings.lockout_duration(),
+        )
+        .await
+        {
+            Ok(is_locked) => is_locked,
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        };
+        if is_locked {
+            tracing::warn!("Too many invalid second factor codes, locking the second step");
+            return restart_login(session, &flash_messages);
+        }
+
+        return HttpResponse::Unauthorized()
+            .content_type(ContentType::html())
+            .body(two_factor_page(
+                "<p><i>Invalid authentication code.</i></p>",
+                &csrf_token,
+            ));
+    }
+
+    if reset_failed_second_factors(&db_pool, user_id)
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+    session.renew();
+    session.remove_pending_user_id();
+    if let Err(err) = session.insert_user_id(user_id) {
+        tracing::error!("Failed to save the user id into the session: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    see_other("/admin/dashboard")
+}
+
+fn restart_login(session: TypedSession, flash_messages: &FlashMessages) -> HttpResponse {
+    session.log_out();
+    flash_messages.error(LOCKED_OUT);
+    see_other("/login")
+}
+
+fn two_factor_page(message: &str, csrf_token: &CsrfToken) -> String {
+    let csrf_field = csrf_token.form_field();
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Two-factor authentication</title>
+</head>
+<body>
+    {message}
+    <form action="/login/2fa" method="post">
+        {csrf_field}
+        <label>Authentication code
+            <input type="text" inputmode="numeric" autocomplete="one-time-code" placeholder="123456 or a recovery code" name="code">
+        </label>
+        <button type="submit">Verify</button>
+    </form>
+</body>
+</html>"#
+    )
+}
@@ -0,0 +1,52 @@
+use std::fmt::Write;
+
+use actix_web::{http::header::ContentType, web, HttpResponse};
+
+use crate::email_client::EmailClient;
+
+// Prometheus text format, so any scraper can pick it up
+pub async fn metrics(email_client: web::Data<EmailClient>) -> HttpResponse {
+    let statuses = email_client.provider_statuses();
+
+    let mut body = String::new();
+    writeln!(
+        body,
+        "# HELP email_permit_wait_seconds Time spent waiting for the provider limits before sending an email"
+    )
+    .unwrap();
+    writeln!(body, "# TYPE email_permit_wait_seconds summary").unwrap();
+    for status in &statuses {
+        writeln!(
+            body,
+            "email_permit_wait_seconds_sum{{provider=\"{}\"}} {}",
+            status.provider,
+            status.permit_wait.total.as_secs_f64()
+        )
+        .unwrap();
+        writeln!(
+            body,
+            "email_permit_wait_seconds_count{{provider=\"{}\"}} {}",
+            status.provider, status.permit_wait.count
+        )
+        .unwrap();
+    }
+    writeln!(
+        body,
+        "# HELP email_permit_wait_seconds_max Longest wait for the provider limits"
+    )
+    .unwrap();
+    writeln!(body, "# TYPE email_permit_wait_seconds_max gauge").unwrap();
+    for status in &statuses {
+        writeln!(
+            body,
+            "email_permit_wait_seconds_max{{provider=\"{}\"}} {}",
+            status.provider,
+            status.permit_wait.max.as_secs_f64()
+        )
+        .unwrap();
+    }
+
+    HttpResponse::Ok()
+        .content_type(ContentType::plaintext())
+        .body(body)
+}
@@ -1,7 +1,37 @@
+mod accept_invite;
+mod admin_api_keys;
+mod admin_dashboard;
+mod admin_logout;
+mod admin_password;
+mod admin_subscribers;
+mod admin_two_factor;
+mod admin_users;
 mod health_check;
+mod login;
+mod login_two_factor;
+mod metrics;
+mod newsletters;
+mod password_reset;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_resend;
+mod subscriptions_unsubscribe;
 
+pub use accept_invite::*;
+pub use admin_api_keys::*;
+pub use admin_dashboard::*;
+pub use admin_logout::*;
+pub use admin_password::*;
+pub use admin_subscribers::*;
+pub use admin_two_factor::*;
+pub use admin_users::*;
 pub use health_check::*;
+pub use login::*;
+pub use login_two_factor::*;
+pub use metrics::*;
+pub use newsletters::*;
+pub use password_reset::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_resend::*;
+pub use subscriptions_unsubscribe::*;
@@ -0,0 +1,191 @@
+use std::ops::DerefMut;
+
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use sqlx::{types::chrono::Utc, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    authentication::{AuthenticatedApiKey, UserId},
+    configurations::IdempotencySettings,
+    domain::{api_key_scope::ApiKeyScope, subscription_status::SubscriptionStatus},
+    idempotency::{
+        get_idempotency_key, save_response, try_processing, NextAction, RequestFingerprint,
+    },
+};
+
+#[derive(serde::Deserialize, serde::Serialize)]
+pub struct NewsletterBody {
+    title: String,
+    content: NewsletterContent,
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+pub struct NewsletterContent {
+    html: String,
+    text: String,
+}
+
+// Delivery happens in the background, see `issue_delivery_worker`
+#[tracing::instrument(
+    name = "Publishing a newsletter issue",
+    skip(request, body, db_pool, idempotency_settings, user_id),
+    fields(newsletter_title = %body.title, user_id = %*user_id)
+)]
+pub async fn publish_newsletter(
+    request: HttpRequest,
+    body: web::Json<NewsletterBody>,
+    db_pool: web::Data<PgPool>,
+    idempotency_settings: web::Data<IdempotencySettings>,
+    user_id: web::ReqData<UserId>,
+) -> impl Responder {
+    publish_issue(
+        &request,
+        &body,
+        &db_pool,
+        &idempotency_settings,
+        *user_id.into_inner(),
+    )
+    .await
+}
+
+// Same as `publish_newsletter`, for machine clients such as a CMS
+#[tracing::instrument(
+    name = "Publishing a newsletter issue with an API key",
+    skip(api_key, request, body, db_pool, idempotency_settings),
+    fields(newsletter_title = %body.title, api_key_id = %api_key.api_key_id)
+)]
+pub async fn publish_newsletter_with_api_key(
+    api_key: AuthenticatedApiKey,
+    request: HttpRequest,
+    body: web::Json<NewsletterBody>,
+    db_pool: web::Data<PgPool>,
+    idempotency_settings: web::Data<IdempotencySettings>,
+) -> impl Responder {
+    if !api_key.has_scope(ApiKeyScope::NewslettersPublish) {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    publish_issue(
+        &request,
+        &body,
+        &db_pool,
+        &idempotency_settings,
+        api_key.user_id,
+    )
+    .await
+}
+
+async fn publish_issue(
+    request: &HttpRequest,
+    body: &NewsletterBody,
+    db_pool: &PgPool,
+    idempotency_settings: &IdempotencySettings,
+    user_id: Uuid,
+) -> HttpResponse {
+    let idempotency_key = match get_idempotency_key(request) {
+        Ok(key) => key,
+        Err(_) => return HttpResponse::BadRequest().finish(),
+    };
+
+    // A retried request gets the response of the first one, without publishing the issue twice
+    let fingerprint = RequestFingerprint::new(request, body);
+    let mut tx = match &idempotency_key {
+        Some(key) => match try_processing(
+            db_pool,
+            key,
+            user_id,
+            &fingerprint,
+            idempotency_settings.ttl(),
+        )
+        .await
+        {
+            Ok(NextAction::StartProcessing(tx)) => tx,
+            Ok(NextAction::ReturnSavedResponse(saved_response)) => return saved_response,
+            // The key was first used for another endpoint or another issue
+            Ok(NextAction::RejectKeyReuse) => return HttpResponse::UnprocessableEntity().finish(),
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        },
+        None => match db_pool.begin().await {
+            Ok(tx) => tx,
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        },
+    };
+
+    let newsletter_issue_id = match insert_newsletter_issue(&mut tx, body).await {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    if enqueue_delivery_tasks(&mut tx, newsletter_issue_id)
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let response = HttpResponse::Accepted().finish();
+    let result = match idempotency_key {
+        Some(key) => save_response(tx, &key, user_id, response).await,
+        None => tx.commit().await.map(|_| response).map_err(Into::into),
+    };
+
+    result.unwrap_or_else(|err| {
+        tracing::error!("Failed to commit newsletter issue: {:?}", err);
+        HttpResponse::InternalServerError().finish()
+    })
+}
+
+#[tracing::instrument(name = "Saving newsletter issue", skip(tx, body))]
+async fn insert_newsletter_issue(
+    tx: &mut Transaction<'_, Postgres>,
+    body: &NewsletterBody,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id, title, text_content, html_content, published_at
+        )
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        newsletter_issue_id,
+        body.title,
+        body.content.text,
+        body.content.html,
+        Utc::now(),
+    )
+    .execute(tx.deref_mut())
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(newsletter_issue_id)
+}
+
+#[tracing::instrument(
+    name = "Enqueueing delivery tasks for confirmed subscribers",
+    skip(tx, newsletter_issue_id)
+)]
+async fn enqueue_delivery_tasks(
+    tx: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
+        SELECT $1, email FROM subscriptions WHERE status = $2
+        "#,
+        newsletter_issue_id,
+        SubscriptionStatus::Confirmed.as_str(),
+    )
+    .execute(tx.deref_mut())
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(())
+}
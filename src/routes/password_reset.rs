@@ -0,0 +1,522 @@
+use std::ops::DerefMut;
+
+use actix_web::{http::header::ContentType, web, HttpResponse, Responder};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{
+    types::chrono::{DateTime, Utc},
+    PgPool, Postgres, Transaction,
+};
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::{
+    authentication::change_password,
+    configurations::PasswordResetSettings,
+    csrf::CsrfToken,
+    domain::{
+        new_password::NewPassword, random_token::PasswordResetToken,
+        subscriber_email::SubscriberEmail,
+    },
+    email_client::{EmailClient, EmailError},
+    flash_messages::FlashMessages,
+    session_store::delete_user_sessions,
+    startup::ApplicationBaseUrl,
+    utils::see_other,
+};
+
+const RESET_LINK_SENT_PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Password reset</title>
+</head>
+<body>
+    <p>If an account is registered with this address, a password reset link has been sent to it.</p>
+</body>
+</html>"#;
+
+const INVALID_RESET_LINK_PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Password reset</title>
+</head>
+<body>
+    <p>This password reset link is invalid or has expired.</p>
+    <p><a href="/password/forgot">Request a new one</a></p>
+</body>
+</html>"#;
+
+#[derive(serde::Deserialize)]
+pub struct ForgotPasswordFormData {
+    email: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ResetPasswordParameters {
+    token: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ResetPasswordFormData {
+    token: String,
+    new_password: Secret<String>,
+    new_password_check: Secret<String>,
+}
+
+pub async fn forgot_password_form(csrf_token: CsrfToken) -> impl Responder {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(forgot_password_page("", &csrf_token))
+}
+
+#[tracing::instrument(
+    name = "Requesting a password reset",
+    skip(
+        form,
+        csrf_token,
+        db_pool,
+        email_client,
+        base_url,
+        password_reset_settings
+    )
+)]
+pub async fn forgot_password(
+    form: web::Form<ForgotPasswordFormData>,
+    csrf_token: CsrfToken,
+    db_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    password_reset_settings: web::Data<PasswordResetSettings>,
+) -> impl Responder {
+    let email = match SubscriberEmail::parse(form.0.email) {
+        Ok(email) => email,
+        Err(_) => {
+            return HttpResponse::BadRequest()
+                .content_type(ContentType::html())
+                .body(forgot_password_page(
+                    "<p><i>Please enter a valid email address.</i></p>",
+                    &csrf_token,
+                ))
+        }
+    };
+
+    let mut tx = match db_pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    // Unknown addresses get the same answer, so the form can't be used to find accounts
+    let user_id = match get_user_id_by_email(&mut tx, &email).await {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => return reset_link_sent(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    // Rate limited requests get the same answer too, so the form can't flood an inbox
+    match get_last_reset_token_issued_at(&mut tx, user_id).await {
+        Ok(Some(last_issued_at))
+            if last_issued_at + password_reset_settings.resend_interval() > Utc::now() =>
+        {
+            return reset_link_sent()
+        }
+        Ok(_) => {}
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
+    // Only the latest reset link should work
+    if delete_password_reset_tokens(&mut tx, user_id)
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    let reset_token = PasswordResetToken::generate();
+    if store_password_reset_token(
+        &mut tx,
+        user_id,
+        &reset_token,
+        password_reset_settings.token_ttl(),
+    )
+    .await
+    .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    // Committed first, so the rate limit holds while the email is on its way
+    if tx.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    // Sent after answering, so known addresses answer as fast as unknown ones,
+    // and a failed delivery doesn't show up in the response either
+    let db_pool = db_pool.into_inner();
+    let email_client = email_client.into_inner();
+    let base_url = base_url.0.clone();
+    tokio::spawn(
+        async move {
+            if send_password_reset_email(&email_client, email, &base_url, &reset_token)
+                .await
+                .is_err()
+            {
+                // Undo the link that never went out, so a new one can be requested right away
+                let _ = delete_password_reset_token(&db_pool, &reset_token).await;
+            }
+        }
+        .instrument(tracing::Span::current()),
+    );
+
+    reset_link_sent()
+}
+
+#[tracing::instrument(
+    name = "Show password reset page",
+    skip(parameters, csrf_token, db_pool)
+)]
+pub async fn reset_password_form(
+    parameters: web::Query<ResetPasswordParameters>,
+    csrf_token: CsrfToken,
+    db_pool: web::Data<PgPool>,
+) -> impl Responder {
+    let reset_token = match PasswordResetToken::parse(parameters.0.token) {
+        Ok(token) => token,
+        Err(_) => return HttpResponse::BadRequest().finish(),
+    };
+
+    match is_valid_reset_token(&db_pool, &reset_token).await {
+        Ok(true) => HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .body(reset_password_page(&reset_token, "", &csrf_token)),
+        Ok(false) => HttpResponse::Unauthorized()
+            .content_type(ContentType::html())
+            .body(INVALID_RESET_LINK_PAGE),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(
+    name = "Resetting a password",
+    skip(form, csrf_token, flash_messages, db_pool)
+)]
+pub async fn reset_password(
+    form: web::Form<ResetPasswordFormData>,
+    csrf_token: CsrfToken,
+    flash_messages: FlashMessages,
+    db_pool: web::Data<PgPool>,
+) -> impl Responder {
+    let form = form.0;
+    let reset_token = match PasswordResetToken::parse(form.token) {
+        Ok(token) => token,
+        Err(_) => return HttpResponse::BadRequest().finish(),
+    };
+
+    if form.new_password.expose_secret() != form.new_password_check.expose_secret() {
+        return HttpResponse::BadRequest()
+            .content_type(ContentType::html())
+            .body(reset_password_page(
+                &reset_token,
+                "<p><i>You entered two different new passwords - the field values must match.</i></p>",
+                &csrf_token,
+            ));
+    }
+
+    let new_password = match NewPassword::parse(form.new_password) {
+        Ok(password) => password,
+        Err(err) => {
+            return HttpResponse::BadRequest()
+                .content_type(ContentType::html())
+                .body(reset_password_page(
+                    &reset_token,
+                    &format!("<p><i>{}</i></p>", err),
+                    &csrf_token,
+                ))
+        }
+    };
+
+    let mut tx = match db_pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let user_id = match get_user_id_from_reset_token(&mut tx, &reset_token).await {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => {
+            return HttpResponse::Unauthorized()
+                .content_type(ContentType::html())
+                .body(INVALID_RESET_LINK_PAGE)
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    if let Err(err) = change_password(&mut tx, user_id, new_password.as_ref().clone()).await {
+        tracing::error!("Failed to change password: {:?}", err);
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    // Reset links are single-use
+    if delete_password_reset_tokens(&mut tx, user_id)
+        .await
+        .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+    // Whoever else knew the old password gets logged out
+    if delete_user_sessions(&mut tx, user_id).await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    if tx.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+
+    flash_messages.info("Your password has been reset, you can now log in.");
+    see_other("/login")
+}
+
+#[tracing::instrument(name = "Send a password reset email", skip_all)]
+async fn send_password_reset_email(
+    email_client: &EmailClient,
+    recipient: SubscriberEmail,
+    base_url: &str,
+    reset_token: &PasswordResetToken,
+) -> Result<(), EmailError> {
+    let reset_link = format!("{}/password/reset?token={}", base_url, reset_token.as_ref());
+    let html_body = format!(
+        "We received a request to reset your password.<br />\
+        Click <a href=\"{}\">here</a> to choose a new one.<br />\
+        If you didn't ask for it, you can safely ignore this email.",
+        reset_link
+    );
+    let plain_body = format!(
+        "We received a request to reset your password.\n\
+        Visit {} to choose a new one.\n\
+        If you didn't ask for it, you can safely ignore this email.",
+        reset_link
+    );
+
+    email_client
+        .send_email(recipient, "Reset your password", &html_body, &plain_body)
+        .await
+        .map_err(|err| {
+            tracing::error!("Failed to send password reset email: {:?}", err);
+            err
+        })
+}
+
+#[tracing::instrument(name = "Get user id by email", skip(tx, email))]
+async fn get_user_id_by_email(
+    tx: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    // Lock the user row, so concurrent requests are serialized and rate limited properly
+    let result = sqlx::query!(
+        r#"SELECT user_id FROM users WHERE email = $1 FOR UPDATE"#,
+        email.as_ref(),
+    )
+    .fetch_optional(tx.deref_mut())
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(result.map(|r| r.user_id))
+}
+
+#[tracing::instrument(name = "Get last password reset token issue time", skip(tx))]
+async fn get_last_reset_token_issued_at(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"SELECT MAX(created_at) AS last_issued_at FROM password_reset_tokens WHERE user_id = $1"#,
+        user_id,
+    )
+    .fetch_one(tx.deref_mut())
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(result.last_issued_at)
+}
+
+#[tracing::instrument(name = "Store password reset token", skip(tx, reset_token))]
+async fn store_password_reset_token(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    reset_token: &PasswordResetToken,
+    token_ttl: chrono::Duration,
+) -> Result<(), sqlx::Error> {
+    let created_at = Utc::now();
+    sqlx::query!(
+        r#"
+        INSERT INTO password_reset_tokens (token_hash, user_id, created_at, expires_at)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        reset_token.hash(),
+        user_id,
+        created_at,
+        created_at + token_ttl,
+    )
+    .execute(tx.deref_mut())
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Delete password reset tokens", skip(tx))]
+async fn delete_password_reset_tokens(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM password_reset_tokens WHERE user_id = $1"#,
+        user_id,
+    )
+    .execute(tx.deref_mut())
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Delete password reset token", skip(db_pool, reset_token))]
+async fn delete_password_reset_token(
+    db_pool: &PgPool,
+    reset_token: &PasswordResetToken,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM password_reset_tokens WHERE token_hash = $1"#,
+        reset_token.hash(),
+    )
+    .execute(db_pool)
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Check password reset token", skip(db_pool, reset_token))]
+async fn is_valid_reset_token(
+    db_pool: &PgPool,
+    reset_token: &PasswordResetToken,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        SELECT user_id FROM password_reset_tokens
+        WHERE token_hash = $1 AND expires_at > $2
+        "#,
+        reset_token.hash(),
+        Utc::now(),
+    )
+    .fetch_optional(db_pool)
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(result.is_some())
+}
+
+// Locks the token, so two concurrent requests can't both use it
+#[tracing::instrument(name = "Get user id from password reset token", skip(tx, reset_token))]
+async fn get_user_id_from_reset_token(
+    tx: &mut Transaction<'_, Postgres>,
+    reset_token: &PasswordResetToken,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        SELECT user_id FROM password_reset_tokens
+        WHERE token_hash = $1 AND expires_at > $2
+        FOR UPDATE
+        "#,
+        reset_token.hash(),
+        Utc::now(),
+    )
+    .fetch_optional(tx.deref_mut())
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(result.map(|r| r.user_id))
+}
+
+fn reset_link_sent() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(RESET_LINK_SENT_PAGE)
+}
+
+fn forgot_password_page(message: &str, csrf_token: &CsrfToken) -> String {
+    let csrf_field = csrf_token.form_field();
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Forgot password</title>
+</head>
+<body>
+    {message}
+    <form action="/password/forgot" method="post">
+        {csrf_field}
+        <label>Email
+            <input type="text" placeholder="Enter your email" name="email">
+        </label>
+        <button type="submit">Send reset link</button>
+    </form>
+</body>
+</html>"#
+    )
+}
+
+fn reset_password_page(
+    reset_token: &PasswordResetToken,
+    message: &str,
+    csrf_token: &CsrfToken,
+) -> String {
+    let csrf_field = csrf_token.form_field();
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Reset password</title>
+</head>
+<body>
+    {message}
+    <form action="/password/reset" method="post">
+        {csrf_field}
+        <input hidden type="text" name="token" value="{}">
+        <label>New password
+            <input type="password" placeholder="Enter new password" name="new_password">
+        </label>
+        <br>
+        <label>Confirm new password
+            <input type="password" placeholder="Type the new password again" name="new_password_check">
+        </label>
+        <br>
+        <button type="submit">Reset password</button>
+    </form>
+</body>
+</html>"#,
+        reset_token.as_ref()
+    )
+}
@@ -1,103 +1,591 @@
 use std::ops::DerefMut;
 
 use actix_web::{
-    web::{self, Form},
-    HttpResponse, Responder,
+    error::InternalError,
+    http::{header::ContentType, StatusCode},
+    web, HttpRequest, HttpResponse, Responder, ResponseError,
+};
+use chrono::Duration;
+use sqlx::{
+    types::chrono::{DateTime, Utc},
+    PgPool, Postgres, Transaction,
 };
-use rand::{distributions::Alphanumeric, thread_rng, Rng};
-use sqlx::{types::chrono::Utc, PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
-use crate::domain::new_subscriber::NewSubscriber;
+use crate::{
+    authentication::AuthenticatedApiKey,
+    configurations::{IdempotencySettings, SubscriptionSettings},
+    content_negotiation::{FormOrJson, ResponseFormat},
+    csrf::CsrfToken,
+    domain::{
+        api_key_scope::ApiKeyScope,
+        new_subscriber::NewSubscriber,
+        random_token::{SubscriptionToken, UnsubscribeToken},
+        subscriber_email::SubscriberEmail,
+        subscription_status::SubscriptionStatus,
+        validation_error::ValidationError,
+    },
+    email_client::{EmailClient, EmailError},
+    flash_messages::FlashMessages,
+    idempotency::{
+        get_idempotency_key, save_response, try_processing, IdempotencyKey, NextAction,
+        RequestFingerprint, ANONYMOUS_USER_ID,
+    },
+    problem_details::{
+        ProblemDetails, BLANK_PROBLEM, IDEMPOTENCY_KEY_REUSED_PROBLEM,
+        INVALID_IDEMPOTENCY_KEY_PROBLEM,
+    },
+    routes::list_unsubscribe_headers,
+    startup::ApplicationBaseUrl,
+    utils::{error_chain_fmt, see_other},
+};
+
+// Same answer whether the address was already subscribed or not, so it doesn't leak who is on the list
+const SUBSCRIPTION_ACCEPTED: &str =
+    "Thanks for subscribing! Please check your inbox to confirm your subscription.";
+const UNEXPECTED_ERROR: &str = "Something went wrong on our side, please try again later.";
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, serde::Serialize)]
 pub struct FormData {
     pub name: String,
     pub email: String,
+    // Only JSON bodies can carry attributes, checked against `SubscriptionSettings::attributes`
+    #[serde(default)]
+    pub attributes: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(thiserror::Error)]
+pub enum SubscribeError {
+    #[error(transparent)]
+    ValidationError(#[from] ValidationError),
+    #[error("Invalid idempotency key: {0}")]
+    InvalidIdempotencyKey(String),
+    #[error("The idempotency key was already used for another request")]
+    IdempotencyKeyReused,
+    #[error("Failed to access the subscriptions store")]
+    StoreError(#[source] anyhow::Error),
+    #[error("Failed to send the confirmation email")]
+    EmailDeliveryError(#[source] EmailError),
+}
+
+// Logged by `TracingLogger` along with the failed request
+impl std::fmt::Debug for SubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl From<sqlx::Error> for SubscribeError {
+    fn from(err: sqlx::Error) -> Self {
+        Self::StoreError(err.into())
+    }
+}
+
+impl ResponseError for SubscribeError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::ValidationError(_) | Self::InvalidIdempotencyKey(_) => StatusCode::BAD_REQUEST,
+            Self::IdempotencyKeyReused => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::StoreError(_) | Self::EmailDeliveryError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    // The causes stay in the logs, clients get a fixed problem for each kind of failure
+    fn error_response(&self) -> HttpResponse {
+        let problem = match self {
+            Self::ValidationError(err) => ProblemDetails::validation(err),
+            Self::InvalidIdempotencyKey(err) => ProblemDetails::new(
+                INVALID_IDEMPOTENCY_KEY_PROBLEM,
+                self.status_code(),
+                "The idempotency key is invalid.",
+            )
+            .with_detail(err),
+            Self::IdempotencyKeyReused => ProblemDetails::new(
+                IDEMPOTENCY_KEY_REUSED_PROBLEM,
+                self.status_code(),
+                "The idempotency key was already used for another request.",
+            ),
+            Self::StoreError(_) | Self::EmailDeliveryError(_) => {
+                ProblemDetails::new(BLANK_PROBLEM, self.status_code(), UNEXPECTED_ERROR)
+            }
+        };
+        problem.to_response()
+    }
+}
+
+pub async fn subscribe_form(
+    csrf_token: CsrfToken,
+    flash_messages: FlashMessages,
+) -> impl Responder {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscribe</title>
+</head>
+<body>
+    {}
+    <form action="/subscriptions" method="post">
+        {}
+        <label>Name
+            <input type="text" placeholder="Enter your name" name="name">
+        </label>
+        <label>Email
+            <input type="email" placeholder="Enter your email" name="email">
+        </label>
+        <button type="submit">Subscribe</button>
+    </form>
+</body>
+</html>"#,
+            flash_messages.to_html(),
+            csrf_token.form_field()
+        ))
 }
 
+// Browsers are sent back to the form with flash messages, other clients get JSON
+#[allow(clippy::too_many_arguments)]
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, db_pool),
+    skip(
+        request,
+        body,
+        flash_messages,
+        db_pool,
+        email_client,
+        base_url,
+        subscription_settings,
+        idempotency_settings
+    ),
     fields(
-        subscriber_email = %form.email,
-        subscriber_name = %form.name
+        subscriber_email = %body.0.email,
+        subscriber_name = %body.0.name
     )
 )]
-pub async fn subscribe(form: Form<FormData>, db_pool: web::Data<PgPool>) -> impl Responder {
-    // If you provide a TryFrom implementation, your type automatically gets the corresponding TryInto implementation, for free
-    let new_subscriber: NewSubscriber = match form.0.try_into() {
-        Ok(subscriber) => subscriber,
-        Err(_) => return HttpResponse::BadRequest().finish(),
-    };
+pub async fn subscribe(
+    request: HttpRequest,
+    body: FormOrJson<FormData>,
+    flash_messages: FlashMessages,
+    db_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    subscription_settings: web::Data<SubscriptionSettings>,
+    idempotency_settings: web::Data<IdempotencySettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // A retried form post replays the first answer instead of sending another confirmation email
+    let result = process_subscription(
+        &request,
+        body.0,
+        ANONYMOUS_USER_ID,
+        &idempotency_settings,
+        &db_pool,
+        &email_client,
+        &base_url,
+        &subscription_settings,
+    )
+    .await;
 
-    let mut tx = match db_pool.begin().await {
-        Ok(tx) => tx,
-        Err(_) => return HttpResponse::InternalServerError().finish(),
-    };
+    match ResponseFormat::preferred(&request) {
+        ResponseFormat::Json => Ok(result?),
+        ResponseFormat::Html => redirect_to_subscribe_form(result, &flash_messages),
+    }
+}
 
-    // Create new subscriber
-    let subscriber_id = match insert_subscriber(&mut tx, &new_subscriber).await {
-        Ok(id) => id,
-        Err(_) => return HttpResponse::InternalServerError().finish(),
-    };
+// Same as `subscribe`, for machine clients such as a CRM
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(
+    name = "Adding a new subscriber with an API key",
+    skip(
+        api_key,
+        request,
+        body,
+        db_pool,
+        email_client,
+        base_url,
+        subscription_settings,
+        idempotency_settings
+    ),
+    fields(
+        api_key_id = %api_key.api_key_id,
+        subscriber_email = %body.email,
+        subscriber_name = %body.name
+    )
+)]
+pub async fn subscribe_with_api_key(
+    api_key: AuthenticatedApiKey,
+    request: HttpRequest,
+    body: web::Json<FormData>,
+    db_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    subscription_settings: web::Data<SubscriptionSettings>,
+    idempotency_settings: web::Data<IdempotencySettings>,
+) -> Result<HttpResponse, SubscribeError> {
+    if !api_key.has_scope(ApiKeyScope::SubscribersWrite) {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
 
-    let subscription_token = generate_subscription_token();
-    if insert_subscription_token(&mut tx, subscriber_id, &subscription_token)
-        .await
-        .is_err()
-    {
-        return HttpResponse::InternalServerError().finish();
+    process_subscription(
+        &request,
+        body.0,
+        api_key.user_id,
+        &idempotency_settings,
+        &db_pool,
+        &email_client,
+        &base_url,
+        &subscription_settings,
+    )
+    .await
+}
+
+fn redirect_to_subscribe_form(
+    result: Result<HttpResponse, SubscribeError>,
+    flash_messages: &FlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    match result {
+        Ok(_) => {
+            flash_messages.info(SUBSCRIPTION_ACCEPTED);
+            Ok(see_other("/subscriptions"))
+        }
+        Err(SubscribeError::ValidationError(err)) => {
+            for field_error in err.field_errors() {
+                flash_messages.error(field_error.to_string());
+            }
+            Ok(see_other("/subscriptions"))
+        }
+        Err(err) => {
+            flash_messages.error(UNEXPECTED_ERROR);
+            // Keep the cause attached, so it still gets logged
+            Err(InternalError::from_response(err, see_other("/subscriptions")).into())
+        }
     }
+}
+
+// Idempotency keys are scoped to `user_id`
+#[allow(clippy::too_many_arguments)]
+async fn process_subscription(
+    request: &HttpRequest,
+    form: FormData,
+    user_id: Uuid,
+    idempotency_settings: &IdempotencySettings,
+    db_pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &ApplicationBaseUrl,
+    subscription_settings: &SubscriptionSettings,
+) -> Result<HttpResponse, SubscribeError> {
+    let fingerprint = RequestFingerprint::new(request, &form);
+    let new_subscriber = NewSubscriber::parse(form, &subscription_settings.attributes)?;
+
+    let idempotency_key =
+        get_idempotency_key(request).map_err(SubscribeError::InvalidIdempotencyKey)?;
+
+    // A retried request gets the response of the first one, without subscribing twice
+    let mut tx = match &idempotency_key {
+        Some(key) => {
+            match try_processing(
+                db_pool,
+                key,
+                user_id,
+                &fingerprint,
+                idempotency_settings.ttl(),
+            )
+            .await
+            .map_err(SubscribeError::StoreError)?
+            {
+                NextAction::StartProcessing(tx) => tx,
+                NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+                NextAction::RejectKeyReuse => return Err(SubscribeError::IdempotencyKeyReused),
+            }
+        }
+        None => db_pool.begin().await?,
+    };
+    let idempotency = idempotency_key.map(|key| (key, user_id));
+
+    let (subscription_token, unsubscribe_token) =
+        match prepare_subscription_token(&mut tx, &new_subscriber, subscription_settings).await? {
+            Some(tokens) => tokens,
+            // Nothing to send, answer exactly like a new subscription to avoid leaking who is subscribed
+            None => return finish_subscription(tx, idempotency).await,
+        };
+
+    // Send the email before committing, so a failed delivery leaves nothing behind
+    send_confirmation_email(
+        email_client,
+        new_subscriber.email,
+        &base_url.0,
+        &subscription_token,
+        &unsubscribe_token,
+    )
+    .await
+    .map_err(SubscribeError::EmailDeliveryError)?;
 
-    if tx.commit().await.is_err() {
-        return HttpResponse::InternalServerError().finish();
+    finish_subscription(tx, idempotency).await
+}
+
+// Commit the subscription, saving the response when the request is idempotent
+async fn finish_subscription(
+    tx: Transaction<'static, Postgres>,
+    idempotency: Option<(IdempotencyKey, Uuid)>,
+) -> Result<HttpResponse, SubscribeError> {
+    let response = HttpResponse::Ok().json(serde_json::json!({ "message": SUBSCRIPTION_ACCEPTED }));
+    match idempotency {
+        Some((key, user_id)) => save_response(tx, &key, user_id, response)
+            .await
+            .map_err(SubscribeError::StoreError),
+        None => {
+            tx.commit().await?;
+            Ok(response)
+        }
     }
+}
 
-    HttpResponse::Ok().finish()
+struct SavedSubscriber {
+    id: Uuid,
+    status: SubscriptionStatus,
+    unsubscribe_token: UnsubscribeToken,
 }
 
+// Get the tokens to send in the confirmation email, depending on the current state of the subscriber
+// Return None when there is nothing to send: the subscriber is already confirmed,
+// or was sent a confirmation email less than `resend_interval` ago
+#[tracing::instrument(
+    name = "Preparing subscription token",
+    skip(tx, new_subscriber, subscription_settings)
+)]
+async fn prepare_subscription_token(
+    tx: &mut Transaction<'_, Postgres>,
+    new_subscriber: &NewSubscriber,
+    subscription_settings: &SubscriptionSettings,
+) -> Result<Option<(SubscriptionToken, UnsubscribeToken)>, sqlx::Error> {
+    // A concurrent first subscription for the same email waits here for the other one to finish,
+    // instead of failing on the unique constraint
+    insert_subscriber_if_new(tx, new_subscriber).await?;
+    let subscriber = get_subscriber_by_email(tx, &new_subscriber.email)
+        .await?
+        .ok_or(sqlx::Error::RowNotFound)?;
+
+    let token_ttl = subscription_settings.token_ttl();
+    let subscription_token = match subscriber.status {
+        SubscriptionStatus::Confirmed => return Ok(None),
+        SubscriptionStatus::Pending => {
+            // Same rate limit as `resend_confirmation`, the response doesn't change
+            if let Some(sent_at) = get_confirmation_sent_at(tx, subscriber.id).await? {
+                if sent_at + subscription_settings.resend_interval() > Utc::now() {
+                    return Ok(None);
+                }
+            }
+            // Resend the current link if it is still valid
+            match get_valid_subscription_token(tx, subscriber.id).await? {
+                Some(token) => token,
+                None => store_new_subscription_token(tx, subscriber.id, token_ttl).await?,
+            }
+        }
+        // Go through double opt-in again
+        SubscriptionStatus::Unsubscribed => {
+            reset_subscriber(tx, subscriber.id, new_subscriber).await?;
+            delete_subscription_tokens(tx, subscriber.id).await?;
+            store_new_subscription_token(tx, subscriber.id, token_ttl).await?
+        }
+    };
+    // Rolled back with the rest if the email can't be sent
+    record_confirmation_sent(tx, subscriber.id).await?;
+
+    Ok(Some((subscription_token, subscriber.unsubscribe_token)))
+}
+
+#[tracing::instrument(name = "Get subscriber by email", skip(tx, email))]
+async fn get_subscriber_by_email(
+    tx: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+) -> Result<Option<SavedSubscriber>, sqlx::Error> {
+    // Lock the subscriber row, so concurrent subscriptions for the same email are serialized
+    let result = sqlx::query!(
+        r#"SELECT id, status, unsubscribe_token FROM subscriptions WHERE email = $1 FOR UPDATE"#,
+        email.as_ref(),
+    )
+    .fetch_optional(tx.deref_mut())
+    .await?;
+
+    result
+        .map(|r| {
+            let status = SubscriptionStatus::try_from(r.status)
+                .map_err(|err| sqlx::Error::Decode(err.into()))?;
+            let unsubscribe_token = UnsubscribeToken::parse(r.unsubscribe_token)
+                .map_err(|err| sqlx::Error::Decode(err.into()))?;
+            Ok(SavedSubscriber {
+                id: r.id,
+                status,
+                unsubscribe_token,
+            })
+        })
+        .transpose()
+}
+
+// Existing subscribers are left untouched, their state decides what happens next
 #[tracing::instrument(
     name = "Saving new subscriber details in the database",
     skip(tx, new_subscriber)
 )]
-async fn insert_subscriber(
+async fn insert_subscriber_if_new(
     tx: &mut Transaction<'_, Postgres>,
     new_subscriber: &NewSubscriber,
-) -> Result<Uuid, sqlx::Error> {
-    let subscriber_id = Uuid::new_v4();
+) -> Result<(), sqlx::Error> {
+    let unsubscribe_token = UnsubscribeToken::generate();
     sqlx::query!(
-        "INSERT INTO subscriptions (id, name, email, subscribed_at, status) VALUES ($1, $2, $3, $4, $5)",
-        subscriber_id,
+        r#"
+        INSERT INTO subscriptions (id, name, email, subscribed_at, status, unsubscribe_token, attributes)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (email) DO NOTHING
+        "#,
+        Uuid::new_v4(),
         new_subscriber.name.as_ref(),
         new_subscriber.email.as_ref(),
         Utc::now(),
-        "pending",
+        SubscriptionStatus::Pending.as_str(),
+        unsubscribe_token.as_ref(),
+        new_subscriber.attributes.to_json(),
     )
     // This extract the inner connection from the tx, which is required for this execute function to work
     .execute(tx.deref_mut())
-    .await
-    .map_err(|err| {
-        tracing::error!("Failed to execute query: {:?}", err);
-        err
-    })?;
+    .await?;
 
-    Ok(subscriber_id)
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Moving subscriber back to pending",
+    skip(tx, subscriber_id, new_subscriber)
+)]
+async fn reset_subscriber(
+    tx: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    new_subscriber: &NewSubscriber,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions SET name = $1, subscribed_at = $2, status = $3, unsubscribed_at = NULL, attributes = $4 WHERE id = $5"#,
+        new_subscriber.name.as_ref(),
+        Utc::now(),
+        SubscriptionStatus::Pending.as_str(),
+        new_subscriber.attributes.to_json(),
+        subscriber_id,
+    )
+    .execute(tx.deref_mut())
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get valid subscription token", skip(tx, subscriber_id))]
+async fn get_valid_subscription_token(
+    tx: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriptionToken>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        SELECT subscription_token FROM subscription_tokens
+        WHERE subscriber_id = $1 AND expires_at > NOW()
+        ORDER BY created_at DESC
+        LIMIT 1
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(tx.deref_mut())
+    .await?;
+
+    // Stored tokens were generated by us, a token failing to parse is simply not reused
+    Ok(result.and_then(|r| SubscriptionToken::parse(r.subscription_token).ok()))
+}
+
+pub async fn store_new_subscription_token(
+    tx: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    token_ttl: Duration,
+) -> Result<SubscriptionToken, sqlx::Error> {
+    let subscription_token = SubscriptionToken::generate();
+    insert_subscription_token(tx, subscriber_id, &subscription_token, token_ttl).await?;
+    Ok(subscription_token)
 }
 
 #[tracing::instrument(
     name = "Saving new subscription token into database",
-    skip(tx, subscriber_id, subscription_token)
+    skip(tx, subscriber_id, subscription_token, token_ttl)
 )]
 async fn insert_subscription_token(
     tx: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
-    subscription_token: &str,
+    subscription_token: &SubscriptionToken,
+    token_ttl: Duration,
 ) -> Result<(), sqlx::Error> {
+    let created_at = Utc::now();
     sqlx::query!(
-        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id) VALUES ($1, $2)"#,
+        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id, created_at, expires_at) VALUES ($1, $2, $3, $4)"#,
+        subscription_token.as_ref(),
+        subscriber_id,
+        created_at,
+        created_at + token_ttl,
+    )
+    .execute(tx.deref_mut())
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Sending a confirmation email to a new subscriber",
+    skip(
+        email_client,
+        recipient,
+        base_url,
         subscription_token,
-        subscriber_id
+        unsubscribe_token
+    )
+)]
+pub async fn send_confirmation_email(
+    email_client: &EmailClient,
+    recipient: SubscriberEmail,
+    base_url: &str,
+    subscription_token: &SubscriptionToken,
+    unsubscribe_token: &UnsubscribeToken,
+) -> Result<(), EmailError> {
+    let confirmation_link = format!(
+        "{}/subscriptions/confirm?subscription_token={}",
+        base_url,
+        subscription_token.as_ref()
+    );
+    let html_body = format!(
+        "Welcome to our newsletter!<br />\
+        Click <a href=\"{}\">here</a> to confirm your subscription.",
+        confirmation_link
+    );
+    let text_body = format!(
+        "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
+        confirmation_link
+    );
+
+    email_client
+        .send_email_with_headers(
+            recipient,
+            "Welcome!",
+            &html_body,
+            &text_body,
+            &list_unsubscribe_headers(base_url, unsubscribe_token),
+        )
+        .await
+}
+
+#[tracing::instrument(
+    name = "Invalidate previous subscription tokens",
+    skip(tx, subscriber_id)
+)]
+pub async fn delete_subscription_tokens(
+    tx: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id,
     )
     .execute(tx.deref_mut())
     .await
@@ -109,10 +597,34 @@ async fn insert_subscription_token(
     Ok(())
 }
 
-fn generate_subscription_token() -> String {
-    let mut rng = thread_rng();
-    std::iter::repeat_with(|| rng.sample(Alphanumeric))
-        .map(char::from)
-        .take(25)
-        .collect()
+#[tracing::instrument(name = "Get confirmation email send time", skip(tx, subscriber_id))]
+pub async fn get_confirmation_sent_at(
+    tx: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"SELECT confirmation_sent_at FROM subscriptions WHERE id = $1"#,
+        subscriber_id,
+    )
+    .fetch_one(tx.deref_mut())
+    .await?;
+
+    Ok(result.confirmation_sent_at)
+}
+
+// Called for every confirmation email, including the ones resending a link that is still valid
+#[tracing::instrument(name = "Record confirmation email send time", skip(tx, subscriber_id))]
+pub async fn record_confirmation_sent(
+    tx: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions SET confirmation_sent_at = $1 WHERE id = $2"#,
+        Utc::now(),
+        subscriber_id,
+    )
+    .execute(tx.deref_mut())
+    .await?;
+
+    Ok(())
 }
@@ -1,15 +1,157 @@
-use actix_web::{web, HttpResponse, Responder};
+use std::ops::DerefMut;
+
+use actix_web::{http::header::ContentType, web, HttpResponse, Responder};
+use sqlx::{
+    types::chrono::{DateTime, Utc},
+    PgPool, Postgres, Transaction,
+};
+use uuid::Uuid;
+
+use crate::{
+    csrf::CsrfToken,
+    domain::{random_token::SubscriptionToken, subscription_status::SubscriptionStatus},
+};
 
 #[derive(serde::Deserialize)]
 pub struct ConfirmPayload {
     subscription_token: String,
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(_parameters))]
-pub async fn confirm_subscription(_parameters: web::Query<ConfirmPayload>) -> impl Responder {
-    println!(
-        "Confirming user with subscription_token: {}",
-        _parameters.into_inner().subscription_token
-    );
-    return HttpResponse::Ok().finish();
+struct SavedToken {
+    subscriber_id: Uuid,
+    expires_at: DateTime<Utc>,
+    status: SubscriptionStatus,
+}
+
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(parameters, csrf_token, db_pool)
+)]
+pub async fn confirm_subscription(
+    parameters: web::Query<ConfirmPayload>,
+    csrf_token: CsrfToken,
+    db_pool: web::Data<PgPool>,
+) -> impl Responder {
+    let subscription_token = match SubscriptionToken::parse(parameters.0.subscription_token) {
+        Ok(token) => token,
+        Err(_) => return HttpResponse::BadRequest().finish(),
+    };
+
+    let mut tx = match db_pool.begin().await {
+        Ok(tx) => tx,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let saved_token = match get_saved_token(&mut tx, &subscription_token).await {
+        Ok(token) => token,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    match saved_token {
+        // Unknown token, the caller is not allowed to confirm anything
+        None => HttpResponse::Unauthorized().finish(),
+        Some(token) if token.expires_at < Utc::now() => HttpResponse::Gone()
+            .content_type(ContentType::html())
+            .body(expired_token_page(&csrf_token)),
+        // Unsubscribed while the link was being followed, it must not bring them back
+        Some(token) if token.status == SubscriptionStatus::Unsubscribed => {
+            HttpResponse::Unauthorized().finish()
+        }
+        Some(token) => {
+            if confirm_subscriber(&mut tx, token.subscriber_id)
+                .await
+                .is_err()
+            {
+                return HttpResponse::InternalServerError().finish();
+            }
+            if tx.commit().await.is_err() {
+                return HttpResponse::InternalServerError().finish();
+            }
+            HttpResponse::Ok().finish()
+        }
+    }
+}
+
+#[tracing::instrument(name = "Get saved subscription token", skip(tx, subscription_token))]
+async fn get_saved_token(
+    tx: &mut Transaction<'_, Postgres>,
+    subscription_token: &SubscriptionToken,
+) -> Result<Option<SavedToken>, sqlx::Error> {
+    // Lock the subscriber row until the confirmation commits, a concurrent unsubscription
+    // waits for it, or is seen here once it committed
+    let result = sqlx::query!(
+        r#"
+        SELECT t.subscriber_id, t.expires_at, s.status
+        FROM subscription_tokens t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE t.subscription_token = $1
+        FOR UPDATE OF s
+        "#,
+        subscription_token.as_ref(),
+    )
+    .fetch_optional(tx.deref_mut())
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    result
+        .map(|r| {
+            let status = SubscriptionStatus::try_from(r.status)
+                .map_err(|err| sqlx::Error::Decode(err.into()))?;
+            Ok(SavedToken {
+                subscriber_id: r.subscriber_id,
+                expires_at: r.expires_at,
+                status,
+            })
+        })
+        .transpose()
+}
+
+#[tracing::instrument(name = "Mark subscriber as confirmed", skip(tx, subscriber_id))]
+async fn confirm_subscriber(
+    tx: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    // Only pending subscribers move to confirmed, following the link twice changes nothing
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = $1 WHERE id = $2 AND status = $3"#,
+        SubscriptionStatus::Confirmed.as_str(),
+        subscriber_id,
+        SubscriptionStatus::Pending.as_str(),
+    )
+    .execute(tx.deref_mut())
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(())
+}
+
+// Shown to subscribers clicking an outdated link, so they know how to get a new one
+fn expired_token_page(csrf_token: &CsrfToken) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Confirmation link expired</title>
+</head>
+<body>
+    <p>This confirmation link has expired.</p>
+    <p>Enter your email below and we will send you a new one.</p>
+    <form action="/subscriptions/resend" method="post">
+        {}
+        <label>Email
+            <input type="email" placeholder="Enter your email" name="email">
+        </label>
+        <button type="submit">Resend confirmation email</button>
+    </form>
+</body>
+</html>"#,
+        csrf_token.form_field()
+    )
 }
@@ -0,0 +1,105 @@
+use std::ops::DerefMut;
+
+use actix_web::{
+    web::{self, Form},
+    HttpResponse,
+};
+use sqlx::{types::chrono::Utc, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    configurations::SubscriptionSettings,
+    domain::{
+        random_token::UnsubscribeToken,
+        subscriber_email::SubscriberEmail,
+        subscription_status::SubscriptionStatus,
+        validation_error::{FieldError, ValidationError},
+    },
+    email_client::EmailClient,
+    routes::{
+        delete_subscription_tokens, get_confirmation_sent_at, record_confirmation_sent,
+        send_confirmation_email, store_new_subscription_token, SubscribeError,
+    },
+    startup::ApplicationBaseUrl,
+};
+
+#[derive(serde::Deserialize)]
+pub struct ResendFormData {
+    pub email: String,
+}
+
+#[tracing::instrument(
+    name = "Resending a confirmation email",
+    skip(form, db_pool, email_client, base_url, subscription_settings),
+    fields(subscriber_email = %form.email)
+)]
+pub async fn resend_confirmation(
+    form: Form<ResendFormData>,
+    db_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    subscription_settings: web::Data<SubscriptionSettings>,
+) -> Result<HttpResponse, SubscribeError> {
+    let email = SubscriberEmail::parse(form.0.email)
+        .map_err(|err| ValidationError::from(FieldError::new("email", err)))?;
+
+    let mut tx = db_pool.begin().await?;
+
+    // Unknown and already confirmed addresses get the same answer as pending ones,
+    // so the endpoint can't be used to find out who is on the list
+    let (subscriber_id, unsubscribe_token) = match get_pending_subscriber(&mut tx, &email).await? {
+        Some(subscriber) => subscriber,
+        None => return Ok(HttpResponse::Ok().finish()),
+    };
+
+    // Rate limited requests get the same answer too, a 429 would tell the address is pending
+    if let Some(sent_at) = get_confirmation_sent_at(&mut tx, subscriber_id).await? {
+        if sent_at + subscription_settings.resend_interval() > Utc::now() {
+            return Ok(HttpResponse::Ok().finish());
+        }
+    }
+
+    // Only the latest confirmation link should work
+    delete_subscription_tokens(&mut tx, subscriber_id).await?;
+    let subscription_token =
+        store_new_subscription_token(&mut tx, subscriber_id, subscription_settings.token_ttl())
+            .await?;
+    record_confirmation_sent(&mut tx, subscriber_id).await?;
+
+    send_confirmation_email(
+        &email_client,
+        email,
+        &base_url.0,
+        &subscription_token,
+        &unsubscribe_token,
+    )
+    .await
+    .map_err(SubscribeError::EmailDeliveryError)?;
+
+    tx.commit().await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[tracing::instrument(name = "Get pending subscriber by email", skip(tx, email))]
+async fn get_pending_subscriber(
+    tx: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+) -> Result<Option<(Uuid, UnsubscribeToken)>, sqlx::Error> {
+    // Lock the subscriber row, so concurrent resends are serialized and rate limited properly
+    let result = sqlx::query!(
+        r#"SELECT id, unsubscribe_token FROM subscriptions WHERE email = $1 AND status = $2 FOR UPDATE"#,
+        email.as_ref(),
+        SubscriptionStatus::Pending.as_str(),
+    )
+    .fetch_optional(tx.deref_mut())
+    .await?;
+
+    result
+        .map(|r| {
+            let unsubscribe_token = UnsubscribeToken::parse(r.unsubscribe_token)
+                .map_err(|err| sqlx::Error::Decode(err.into()))?;
+            Ok((r.id, unsubscribe_token))
+        })
+        .transpose()
+}
@@ -0,0 +1,205 @@
+use std::ops::DerefMut;
+
+use actix_web::{
+    http::header::ContentType,
+    web::{self, Form},
+    HttpResponse, Responder,
+};
+use sqlx::{types::chrono::Utc, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    csrf::CsrfToken,
+    domain::{random_token::UnsubscribeToken, subscription_status::SubscriptionStatus},
+    email_client::EmailHeader,
+    routes::delete_subscription_tokens,
+};
+
+const UNSUBSCRIBED_PAGE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribed</title>
+</head>
+<body>
+    <p>You have been unsubscribed, you will not receive any more emails from us.</p>
+</body>
+</html>"#;
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribePayload {
+    unsubscribe_token: String,
+}
+
+// Landing page linked from our emails, the actual unsubscription needs an explicit POST
+// so link scanners and prefetchers can't unsubscribe people by accident
+#[tracing::instrument(name = "Show unsubscribe page", skip(parameters, csrf_token, db_pool))]
+pub async fn unsubscribe_page(
+    parameters: web::Query<UnsubscribePayload>,
+    csrf_token: CsrfToken,
+    db_pool: web::Data<PgPool>,
+) -> impl Responder {
+    let unsubscribe_token = match UnsubscribeToken::parse(parameters.0.unsubscribe_token) {
+        Ok(token) => token,
+        Err(_) => return HttpResponse::BadRequest().finish(),
+    };
+
+    let status = match get_status_from_token(&db_pool, &unsubscribe_token).await {
+        Ok(Some(status)) => status,
+        Ok(None) => return HttpResponse::Unauthorized().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    if status == SubscriptionStatus::Unsubscribed {
+        return HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .body(UNSUBSCRIBED_PAGE);
+    }
+
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribe</title>
+</head>
+<body>
+    <p>Do you want to stop receiving our newsletter?</p>
+    <form action="/subscriptions/unsubscribe" method="post">
+        {}
+        <input hidden type="text" name="unsubscribe_token" value="{}">
+        <button type="submit">Unsubscribe</button>
+    </form>
+</body>
+</html>"#,
+            csrf_token.form_field(),
+            unsubscribe_token.as_ref()
+        ))
+}
+
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip(form, db_pool))]
+pub async fn unsubscribe(
+    form: Form<UnsubscribePayload>,
+    db_pool: web::Data<PgPool>,
+) -> impl Responder {
+    let unsubscribe_token = match UnsubscribeToken::parse(form.0.unsubscribe_token) {
+        Ok(token) => token,
+        Err(_) => return HttpResponse::BadRequest().finish(),
+    };
+
+    match unsubscribe_subscriber(&db_pool, &unsubscribe_token).await {
+        Ok(true) => HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .body(UNSUBSCRIBED_PAGE),
+        Ok(false) => HttpResponse::Unauthorized().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+// RFC 8058 one-click unsubscription, called by mailbox providers without any user interaction
+// The body is always `List-Unsubscribe=One-Click`, the token in the URL is all we need
+#[tracing::instrument(name = "One-click unsubscribe a subscriber", skip(parameters, db_pool))]
+pub async fn unsubscribe_one_click(
+    parameters: web::Query<UnsubscribePayload>,
+    db_pool: web::Data<PgPool>,
+) -> impl Responder {
+    let unsubscribe_token = match UnsubscribeToken::parse(parameters.0.unsubscribe_token) {
+        Ok(token) => token,
+        Err(_) => return HttpResponse::BadRequest().finish(),
+    };
+
+    match unsubscribe_subscriber(&db_pool, &unsubscribe_token).await {
+        Ok(true) => HttpResponse::Ok().finish(),
+        Ok(false) => HttpResponse::Unauthorized().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+// Headers letting mailbox providers show their native unsubscribe button
+pub fn list_unsubscribe_headers(
+    base_url: &str,
+    unsubscribe_token: &UnsubscribeToken,
+) -> Vec<EmailHeader> {
+    let one_click_link = format!(
+        "{}/subscriptions/unsubscribe/one-click?unsubscribe_token={}",
+        base_url,
+        unsubscribe_token.as_ref()
+    );
+    vec![
+        EmailHeader::new("List-Unsubscribe", format!("<{}>", one_click_link)),
+        EmailHeader::new("List-Unsubscribe-Post", "List-Unsubscribe=One-Click"),
+    ]
+}
+
+// Return false when no subscriber owns the token
+pub async fn unsubscribe_subscriber(
+    db_pool: &PgPool,
+    unsubscribe_token: &UnsubscribeToken,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = db_pool.begin().await?;
+
+    let subscriber_id = match mark_subscriber_as_unsubscribed(&mut tx, unsubscribe_token).await? {
+        Some(id) => id,
+        None => return Ok(false),
+    };
+    // Pending confirmation links must not bring the subscriber back
+    delete_subscription_tokens(&mut tx, subscriber_id).await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+#[tracing::instrument(
+    name = "Get subscription status from unsubscribe token",
+    skip(db_pool, unsubscribe_token)
+)]
+async fn get_status_from_token(
+    db_pool: &PgPool,
+    unsubscribe_token: &UnsubscribeToken,
+) -> Result<Option<SubscriptionStatus>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"SELECT status FROM subscriptions WHERE unsubscribe_token = $1"#,
+        unsubscribe_token.as_ref(),
+    )
+    .fetch_optional(db_pool)
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    result
+        .map(|r| {
+            SubscriptionStatus::try_from(r.status).map_err(|err| sqlx::Error::Decode(err.into()))
+        })
+        .transpose()
+}
+
+#[tracing::instrument(name = "Mark subscriber as unsubscribed", skip(tx, unsubscribe_token))]
+async fn mark_subscriber_as_unsubscribed(
+    tx: &mut Transaction<'_, Postgres>,
+    unsubscribe_token: &UnsubscribeToken,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    // Keep the original unsubscription time if the subscriber unsubscribes twice
+    let result = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = $1, unsubscribed_at = COALESCE(unsubscribed_at, $2)
+        WHERE unsubscribe_token = $3
+        RETURNING id
+        "#,
+        SubscriptionStatus::Unsubscribed.as_str(),
+        Utc::now(),
+        unsubscribe_token.as_ref(),
+    )
+    .fetch_optional(tx.deref_mut())
+    .await
+    .map_err(|err| {
+        tracing::error!("Failed to execute query: {:?}", err);
+        err
+    })?;
+
+    Ok(result.map(|r| r.id))
+}
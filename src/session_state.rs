@@ -0,0 +1,69 @@
+use std::future::{ready, Ready};
+
+use actix_session::{Session, SessionExt, SessionGetError, SessionInsertError};
+use actix_web::{dev::Payload, FromRequest, HttpRequest};
+use uuid::Uuid;
+
+// Typed wrapper, so the session keys live in one place instead of every handler
+pub struct TypedSession(Session);
+
+// Also read by `PgSessionStore`, to tell which user a session belongs to
+pub const USER_ID_KEY: &str = "user_id";
+
+impl TypedSession {
+    // Passed the password check, still waiting for the second factor
+    const PENDING_USER_ID_KEY: &'static str = "pending_user_id";
+    // Secret being enrolled, only saved on the user once a code has been verified
+    const TOTP_SETUP_SECRET_KEY: &'static str = "totp_setup_secret";
+
+    // Rotate the session key on privilege changes to prevent session fixation
+    pub fn renew(&self) {
+        self.0.renew();
+    }
+
+    pub fn insert_user_id(&self, user_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.insert(USER_ID_KEY, user_id)
+    }
+
+    pub fn get_user_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(USER_ID_KEY)
+    }
+
+    pub fn insert_pending_user_id(&self, user_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::PENDING_USER_ID_KEY, user_id)
+    }
+
+    pub fn get_pending_user_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::PENDING_USER_ID_KEY)
+    }
+
+    pub fn remove_pending_user_id(&self) {
+        self.0.remove(Self::PENDING_USER_ID_KEY);
+    }
+
+    pub fn insert_totp_setup_secret(&self, secret: &str) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::TOTP_SETUP_SECRET_KEY, secret)
+    }
+
+    pub fn get_totp_setup_secret(&self) -> Result<Option<String>, SessionGetError> {
+        self.0.get(Self::TOTP_SETUP_SECRET_KEY)
+    }
+
+    pub fn remove_totp_setup_secret(&self) {
+        self.0.remove(Self::TOTP_SETUP_SECRET_KEY);
+    }
+
+    pub fn log_out(self) {
+        self.0.purge();
+    }
+}
+
+impl FromRequest for TypedSession {
+    // Same error as the `Session` extractor
+    type Error = <Session as FromRequest>::Error;
+    type Future = Ready<Result<TypedSession, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(Ok(TypedSession(req.get_session())))
+    }
+}
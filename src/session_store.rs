@@ -0,0 +1,214 @@
+use std::{collections::HashMap, ops::DerefMut, time::Duration};
+
+use actix_session::storage::{LoadError, SaveError, SessionKey, SessionStore, UpdateError};
+use actix_web::cookie::time;
+use anyhow::Context;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use sqlx::{types::chrono::Utc, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::session_state::USER_ID_KEY;
+
+type SessionState = HashMap<String, String>;
+
+// Long enough to make guessing an existing session key impractical
+const SESSION_KEY_LENGTH: usize = 64;
+
+// Server-side session storage, the cookie only carries the session key
+#[derive(Clone)]
+pub struct PgSessionStore {
+    db_pool: PgPool,
+}
+
+impl PgSessionStore {
+    pub fn new(db_pool: PgPool) -> Self {
+        Self { db_pool }
+    }
+}
+
+impl SessionStore for PgSessionStore {
+    async fn load(&self, session_key: &SessionKey) -> Result<Option<SessionState>, LoadError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT session_state
+            FROM sessions
+            WHERE session_key = $1 AND expires_at > $2
+            "#,
+            session_key.as_ref(),
+            Utc::now(),
+        )
+        .fetch_optional(&self.db_pool)
+        .await
+        .context("Failed to load session state")
+        .map_err(LoadError::Other)?;
+
+        row.map(|r| serde_json::from_value(r.session_state))
+            .transpose()
+            .context("Failed to deserialize session state")
+            .map_err(LoadError::Deserialization)
+    }
+
+    async fn save(
+        &self,
+        session_state: SessionState,
+        ttl: &time::Duration,
+    ) -> Result<SessionKey, SaveError> {
+        let user_id = get_user_id(&session_state);
+        let session_state = serde_json::to_value(session_state)
+            .context("Failed to serialize session state")
+            .map_err(SaveError::Serialization)?;
+        let session_key = generate_session_key();
+
+        sqlx::query!(
+            r#"
+            INSERT INTO sessions (session_key, session_state, expires_at, user_id)
+            VALUES ($1, $2, $3, $4)
+            "#,
+            session_key,
+            session_state,
+            Utc::now() + to_chrono(ttl),
+            user_id,
+        )
+        .execute(&self.db_pool)
+        .await
+        .context("Failed to save session state")
+        .map_err(SaveError::Other)?;
+
+        session_key
+            .try_into()
+            .context("Failed to build session key")
+            .map_err(SaveError::Other)
+    }
+
+    async fn update(
+        &self,
+        session_key: SessionKey,
+        session_state: SessionState,
+        ttl: &time::Duration,
+    ) -> Result<SessionKey, UpdateError> {
+        let serialized_state = serde_json::to_value(&session_state)
+            .context("Failed to serialize session state")
+            .map_err(UpdateError::Serialization)?;
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE sessions
+            SET session_state = $1, expires_at = $2, user_id = $3
+            WHERE session_key = $4 AND expires_at > $5
+            "#,
+            serialized_state,
+            Utc::now() + to_chrono(ttl),
+            get_user_id(&session_state),
+            session_key.as_ref(),
+            Utc::now(),
+        )
+        .execute(&self.db_pool)
+        .await
+        .context("Failed to update session state")
+        .map_err(UpdateError::Other)?;
+
+        if result.rows_affected() > 0 {
+            return Ok(session_key);
+        }
+
+        // The session expired or was ended (e.g. by a password change) since it was loaded,
+        // start over with an empty one, saving the loaded state would revive it
+        self.save(SessionState::new(), ttl)
+            .await
+            .map_err(|err| match err {
+                SaveError::Serialization(err) => UpdateError::Serialization(err),
+                SaveError::Other(err) => UpdateError::Other(err),
+            })
+    }
+
+    async fn update_ttl(
+        &self,
+        session_key: &SessionKey,
+        ttl: &time::Duration,
+    ) -> Result<(), anyhow::Error> {
+        sqlx::query!(
+            r#"UPDATE sessions SET expires_at = $1 WHERE session_key = $2"#,
+            Utc::now() + to_chrono(ttl),
+            session_key.as_ref(),
+        )
+        .execute(&self.db_pool)
+        .await
+        .context("Failed to update session ttl")?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> Result<(), anyhow::Error> {
+        sqlx::query!(
+            r#"DELETE FROM sessions WHERE session_key = $1"#,
+            session_key.as_ref(),
+        )
+        .execute(&self.db_pool)
+        .await
+        .context("Failed to delete session")?;
+
+        Ok(())
+    }
+}
+
+// Expired sessions are already ignored by `load`, this only keeps the table small
+#[tracing::instrument(name = "Delete expired sessions", skip(db_pool))]
+pub async fn delete_expired_sessions(db_pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(r#"DELETE FROM sessions WHERE expires_at <= $1"#, Utc::now())
+        .execute(db_pool)
+        .await
+        .map_err(|err| {
+            tracing::error!("Failed to execute query: {:?}", err);
+            err
+        })?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn run_session_cleanup_until_stopped(
+    db_pool: PgPool,
+    cleanup_interval: Duration,
+) -> Result<(), std::io::Error> {
+    loop {
+        tokio::time::sleep(cleanup_interval).await;
+        // Errors are already logged, the next run will try again
+        let _ = delete_expired_sessions(&db_pool).await;
+    }
+}
+
+// Ends every session of the user, e.g. once their password changed
+// The current request keeps its session by renewing it, which saves it again under a new key
+#[tracing::instrument(name = "Delete user sessions", skip(tx))]
+pub async fn delete_user_sessions(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(r#"DELETE FROM sessions WHERE user_id = $1"#, user_id)
+        .execute(tx.deref_mut())
+        .await
+        .map_err(|err| {
+            tracing::error!("Failed to execute query: {:?}", err);
+            err
+        })?;
+
+    Ok(result.rows_affected())
+}
+
+// Values are stored JSON encoded by actix-session
+fn get_user_id(session_state: &SessionState) -> Option<Uuid> {
+    session_state
+        .get(USER_ID_KEY)
+        .and_then(|user_id| serde_json::from_str(user_id).ok())
+}
+
+fn generate_session_key() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(SESSION_KEY_LENGTH)
+        .collect()
+}
+
+fn to_chrono(ttl: &time::Duration) -> chrono::Duration {
+    chrono::Duration::seconds(ttl.whole_seconds())
+}
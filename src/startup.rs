@@ -1,28 +1,112 @@
-use std::net::TcpListener;
+use std::{net::TcpListener, time::Duration};
 
-use actix_web::{dev::Server, web, App, HttpServer};
+use actix_session::{
+    config::{BrowserSession, TtlExtensionPolicy},
+    SessionMiddleware,
+};
+use actix_web::{
+    cookie::{time, Key},
+    dev::Server,
+    middleware::from_fn,
+    web, App, HttpServer,
+};
 use secrecy::ExposeSecret;
 use sqlx::PgPool;
 use tracing_actix_web::TracingLogger;
 
 use crate::{
-    configurations::Settings,
-    email_client::EmailClient,
-    routes::{confirm_subscription, health_check, subscribe},
+    authentication::{
+        reject_anonymous_users, require_password_change, require_permission,
+        require_two_factor_enrolment,
+    },
+    configurations::{EmailClientSettings, EmailProvider, IdempotencySettings, Settings},
+    csrf::csrf_protection,
+    domain::user_role::Permission,
+    email_client::{
+        EmailClient, EmailProviderClient, FileTransport, InMemoryTransport, JsonHttpTransport,
+        PostmarkTransport, SmtpTransport,
+    },
+    flash_messages::flash_messages,
+    idempotency::run_idempotency_cleanup_until_stopped,
+    issue_delivery_worker::IssueDeliveryWorker,
+    routes::{
+        accept_invite, accept_invite_form, admin_dashboard, api_keys_form,
+        change_password_for_user, change_password_form, change_user_role, confirm_subscription,
+        create_api_key, delete_subscriber, forgot_password, forgot_password_form, health_check,
+        invite_user, list_subscribers_with_api_key, log_out, login, login_form, metrics,
+        publish_newsletter, publish_newsletter_with_api_key, resend_confirmation, reset_password,
+        reset_password_form, revoke_api_key_for_user, subscribe, subscribe_form,
+        subscribe_with_api_key, subscribers_list, two_factor_disable, two_factor_form,
+        two_factor_login, two_factor_setup, two_factor_setup_form, unsubscribe,
+        unsubscribe_one_click, unsubscribe_page, users_form,
+    },
+    session_store::{run_session_cleanup_until_stopped, PgSessionStore},
 };
 
 pub fn build_email_client(config: &Settings) -> EmailClient {
-    let sender = config
-        .email_client
-        .sender()
-        .expect("Invalid sender's email");
-    let timeout = config.email_client.timeout;
-    EmailClient::new(
-        config.email_client.base_url.to_owned(),
-        sender,
-        config.email_client.auth_token.to_owned(),
-        std::time::Duration::from_millis(timeout),
-    )
+    let settings = &config.email_client;
+    let sender = settings.sender().expect("Invalid sender's email");
+    let email_client = settings.fallbacks.iter().fold(
+        EmailClient::with_provider(sender, build_email_provider(settings, settings.provider)),
+        |email_client, &fallback| {
+            email_client.with_fallback(build_email_provider(settings, fallback))
+        },
+    );
+    email_client.with_retry(settings.retry.to_owned())
+}
+
+fn build_email_provider(
+    settings: &EmailClientSettings,
+    provider: EmailProvider,
+) -> EmailProviderClient {
+    let name = provider.as_str();
+    let timeout = settings.timeout();
+    let email_provider = match provider {
+        EmailProvider::Postmark => {
+            let postmark = settings
+                .postmark
+                .to_owned()
+                .expect("Missing the `email_client.postmark` settings");
+            EmailProviderClient::new(name, PostmarkTransport::new(postmark, timeout))
+        }
+        EmailProvider::Smtp => {
+            let smtp = settings
+                .smtp
+                .to_owned()
+                .expect("Missing the `email_client.smtp` settings");
+            let transport = SmtpTransport::new(smtp, timeout).expect("Invalid SMTP settings");
+            EmailProviderClient::new(name, transport)
+        }
+        EmailProvider::JsonHttp => {
+            let json_http = settings
+                .json_http
+                .to_owned()
+                .expect("Missing the `email_client.json_http` settings");
+            EmailProviderClient::new(name, JsonHttpTransport::new(json_http, timeout))
+        }
+        EmailProvider::File => {
+            let file = settings
+                .file
+                .to_owned()
+                .expect("Missing the `email_client.file` settings");
+            EmailProviderClient::new(name, FileTransport::new(file))
+        }
+        EmailProvider::InMemory => {
+            let in_memory = settings
+                .in_memory
+                .to_owned()
+                .expect("Missing the `email_client.in_memory` settings");
+            EmailProviderClient::new(name, InMemoryTransport::named(&in_memory.outbox))
+        }
+    };
+    let limits = settings
+        .limits
+        .get(&provider)
+        .map(|limits| limits.per_process(settings.sending_processes))
+        .unwrap_or_default();
+    email_provider
+        .with_limits(&limits)
+        .with_circuit_breaker(settings.circuit_breaker.to_owned())
 }
 
 pub fn build_connection_pool(config: &Settings) -> PgPool {
@@ -39,6 +123,12 @@ pub fn build_connection_pool(config: &Settings) -> PgPool {
 pub struct Application {
     port: u16,
     server: Server,
+    metrics_port: u16,
+    metrics_server: Server,
+    delivery_worker: Option<IssueDeliveryWorker>,
+    db_pool: PgPool,
+    session_cleanup_interval: Duration,
+    idempotency_settings: IdempotencySettings,
 }
 
 impl Application {
@@ -53,38 +143,209 @@ impl Application {
         );
         let listener = TcpListener::bind(address)?;
         let port = listener.local_addr().unwrap().port();
-        let server = run(listener, db_pool, email_client).await?;
+        // The embedded worker shares the client, and with it the provider limits
+        let server = run(listener, db_pool.clone(), email_client.clone(), config).await?;
+
+        let metrics_listener =
+            TcpListener::bind(format!("{}:{}", config.metrics.host, config.metrics.port))?;
+        let metrics_port = metrics_listener.local_addr().unwrap().port();
+        let metrics_server = run_metrics(metrics_listener, email_client.clone())?;
+
+        let delivery_worker = config
+            .delivery_worker
+            .embedded
+            .then(|| IssueDeliveryWorker::new(db_pool.clone(), email_client, config));
 
-        Ok(Self { port, server })
+        Ok(Self {
+            port,
+            server,
+            metrics_port,
+            metrics_server,
+            delivery_worker,
+            db_pool,
+            session_cleanup_interval: Duration::from_secs(config.session.cleanup_interval),
+            idempotency_settings: config.idempotency.to_owned(),
+        })
     }
 
     pub fn port(&self) -> u16 {
         self.port
     }
 
+    pub fn metrics_port(&self) -> u16 {
+        self.metrics_port
+    }
+
     // Consume self
     pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
-        self.server.await
+        let delivery_worker = async move {
+            match self.delivery_worker {
+                Some(delivery_worker) => delivery_worker.run_until_stopped().await,
+                None => std::future::pending().await,
+            }
+        };
+        let session_cleanup =
+            run_session_cleanup_until_stopped(self.db_pool.clone(), self.session_cleanup_interval);
+        let idempotency_cleanup = run_idempotency_cleanup_until_stopped(
+            self.db_pool,
+            self.idempotency_settings.ttl(),
+            Duration::from_secs(self.idempotency_settings.cleanup_interval),
+        );
+
+        // Stop everything as soon as one of them stops
+        tokio::select! {
+            result = self.server => result,
+            result = self.metrics_server => result,
+            result = delivery_worker => result,
+            result = session_cleanup => result,
+            result = idempotency_cleanup => result,
+        }
     }
 }
 
+// Wrapper type, so the base url can be retrieved from app data without ambiguity
+pub struct ApplicationBaseUrl(pub String);
+
 pub async fn run(
     listener: TcpListener,
     db_pool: PgPool,
     email_client: EmailClient,
+    config: &Settings,
 ) -> Result<Server, std::io::Error> {
+    let session_store = PgSessionStore::new(db_pool.clone());
+
     // Atomic Reference Counted pointer - smart pointer
     let db_pool = web::Data::new(db_pool);
     let email_client = web::Data::new(email_client);
+    let base_url = web::Data::new(ApplicationBaseUrl(config.application.base_url.to_owned()));
+    let subscription_settings = web::Data::new(config.subscriptions.to_owned());
+    let password_reset_settings = web::Data::new(config.password_reset.to_owned());
+    let two_factor_settings = web::Data::new(config.two_factor.to_owned());
+    let invite_settings = web::Data::new(config.invites.to_owned());
+    let idempotency_settings = web::Data::new(config.idempotency.to_owned());
+    let secret_key = Key::from(config.application.hmac_secret.expose_secret().as_bytes());
+    let session_ttl = time::Duration::seconds(config.session.ttl);
 
     let server = HttpServer::new(move || {
         App::new()
+            // Every HTML form gets a CSRF token, checked before anything else runs
+            .wrap(from_fn(flash_messages(secret_key.clone())))
+            .wrap(from_fn(csrf_protection(secret_key.clone())))
+            .wrap(
+                SessionMiddleware::builder(session_store.clone(), secret_key.clone())
+                    // Keep active sessions alive, expire idle ones
+                    .session_lifecycle(
+                        BrowserSession::default()
+                            .state_ttl(session_ttl)
+                            .state_ttl_extension_policy(TtlExtensionPolicy::OnEveryRequest),
+                    )
+                    .build(),
+            )
             .wrap(TracingLogger::default())
             .route("health_check", web::get().to(health_check))
+            .route("subscriptions", web::get().to(subscribe_form))
             .route("subscriptions", web::post().to(subscribe))
             .route("subscriptions/confirm", web::get().to(confirm_subscription))
+            .route("subscriptions/resend", web::post().to(resend_confirmation))
+            .route("subscriptions/unsubscribe", web::get().to(unsubscribe_page))
+            .route("subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .route(
+                "subscriptions/unsubscribe/one-click",
+                web::post().to(unsubscribe_one_click),
+            )
+            .route("login", web::get().to(login_form))
+            .route("login", web::post().to(login))
+            .route("login/2fa", web::get().to(two_factor_form))
+            .route("login/2fa", web::post().to(two_factor_login))
+            .route("password/forgot", web::get().to(forgot_password_form))
+            .route("password/forgot", web::post().to(forgot_password))
+            .route("password/reset", web::get().to(reset_password_form))
+            .route("password/reset", web::post().to(reset_password))
+            .route("invite", web::get().to(accept_invite_form))
+            .route("invite", web::post().to(accept_invite))
+            .service(
+                web::scope("/admin")
+                    // Wrapped in reverse order, the user must be known before checking enrolment
+                    .wrap(from_fn(require_two_factor_enrolment))
+                    .wrap(from_fn(require_password_change))
+                    .wrap(from_fn(reject_anonymous_users))
+                    .route("/dashboard", web::get().to(admin_dashboard))
+                    .route("/password", web::get().to(change_password_form))
+                    .route("/password", web::post().to(change_password_for_user))
+                    .route("/2fa", web::get().to(two_factor_setup_form))
+                    .route("/2fa", web::post().to(two_factor_setup))
+                    .route("/2fa/disable", web::post().to(two_factor_disable))
+                    // Everything below requires a permission granted by the user's role
+                    .service(
+                        web::resource("/newsletters")
+                            .wrap(from_fn(require_permission(Permission::PublishNewsletters)))
+                            .route(web::post().to(publish_newsletter)),
+                    )
+                    .service(
+                        web::resource("/subscribers")
+                            .wrap(from_fn(require_permission(Permission::ViewSubscribers)))
+                            .route(web::get().to(subscribers_list)),
+                    )
+                    .service(
+                        web::resource("/subscribers/{subscriber_id}/delete")
+                            .wrap(from_fn(require_permission(Permission::DeleteSubscribers)))
+                            .route(web::post().to(delete_subscriber)),
+                    )
+                    .service(
+                        web::scope("/api-keys")
+                            .wrap(from_fn(require_permission(Permission::ManageApiKeys)))
+                            .route("", web::get().to(api_keys_form))
+                            .route("", web::post().to(create_api_key))
+                            .route(
+                                "/{api_key_id}/revoke",
+                                web::post().to(revoke_api_key_for_user),
+                            ),
+                    )
+                    .service(
+                        web::scope("/users")
+                            .wrap(from_fn(require_permission(Permission::ManageUsers)))
+                            .route("", web::get().to(users_form))
+                            .route("/invite", web::post().to(invite_user))
+                            .route("/{user_id}/role", web::post().to(change_user_role)),
+                    )
+                    .route("/logout", web::post().to(log_out)),
+            )
+            // Machine clients, authenticated with a Bearer API key instead of a session
+            .service(
+                web::scope("/api")
+                    .route("/subscribers", web::get().to(list_subscribers_with_api_key))
+                    .route("/subscribers", web::post().to(subscribe_with_api_key))
+                    .route(
+                        "/newsletters",
+                        web::post().to(publish_newsletter_with_api_key),
+                    ),
+            )
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
+            .app_data(base_url.clone())
+            .app_data(subscription_settings.clone())
+            .app_data(password_reset_settings.clone())
+            .app_data(two_factor_settings.clone())
+            .app_data(invite_settings.clone())
+            .app_data(idempotency_settings.clone())
+    })
+    .listen(listener)?
+    .run();
+
+    Ok(server)
+}
+
+// Internal listener, scraped from inside the deployment, see `MetricsSettings`
+pub fn run_metrics(
+    listener: TcpListener,
+    email_client: EmailClient,
+) -> Result<Server, std::io::Error> {
+    let email_client = web::Data::new(email_client);
+    let server = HttpServer::new(move || {
+        App::new()
+            .wrap(TracingLogger::default())
+            .route("metrics", web::get().to(metrics))
+            .app_data(email_client.clone())
     })
     .listen(listener)?
     .run();
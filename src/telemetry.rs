@@ -1,3 +1,4 @@
+use tokio::task::JoinHandle;
 use tracing::{subscriber::set_global_default, Subscriber};
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
 use tracing_log::LogTracer;
@@ -29,3 +30,14 @@ pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
     // Set global subscriber
     set_global_default(subscriber).expect("Failed to set subscriber");
 }
+
+// CPU-heavy work (e.g. password hashing) must not block the async executor,
+// run it on the blocking thread pool while staying inside the current span
+pub fn spawn_blocking_with_tracing<F, R>(f: F) -> JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let current_span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || current_span.in_scope(f))
+}
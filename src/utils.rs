@@ -0,0 +1,62 @@
+use std::{future::Future, pin::Pin};
+
+use actix_web::{
+    cookie::{Cookie, CookieJar, Key},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::LOCATION,
+    HttpResponse,
+};
+
+// Future returned by the middleware factories used with `from_fn`, e.g. `require_permission`
+pub type MiddlewareFuture<B> =
+    Pin<Box<dyn Future<Output = Result<ServiceResponse<B>, actix_web::Error>>>>;
+
+// Return an opaque 500 while preserving the error's root cause for logging
+pub fn e500<T>(err: T) -> actix_web::Error
+where
+    T: std::fmt::Debug + std::fmt::Display + 'static,
+{
+    actix_web::error::ErrorInternalServerError(err)
+}
+
+// Debug output listing every cause, so the log of a failed request shows why it failed
+pub fn error_chain_fmt(
+    err: &impl std::error::Error,
+    f: &mut std::fmt::Formatter<'_>,
+) -> std::fmt::Result {
+    writeln!(f, "{}", err)?;
+    let mut current = err.source();
+    while let Some(cause) = current {
+        writeln!(f, "Caused by:\n\t{}", cause)?;
+        current = cause.source();
+    }
+    Ok(())
+}
+
+pub fn see_other(location: &str) -> HttpResponse {
+    HttpResponse::SeeOther()
+        .insert_header((LOCATION, location))
+        .finish()
+}
+
+// Value of a cookie signed with `add_signed_cookie`, None if it is missing or was tampered with
+pub fn get_signed_cookie(req: &ServiceRequest, key: &Key, name: &str) -> Option<String> {
+    let mut jar = CookieJar::new();
+    jar.add_original(req.cookie(name)?);
+    jar.signed(key)
+        .get(name)
+        .map(|cookie| cookie.value().to_string())
+}
+
+pub fn add_signed_cookie<B>(
+    response: &mut ServiceResponse<B>,
+    key: &Key,
+    cookie: Cookie<'static>,
+) -> Result<(), actix_web::Error> {
+    let mut jar = CookieJar::new();
+    jar.signed_mut(key).add(cookie);
+    for cookie in jar.delta() {
+        response.response_mut().add_cookie(cookie).map_err(e500)?;
+    }
+    Ok(())
+}
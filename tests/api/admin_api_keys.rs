@@ -0,0 +1,396 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_server, TestApp, TestUser};
+
+fn newsletter_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+fn subscriber_body() -> serde_json::Value {
+    serde_json::json!({
+        "name": "test",
+        "email": "test@gmail.com",
+    })
+}
+
+async fn get_api_key_id(app: &TestApp, api_key: &str) -> Uuid {
+    let prefix = &api_key[..api_key.rfind('_').unwrap()];
+    sqlx::query!("SELECT api_key_id FROM api_keys WHERE prefix = $1", prefix)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .api_key_id
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_api_keys() {
+    let app = spawn_server().await;
+
+    let response = app
+        .post_create_api_key("cms", &["newsletters:publish"])
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn api_key_is_only_shown_once_and_stored_hashed() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+
+    let api_key = app.create_api_key(&["newsletters:publish"]).await;
+
+    let html = app.get_api_keys_html().await;
+    let prefix = &api_key[..api_key.rfind('_').unwrap()];
+    assert!(html.contains(prefix));
+    assert!(!html.contains(&api_key));
+
+    let saved = sqlx::query!("SELECT key_hash, scopes FROM api_keys")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(!saved.key_hash.contains(&api_key));
+    assert_eq!(saved.scopes, vec!["newsletters:publish"]);
+}
+
+#[tokio::test]
+async fn creating_an_api_key_without_scopes_is_rejected() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+
+    let response = app.post_create_api_key("cms", &[]).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Select at least one scope."));
+}
+
+#[tokio::test]
+async fn creating_an_api_key_with_an_unknown_scope_is_rejected() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_create_api_key("cms", &["subscribers:delete"])
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn api_key_with_publish_scope_can_publish_newsletters() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key(&["newsletters:publish"]).await;
+
+    let response = app.post_api_newsletters(&api_key, newsletter_body()).await;
+
+    assert_eq!(response.status().as_u16(), 202);
+}
+
+#[tokio::test]
+async fn api_key_with_subscribers_scope_can_add_subscribers() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key(&["subscribers:write"]).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_api_subscribers(&api_key, subscriber_body()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "test@gmail.com");
+    assert_eq!(saved.status, "pending");
+}
+
+#[tokio::test]
+async fn api_key_with_subscribers_read_scope_can_list_subscribers_with_their_attributes() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+    let api_key = app
+        .create_api_key(&["subscribers:read", "subscribers:write"])
+        .await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let mut body = subscriber_body();
+    body["attributes"] = serde_json::json!({ "plan": "pro" });
+    app.post_api_subscribers(&api_key, body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let response = app.get_api_subscribers(&api_key).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let subscribers = body["subscribers"].as_array().unwrap();
+    assert_eq!(subscribers.len(), 1);
+    assert_eq!(subscribers[0]["email"], "test@gmail.com");
+    assert_eq!(
+        subscribers[0]["attributes"],
+        serde_json::json!({ "plan": "pro" })
+    );
+}
+
+#[tokio::test]
+async fn api_subscribers_are_listed_page_by_page() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key(&["subscribers:read"]).await;
+    for i in 0..5 {
+        sqlx::query!(
+            r#"
+            INSERT INTO subscriptions (id, email, name, subscribed_at, status, unsubscribe_token)
+            VALUES ($1, $2, 'test', NOW() - make_interval(mins => $3), 'confirmed', $4)
+            "#,
+            Uuid::new_v4(),
+            format!("subscriber{}@gmail.com", i),
+            i,
+            Uuid::new_v4().to_string(),
+        )
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to insert subscriber");
+    }
+
+    let mut emails = Vec::new();
+    let mut cursor: Option<String> = None;
+    let mut n_pages = 0;
+    loop {
+        let mut query = vec![("limit", "2")];
+        if let Some(cursor) = &cursor {
+            query.push(("cursor", cursor));
+        }
+        let response = app.get_api_subscribers_page(&api_key, &query).await;
+        assert_eq!(response.status().as_u16(), 200);
+        let body: serde_json::Value = response.json().await.unwrap();
+        n_pages += 1;
+        for subscriber in body["subscribers"].as_array().unwrap() {
+            emails.push(subscriber["email"].as_str().unwrap().to_string());
+        }
+        match body["next_cursor"].as_str() {
+            Some(next_cursor) => cursor = Some(next_cursor.to_string()),
+            None => break,
+        }
+    }
+
+    // Newest first, each subscriber once
+    assert_eq!(n_pages, 3);
+    let expected: Vec<_> = (0..5)
+        .map(|i| format!("subscriber{}@gmail.com", i))
+        .collect();
+    assert_eq!(emails, expected);
+}
+
+#[tokio::test]
+async fn api_subscribers_400_for_an_invalid_cursor() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key(&["subscribers:read"]).await;
+
+    let response = app
+        .get_api_subscribers_page(&api_key, &[("cursor", "not-a-cursor")])
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn listing_subscribers_requires_the_read_scope() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key(&["subscribers:write"]).await;
+
+    let response = app.get_api_subscribers(&api_key).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn api_key_without_the_required_scope_is_forbidden() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key(&["subscribers:write"]).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_api_newsletters(&api_key, newsletter_body()).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn requests_without_a_valid_api_key_are_rejected() {
+    let app = spawn_server().await;
+    let unknown_key = format!("z2p_{}_{}", "a".repeat(8), "b".repeat(32));
+
+    for api_key in ["", "not-a-key", unknown_key.as_str()] {
+        let response = app.post_api_newsletters(api_key, newsletter_body()).await;
+
+        assert_eq!(response.status().as_u16(), 401);
+        assert_eq!(
+            response.headers()["WWW-Authenticate"],
+            r#"Bearer realm="z2p""#
+        );
+    }
+}
+
+#[tokio::test]
+async fn session_cookies_are_not_accepted_by_the_api() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/api/newsletters", app.address))
+        .json(&newsletter_body())
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn revoked_api_key_is_rejected() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key(&["newsletters:publish"]).await;
+    let api_key_id = get_api_key_id(&app, &api_key).await;
+
+    let response = app.post_revoke_api_key(api_key_id).await;
+    assert_is_redirect_to(&response, "/admin/api-keys");
+
+    let response = app.post_api_newsletters(&api_key, newsletter_body()).await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(app.get_api_keys_html().await.contains("Revoked on"));
+}
+
+#[tokio::test]
+async fn revoking_an_unknown_api_key_returns_404() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+
+    let response = app.post_revoke_api_key(Uuid::new_v4()).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn last_used_timestamp_is_recorded() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key(&["newsletters:publish"]).await;
+
+    let last_used_at = || async {
+        sqlx::query!("SELECT last_used_at FROM api_keys")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .last_used_at
+    };
+    assert!(last_used_at().await.is_none());
+
+    app.post_api_newsletters(&api_key, newsletter_body())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    assert!(last_used_at().await.is_some());
+}
+
+#[tokio::test]
+async fn api_keys_can_not_have_scopes_beyond_the_role_of_their_creator() {
+    let app = spawn_server().await;
+    let editor = TestUser::generate_with_role("editor");
+    editor.store(&app.db_pool).await;
+    editor.login(&app).await;
+
+    let html = app.get_api_keys_html().await;
+    assert!(html.contains(r#"value="newsletters:publish""#));
+    assert!(!html.contains(r#"value="subscribers:write""#));
+
+    let response = app
+        .post_create_api_key("cms", &["newsletters:publish", "subscribers:write"])
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("Your role does not allow the subscribers:write scope."));
+    let saved = sqlx::query!("SELECT api_key_id FROM api_keys")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_empty());
+}
+
+#[tokio::test]
+async fn api_keys_follow_the_current_role_of_their_owner() {
+    let app = spawn_server().await;
+    let editor = TestUser::generate_with_role("editor");
+    editor.store(&app.db_pool).await;
+    editor.login(&app).await;
+    let api_key = app.create_api_key(&["newsletters:publish"]).await;
+
+    sqlx::query!(
+        "UPDATE users SET role = 'viewer' WHERE user_id = $1",
+        editor.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let response = app.post_api_newsletters(&api_key, newsletter_body()).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn demoting_a_user_revokes_the_api_keys_their_new_role_does_not_allow() {
+    let app = spawn_server().await;
+    let editor = TestUser::generate_with_role("editor");
+    editor.store(&app.db_pool).await;
+    editor.login(&app).await;
+    let publish_key = app.create_api_key(&["newsletters:publish"]).await;
+    let read_key = app.create_api_key(&["subscribers:read"]).await;
+
+    app.test_user.login(&app).await;
+    let response = app.post_change_user_role(editor.user_id, "viewer").await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    let response = app
+        .post_api_newsletters(&publish_key, newsletter_body())
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = app.get_api_subscribers(&read_key).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
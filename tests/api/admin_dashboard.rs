@@ -0,0 +1,40 @@
+use crate::helpers::{assert_is_redirect_to, spawn_server};
+
+#[tokio::test]
+async fn admin_dashboard_requires_login() {
+    let app = spawn_server().await;
+
+    let response = app.get_admin_dashboard().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn admin_dashboard_is_not_accessible_after_logout() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
+
+    let response = app.post_logout().await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn logout_message_is_shown_once() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+
+    let response = app.post_logout().await;
+    assert_is_redirect_to(&response, "/login");
+
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("<p><i>You have successfully logged out.</i></p>"));
+
+    let html_page = app.get_login_html().await;
+    assert!(!html_page.contains("You have successfully logged out."));
+}
@@ -0,0 +1,204 @@
+use claims::assert_err;
+use secrecy::ExposeSecret;
+use uuid::Uuid;
+use z2p::authentication::{bootstrap_owner, BootstrapOwnerError};
+
+use crate::helpers::{assert_is_redirect_to, spawn_server};
+
+#[tokio::test]
+async fn admin_password_requires_login_to_see_the_form() {
+    let app = spawn_server().await;
+
+    let response = app.get_change_password().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn admin_password_requires_login_to_change_password() {
+    let app = spawn_server().await;
+    let new_password = Uuid::new_v4().to_string();
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": Uuid::new_v4().to_string(),
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn admin_password_400_when_new_passwords_do_not_match() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": Uuid::new_v4().to_string(),
+            "new_password_check": Uuid::new_v4().to_string(),
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(
+        "<p><i>You entered two different new passwords - the field values must match.</i></p>"
+    ));
+}
+
+#[tokio::test]
+async fn admin_password_400_for_invalid_new_password_length() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+
+    let test_cases = vec![("a".repeat(11), "too short"), ("a".repeat(129), "too long")];
+
+    for (new_password, message) in test_cases {
+        let response = app
+            .post_change_password(&serde_json::json!({
+                "current_password": &app.test_user.password,
+                "new_password": &new_password,
+                "new_password_check": &new_password,
+            }))
+            .await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with 400 status code, case: {}",
+            message
+        );
+    }
+}
+
+#[tokio::test]
+async fn admin_password_401_for_wrong_current_password() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+    let new_password = Uuid::new_v4().to_string();
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": Uuid::new_v4().to_string(),
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<p><i>The current password is incorrect.</i></p>"));
+}
+
+#[tokio::test]
+async fn admin_password_change_works() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+    let new_password = Uuid::new_v4().to_string();
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<p><i>Your password has been changed.</i></p>"));
+
+    app.post_logout().await;
+
+    // The old password no longer works
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn admin_password_change_logs_out_the_other_sessions() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+    let other_client = app.login_from_another_client().await;
+    let new_password = Uuid::new_v4().to_string();
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = other_client
+        .get(format!("{}/admin/dashboard", app.address))
+        .send()
+        .await
+        .expect("Failed to send the request to the server");
+    assert_is_redirect_to(&response, "/login");
+    // The session that changed the password stays logged in
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn bootstrapping_an_owner_is_refused_once_an_account_exists() {
+    let app = spawn_server().await;
+
+    let result = bootstrap_owner(&app.db_pool, "owner", "owner@example.com").await;
+
+    assert!(matches!(
+        assert_err!(result),
+        BootstrapOwnerError::UsersExist
+    ));
+}
+
+#[tokio::test]
+async fn bootstrapped_owner_has_to_change_the_temporary_password_first() {
+    let app = spawn_server().await;
+    sqlx::query!("DELETE FROM users")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let password = bootstrap_owner(&app.db_pool, "owner", "owner@example.com")
+        .await
+        .unwrap();
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "owner",
+            "password": password.expose_secret(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/admin/password");
+
+    let new_password = Uuid::new_v4().to_string();
+    app.post_change_password(&serde_json::json!({
+        "current_password": password.expose_secret(),
+        "new_password": &new_password,
+        "new_password_check": &new_password,
+    }))
+    .await;
+
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+
+use actix_session::storage::SessionStore;
+use actix_web::cookie::time;
+use z2p::{
+    session_state::USER_ID_KEY,
+    session_store::{delete_expired_sessions, PgSessionStore},
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_server, TestApp};
+
+async fn get_session_keys(app: &TestApp) -> Vec<String> {
+    sqlx::query!("SELECT session_key FROM sessions")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase")
+        .into_iter()
+        .map(|r| r.session_key)
+        .collect()
+}
+
+#[tokio::test]
+async fn sessions_are_persisted_in_the_database_on_login() {
+    let app = spawn_server().await;
+
+    app.test_user.login(&app).await;
+
+    let saved_session = sqlx::query!("SELECT session_state FROM sessions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase");
+    assert!(saved_session
+        .session_state
+        .to_string()
+        .contains(&app.test_user.user_id.to_string()));
+}
+
+#[tokio::test]
+async fn sessions_key_is_rotated_on_login() {
+    let app = spawn_server().await;
+
+    app.test_user.login(&app).await;
+    let first_session_keys = get_session_keys(&app).await;
+    assert_eq!(first_session_keys.len(), 1);
+
+    app.test_user.login(&app).await;
+    let second_session_keys = get_session_keys(&app).await;
+    assert_eq!(second_session_keys.len(), 1);
+    assert_ne!(first_session_keys[0], second_session_keys[0]);
+}
+
+#[tokio::test]
+async fn sessions_are_destroyed_on_logout() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+
+    let response = app.post_logout().await;
+    assert_is_redirect_to(&response, "/login");
+
+    assert!(get_session_keys(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn sessions_expired_are_rejected() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+
+    sqlx::query!("UPDATE sessions SET expires_at = NOW() - INTERVAL '1 second'")
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to expire sessions");
+
+    let response = app.get_admin_dashboard().await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn sessions_cleanup_only_deletes_expired_sessions() {
+    let app = spawn_server().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO sessions (session_key, session_state, expires_at)
+        VALUES
+            ('expired', '{}', NOW() - INTERVAL '1 second'),
+            ('active', '{}', NOW() + INTERVAL '1 hour')
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to insert sessions");
+
+    let deleted = delete_expired_sessions(&app.db_pool).await.unwrap();
+
+    assert_eq!(deleted, 1);
+    assert_eq!(get_session_keys(&app).await, vec!["active".to_string()]);
+}
+
+#[tokio::test]
+async fn sessions_ended_during_a_request_are_not_revived() {
+    let app = spawn_server().await;
+    let session_store = PgSessionStore::new(app.db_pool.clone());
+    let ttl = time::Duration::hours(1);
+    let session_state = HashMap::from([(
+        USER_ID_KEY.to_string(),
+        serde_json::to_string(&app.test_user.user_id).unwrap(),
+    )]);
+    let session_key = session_store
+        .save(session_state.clone(), &ttl)
+        .await
+        .unwrap();
+
+    // e.g. a password change on another client, while this request was running
+    sqlx::query!("DELETE FROM sessions")
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to delete sessions");
+    let new_session_key = session_store
+        .update(session_key, session_state, &ttl)
+        .await
+        .unwrap();
+
+    let saved_state = session_store.load(&new_session_key).await.unwrap();
+    assert_eq!(saved_state, Some(HashMap::new()));
+    let saved_session = sqlx::query!("SELECT user_id FROM sessions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase");
+    assert_eq!(saved_session.user_id, None);
+}
@@ -0,0 +1,90 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_server, TestApp, TestUser};
+
+async fn create_subscriber(app: &TestApp) -> Uuid {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=test&email=test@gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+async fn login_with_role(app: &TestApp, role: &str) {
+    let user = TestUser::generate_with_role(role);
+    user.store(&app.db_pool).await;
+    user.login(app).await;
+}
+
+#[tokio::test]
+async fn viewers_can_list_subscribers_without_delete_buttons() {
+    let app = spawn_server().await;
+    create_subscriber(&app).await;
+    login_with_role(&app, "viewer").await;
+
+    let response = app.get_admin_subscribers().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("test@gmail.com"));
+    assert!(!html.contains("Delete"));
+}
+
+#[tokio::test]
+async fn owners_can_delete_subscribers() {
+    let app = spawn_server().await;
+    let subscriber_id = create_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let response = app.post_delete_subscriber(subscriber_id).await;
+
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let saved = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_none());
+    let html = app.get_admin_subscribers().await.text().await.unwrap();
+    assert!(html.contains("<p><i>The subscriber has been deleted.</i></p>"));
+}
+
+#[tokio::test]
+async fn editors_can_not_delete_subscribers() {
+    let app = spawn_server().await;
+    let subscriber_id = create_subscriber(&app).await;
+    login_with_role(&app, "editor").await;
+
+    let response = app.post_delete_subscriber(subscriber_id).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+    let saved = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_some());
+}
+
+#[tokio::test]
+async fn deleting_an_unknown_subscriber_returns_404() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+
+    let response = app.post_delete_subscriber(Uuid::new_v4()).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
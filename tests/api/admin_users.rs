@@ -0,0 +1,269 @@
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_server, spawn_server_with, TestApp, TestUser};
+
+fn newsletter_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+// Invite an address as the logged in user and return the token from the invite email
+async fn invite(app: &TestApp, email: &str, role: &str) -> String {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Invite user")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_invite_user(email, role)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let links = app.get_confirmation_links(email_request);
+    assert_eq!(links.html.path(), "/invite");
+
+    links
+        .html
+        .query_pairs()
+        .find(|(key, _)| key == "token")
+        .map(|(_, value)| value.into_owned())
+        .unwrap()
+}
+
+fn accept_invite_body(token: &str, username: &str, password: &str) -> serde_json::Value {
+    serde_json::json!({
+        "token": token,
+        "username": username,
+        "password": password,
+        "password_check": password,
+    })
+}
+
+#[tokio::test]
+async fn viewers_can_not_publish_newsletters() {
+    let app = spawn_server().await;
+    let viewer = TestUser::generate_with_role("viewer");
+    viewer.store(&app.db_pool).await;
+    viewer.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(newsletter_body()).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+    let issues = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(issues.is_empty());
+}
+
+#[tokio::test]
+async fn editors_can_publish_newsletters() {
+    let app = spawn_server().await;
+    let editor = TestUser::generate_with_role("editor");
+    editor.store(&app.db_pool).await;
+    editor.login(&app).await;
+
+    let response = app.post_newsletters(newsletter_body()).await;
+
+    assert_eq!(response.status().as_u16(), 202);
+}
+
+#[tokio::test]
+async fn editors_can_not_manage_users() {
+    let app = spawn_server().await;
+    let editor = TestUser::generate_with_role("editor");
+    editor.store(&app.db_pool).await;
+    editor.login(&app).await;
+
+    let response = app.post_invite_user("someone@example.com", "owner").await;
+
+    assert_eq!(response.status().as_u16(), 403);
+    let dashboard = app.get_admin_dashboard_html().await;
+    assert!(dashboard.contains("You are signed in as editor."));
+    assert!(!dashboard.contains(r#"href="/admin/users""#));
+}
+
+#[tokio::test]
+async fn role_changes_apply_to_existing_sessions() {
+    let app = spawn_server().await;
+    let editor = TestUser::generate_with_role("editor");
+    editor.store(&app.db_pool).await;
+
+    app.test_user.login(&app).await;
+    let response = app.post_change_user_role(editor.user_id, "viewer").await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    editor.login(&app).await;
+    let response = app.post_newsletters(newsletter_body()).await;
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn owners_can_not_change_their_own_role() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_change_user_role(app.test_user.user_id, "viewer")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let role = sqlx::query!(
+        "SELECT role FROM users WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .role;
+    assert_eq!(role, "owner");
+}
+
+#[tokio::test]
+async fn invited_user_can_create_an_account_with_the_invited_role() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+
+    let token = invite(&app, "new-editor@example.com", "editor").await;
+    assert!(app
+        .get_users_html()
+        .await
+        .contains("new-editor@example.com"));
+
+    let response = reqwest::get(format!("{}/invite?token={}", app.address, token))
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_accept_invite(&accept_invite_body(
+            &token,
+            "new-editor",
+            "a-long-enough-password",
+        ))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let saved = sqlx::query!("SELECT email, role FROM users WHERE username = 'new-editor'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email.as_deref(), Some("new-editor@example.com"));
+    assert_eq!(saved.role, "editor");
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "new-editor",
+            "password": "a-long-enough-password",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn invite_links_are_single_use() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+    let token = invite(&app, "new-viewer@example.com", "viewer").await;
+
+    app.post_accept_invite(&accept_invite_body(
+        &token,
+        "new-viewer",
+        "a-long-enough-password",
+    ))
+    .await;
+    let response = app
+        .post_accept_invite(&accept_invite_body(
+            &token,
+            "another-viewer",
+            "a-long-enough-password",
+        ))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn expired_invite_links_are_rejected() {
+    let app = spawn_server_with(|config| config.invites.token_ttl = -1).await;
+    app.test_user.login(&app).await;
+    let token = invite(&app, "new-viewer@example.com", "viewer").await;
+
+    let response = reqwest::get(format!("{}/invite?token={}", app.address, token))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn accepting_an_invite_with_a_taken_username_is_rejected() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+    let token = invite(&app, "new-viewer@example.com", "viewer").await;
+
+    let response = app
+        .post_accept_invite(&accept_invite_body(
+            &token,
+            &app.test_user.username,
+            "a-long-enough-password",
+        ))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("This username is already taken."));
+}
+
+#[tokio::test]
+async fn inviting_an_existing_account_is_rejected() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_invite_user(&app.test_user.email, "viewer").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn inviting_with_an_invalid_role_is_rejected() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+
+    let response = app.post_invite_user("someone@example.com", "admin").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
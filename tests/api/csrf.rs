@@ -0,0 +1,109 @@
+use crate::helpers::{assert_is_redirect_to, extract_csrf_token, spawn_server};
+
+fn login_body(username: &str, password: &str) -> [(&'static str, String); 2] {
+    [
+        ("username", username.to_string()),
+        ("password", password.to_string()),
+    ]
+}
+
+#[tokio::test]
+async fn form_posts_without_a_csrf_token_are_rejected() {
+    let app = spawn_server().await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/login", app.address))
+        .form(&login_body(
+            &app.test_user.username,
+            &app.test_user.password,
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 403);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("This form has expired"));
+}
+
+#[tokio::test]
+async fn form_posts_with_a_wrong_csrf_token_are_rejected() {
+    let app = spawn_server().await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/login", app.address))
+        .header("X-CSRF-Token", "a".repeat(app.csrf_token.len()))
+        .form(&login_body(
+            &app.test_user.username,
+            &app.test_user.password,
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn csrf_token_is_accepted_from_the_hidden_form_field() {
+    let app = spawn_server().await;
+
+    let html_page = app.get_login_html().await;
+    let csrf_token = extract_csrf_token(&html_page);
+    let mut body = login_body(&app.test_user.username, &app.test_user.password).to_vec();
+    body.push(("csrf_token", csrf_token));
+
+    let response = app
+        .api_client
+        .post(format!("{}/login", app.address))
+        .form(&body)
+        .send()
+        .await
+        .unwrap();
+
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+// A third-party page can't attach our cookie to a token it got hold of
+#[tokio::test]
+async fn csrf_token_without_the_matching_cookie_is_rejected() {
+    let app = spawn_server().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions", app.address))
+        .header("X-CSRF-Token", &app.csrf_token)
+        .form(&[("name", "le guin"), ("email", "ursula_le_guin@gmail.com")])
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 403);
+    let saved = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_none());
+}
+
+#[tokio::test]
+async fn subscription_form_embeds_the_csrf_token() {
+    let app = spawn_server().await;
+
+    let html_page = app
+        .api_client
+        .get(format!("{}/subscriptions", app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    assert!(html_page.contains(r#"<form action="/subscriptions" method="post">"#));
+    assert_eq!(extract_csrf_token(&html_page), app.csrf_token);
+}
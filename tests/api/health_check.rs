@@ -1,21 +1,105 @@
 use reqwest::Client;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use z2p::{
+    configurations::{CircuitBreakerSettings, EmailProvider, InMemorySettings},
+    email_client::InMemoryTransport,
+};
 
-use crate::helpers::spawn_server;
+use crate::helpers::{spawn_server, spawn_server_with, TestApp};
+
+async fn get_health(app: &TestApp) -> serde_json::Value {
+    let response = Client::new()
+        .get(format!("{}/health_check", app.address))
+        .send()
+        .await
+        .expect("Failed to send the request to server");
+
+    assert!(response.status().is_success());
+    response.json().await.unwrap()
+}
+
+fn open_after_one_failure() -> Option<CircuitBreakerSettings> {
+    Some(CircuitBreakerSettings {
+        failure_threshold: 1,
+        open_duration: 60_000,
+        success_threshold: 1,
+    })
+}
 
 #[tokio::test]
 async fn health_check_works_reqwest() {
     // Ignore warning, tokio manage the server in a different thread
-    let config = spawn_server().await;
-    let server_address = config.0;
+    let app = spawn_server().await;
 
-    let test_client = Client::new();
+    let health = get_health(&app).await;
 
-    let response = test_client
-        .get(format!("{}/health_check", server_address))
-        .send()
-        .await
-        .expect("Failed to send the request to server");
+    assert_eq!(health["status"], "ok");
+    assert_eq!(health["email_providers"][0]["provider"], "postmark");
+    assert_eq!(health["email_providers"][0]["circuit"], "closed");
+}
 
-    assert!(response.status().is_success());
-    assert_eq!(response.content_length(), Some(0));
+#[tokio::test]
+async fn health_check_reports_an_open_circuit_after_provider_failures() {
+    let app = spawn_server_with(|config| {
+        config.email_client.circuit_breaker = open_after_one_failure();
+    })
+    .await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(503))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=ursula&email=ursula%40example.com".into())
+        .await;
+    // Refused right away, the provider isn't called again
+    let response = app
+        .post_subscriptions("name=octavia&email=octavia%40example.com".into())
+        .await;
+    assert_eq!(response.status().as_u16(), 500);
+
+    let health = get_health(&app).await;
+    assert_eq!(health["status"], "degraded");
+    assert_eq!(health["email_providers"][0]["circuit"], "open");
+}
+
+#[tokio::test]
+async fn emails_fail_over_to_the_fallback_while_the_circuit_is_open() {
+    let outbox = uuid::Uuid::new_v4().to_string();
+    let app = spawn_server_with(|config| {
+        config.email_client.circuit_breaker = open_after_one_failure();
+        config.email_client.fallbacks = vec![EmailProvider::InMemory];
+        config.email_client.in_memory = Some(InMemorySettings {
+            outbox: outbox.clone(),
+        });
+    })
+    .await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(503))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=ursula&email=ursula%40example.com".into())
+        .await;
+    let response = app
+        .post_subscriptions("name=octavia&email=octavia%40example.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let outbox = InMemoryTransport::named(&outbox);
+    assert_eq!(outbox.sent_to("octavia@example.com").len(), 1);
+    assert!(outbox.sent_to("ursula@example.com").is_empty());
+
+    let health = get_health(&app).await;
+    assert_eq!(health["email_providers"][0]["circuit"], "open");
+    assert_eq!(health["email_providers"][1]["provider"], "in_memory");
+    assert_eq!(health["email_providers"][1]["circuit"], "closed");
 }
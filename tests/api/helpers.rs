@@ -1,7 +1,14 @@
 use once_cell::sync::Lazy;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use totp_rs::TOTP;
 use uuid::Uuid;
+use wiremock::MockServer;
 use z2p::{
-    configurations::{read_configuration, Settings},
+    authentication::{build_totp, compute_password_hash, generate_totp_secret},
+    configurations::{read_configuration, EmailProvider, PostmarkSettings, Settings},
+    email_client::Email,
+    issue_delivery_worker::{ExecutionOutcome, IssueDeliveryWorker},
     startup::Application,
     telemetry::{gen_subscriber, init_subscriber},
 };
@@ -20,12 +27,682 @@ static TRACING: Lazy<()> = Lazy::new(|| {
     }
 });
 
+pub struct TestApp {
+    pub address: String,
+    pub port: u16,
+    // Internal listener serving `/metrics`
+    pub metrics_address: String,
+    pub config: Settings,
+    pub db_pool: PgPool,
+    pub email_server: MockServer,
+    pub delivery_worker: IssueDeliveryWorker,
+    pub test_user: TestUser,
+    // Keeps the session cookie between requests and does not follow redirects
+    pub api_client: reqwest::Client,
+    // Matches the CSRF cookie held by `api_client`
+    pub csrf_token: String,
+}
+
+pub struct TestUser {
+    pub user_id: Uuid,
+    pub username: String,
+    pub password: String,
+    pub email: String,
+    pub role: String,
+}
+
+impl TestUser {
+    // Owners have every permission, use `generate_with_role` to test the others
+    pub fn generate() -> Self {
+        Self::generate_with_role("owner")
+    }
+
+    pub fn generate_with_role(role: &str) -> Self {
+        Self {
+            user_id: Uuid::new_v4(),
+            username: Uuid::new_v4().to_string(),
+            password: Uuid::new_v4().to_string(),
+            email: format!("{}@example.com", Uuid::new_v4().simple()),
+            role: role.to_string(),
+        }
+    }
+
+    pub async fn login(&self, app: &TestApp) {
+        app.post_login(&serde_json::json!({
+            "username": &self.username,
+            "password": &self.password,
+        }))
+        .await;
+    }
+
+    // Enrol a second factor directly in the database, returns the matching generator
+    pub async fn enable_two_factor(&self, app: &TestApp) -> TOTP {
+        let secret = generate_totp_secret();
+        sqlx::query!(
+            "UPDATE users SET totp_secret = $1 WHERE user_id = $2",
+            secret,
+            self.user_id,
+        )
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to enable two-factor authentication");
+
+        build_totp(&secret, "z2p", &self.username).unwrap()
+    }
+
+    pub async fn store(&self, db_pool: &PgPool) {
+        let password_hash = compute_password_hash(Secret::new(self.password.clone())).unwrap();
+        sqlx::query!(
+            "INSERT INTO users (user_id, username, password_hash, email, role) VALUES ($1, $2, $3, $4, $5)",
+            self.user_id,
+            self.username,
+            password_hash.expose_secret(),
+            self.email,
+            self.role,
+        )
+        .execute(db_pool)
+        .await
+        .expect("Failed to store test user");
+    }
+}
+
+// Links sent inside a confirmation email
+pub struct ConfirmationLinks {
+    pub html: reqwest::Url,
+    pub plain_text: reqwest::Url,
+}
+
+// RFC 8058 headers attached to an outgoing email
+pub struct ListUnsubscribeHeaders {
+    pub link: reqwest::Url,
+    pub post: String,
+}
+
+impl TestApp {
+    // Tests drive the delivery worker themselves, instead of waiting for it to poll
+    pub async fn dispatch_all_pending_emails(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue = self
+                .delivery_worker
+                .try_execute_task()
+                .await
+                .expect("Failed to execute issue delivery task")
+            {
+                break;
+            }
+        }
+    }
+
+    pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions", self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .header("X-CSRF-Token", &self.csrf_token)
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    // JSON bodies are sent by scripts, which are not subject to CSRF checks
+    pub async fn post_subscriptions_json(&self, body: serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions", self.address))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    // Same as an HTML form submitted by a browser
+    pub async fn post_subscriptions_from_browser(&self, body: String) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions", self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("Accept", "text/html,application/xhtml+xml,*/*;q=0.8")
+            .header("X-CSRF-Token", &self.csrf_token)
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    pub async fn get_subscribe_form_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/subscriptions", self.address))
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_resend_confirmation(&self, body: String) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions/resend", self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .header("X-CSRF-Token", &self.csrf_token)
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    pub async fn post_subscriptions_with_idempotency_key(
+        &self,
+        body: String,
+        idempotency_key: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions", self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("Idempotency-Key", idempotency_key)
+            .body(body)
+            .header("X-CSRF-Token", &self.csrf_token)
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    pub async fn post_newsletters_with_idempotency_key(
+        &self,
+        body: serde_json::Value,
+        idempotency_key: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/newsletters", self.address))
+            .header("Idempotency-Key", idempotency_key)
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    pub async fn post_newsletters(&self, body: serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/newsletters", self.address))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/login", self.address))
+            .form(body)
+            .header("X-CSRF-Token", &self.csrf_token)
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    // Another browser logged in as the test user, with its own session
+    pub async fn login_from_another_client(&self) -> reqwest::Client {
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .cookie_store(true)
+            .build()
+            .unwrap();
+        let login_html = client
+            .get(format!("{}/login", self.address))
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+            .text()
+            .await
+            .unwrap();
+        let response = client
+            .post(format!("{}/login", self.address))
+            .form(&serde_json::json!({
+                "username": &self.test_user.username,
+                "password": &self.test_user.password,
+            }))
+            .header("X-CSRF-Token", extract_csrf_token(&login_html))
+            .send()
+            .await
+            .expect("Failed to send the request to the server");
+        assert_is_redirect_to(&response, "/admin/dashboard");
+        client
+    }
+
+    pub async fn get_login_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/login", self.address))
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_admin_dashboard(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/dashboard", self.address))
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    pub async fn get_admin_dashboard_html(&self) -> String {
+        self.get_admin_dashboard().await.text().await.unwrap()
+    }
+
+    pub async fn get_change_password(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/password", self.address))
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    pub async fn post_change_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/password", self.address))
+            .form(body)
+            .header("X-CSRF-Token", &self.csrf_token)
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    pub async fn post_forgot_password(&self, body: String) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/password/forgot", self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .header("X-CSRF-Token", &self.csrf_token)
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    pub async fn post_reset_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/password/reset", self.address))
+            .form(body)
+            .header("X-CSRF-Token", &self.csrf_token)
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    pub async fn post_login_two_factor(&self, code: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/login/2fa", self.address))
+            .form(&serde_json::json!({ "code": code }))
+            .header("X-CSRF-Token", &self.csrf_token)
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    pub async fn get_two_factor_setup_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/2fa", self.address))
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_two_factor_setup(&self, code: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/2fa", self.address))
+            .form(&serde_json::json!({ "code": code }))
+            .header("X-CSRF-Token", &self.csrf_token)
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    pub async fn post_two_factor_disable(&self, current_password: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/2fa/disable", self.address))
+            .form(&serde_json::json!({ "current_password": current_password }))
+            .header("X-CSRF-Token", &self.csrf_token)
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    pub async fn get_api_keys_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/api-keys", self.address))
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_create_api_key(&self, name: &str, scopes: &[&str]) -> reqwest::Response {
+        let mut form = vec![("name", name)];
+        form.extend(scopes.iter().map(|scope| ("scopes", *scope)));
+        self.api_client
+            .post(format!("{}/admin/api-keys", self.address))
+            .form(&form)
+            .header("X-CSRF-Token", &self.csrf_token)
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    // Create a key through the admin, the user must be logged in
+    pub async fn create_api_key(&self, scopes: &[&str]) -> String {
+        let html = self
+            .post_create_api_key("test", scopes)
+            .await
+            .error_for_status()
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        let start = html.find(r#"<code id="api-key">"#).unwrap() + r#"<code id="api-key">"#.len();
+        let end = start + html[start..].find("</code>").unwrap();
+        html[start..end].to_string()
+    }
+
+    pub async fn post_revoke_api_key(&self, api_key_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/api-keys/{}/revoke",
+                self.address, api_key_id
+            ))
+            .header("X-CSRF-Token", &self.csrf_token)
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    pub async fn post_api_newsletters(
+        &self,
+        api_key: &str,
+        body: serde_json::Value,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/api/newsletters", self.address))
+            .bearer_auth(api_key)
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    pub async fn post_api_subscribers(
+        &self,
+        api_key: &str,
+        body: serde_json::Value,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/api/subscribers", self.address))
+            .bearer_auth(api_key)
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    pub async fn post_api_subscribers_with_idempotency_key(
+        &self,
+        api_key: &str,
+        body: serde_json::Value,
+        idempotency_key: &str,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/api/subscribers", self.address))
+            .bearer_auth(api_key)
+            .header("Idempotency-Key", idempotency_key)
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    pub async fn post_api_newsletters_with_idempotency_key(
+        &self,
+        api_key: &str,
+        body: serde_json::Value,
+        idempotency_key: &str,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/api/newsletters", self.address))
+            .bearer_auth(api_key)
+            .header("Idempotency-Key", idempotency_key)
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    pub async fn get_metrics_text(&self) -> String {
+        self.api_client
+            .get(format!("{}/metrics", &self.metrics_address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_api_subscribers_page(
+        &self,
+        api_key: &str,
+        query: &[(&str, &str)],
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/api/subscribers", self.address))
+            .query(query)
+            .bearer_auth(api_key)
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    pub async fn get_api_subscribers(&self, api_key: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/api/subscribers", self.address))
+            .bearer_auth(api_key)
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    pub async fn get_users_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/users", self.address))
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_invite_user(&self, email: &str, role: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/users/invite", self.address))
+            .form(&serde_json::json!({ "email": email, "role": role }))
+            .header("X-CSRF-Token", &self.csrf_token)
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    pub async fn post_change_user_role(&self, user_id: Uuid, role: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/users/{}/role", self.address, user_id))
+            .form(&serde_json::json!({ "role": role }))
+            .header("X-CSRF-Token", &self.csrf_token)
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    pub async fn post_accept_invite<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/invite", self.address))
+            .form(body)
+            .header("X-CSRF-Token", &self.csrf_token)
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    pub async fn get_admin_subscribers(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers", self.address))
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    pub async fn post_delete_subscriber(&self, subscriber_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/subscribers/{}/delete",
+                self.address, subscriber_id
+            ))
+            .header("X-CSRF-Token", &self.csrf_token)
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    pub async fn post_logout(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/logout", self.address))
+            .header("X-CSRF-Token", &self.csrf_token)
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    pub async fn post_unsubscribe(&self, body: String) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions/unsubscribe", self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .header("X-CSRF-Token", &self.csrf_token)
+            .send()
+            .await
+            .expect("Failed to send the request to the server")
+    }
+
+    // Some emails are sent after answering, wait until the mock email server got `count` requests
+    pub async fn wait_for_email_requests(&self, count: usize) -> Vec<wiremock::Request> {
+        for _ in 0..100 {
+            let email_requests = self.email_server.received_requests().await.unwrap();
+            if email_requests.len() >= count {
+                return email_requests;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        panic!("The mock email server did not receive {} requests", count);
+    }
+
+    /**
+     * Extract the confirmation links from a request intercepted by the mock email server
+     */
+    pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        self.get_links_from_bodies(
+            body["HtmlBody"].as_str().unwrap(),
+            body["TextBody"].as_str().unwrap(),
+        )
+    }
+
+    // Same as `get_confirmation_links`, for an email kept by the in-memory transport
+    pub fn get_confirmation_links_from_outbox(&self, email: &Email) -> ConfirmationLinks {
+        self.get_links_from_bodies(&email.html_body, &email.text_body)
+    }
+
+    fn get_links_from_bodies(&self, html_body: &str, text_body: &str) -> ConfirmationLinks {
+        let get_link = |s: &str| {
+            let links: Vec<_> = linkify::LinkFinder::new()
+                .links(s)
+                .filter(|l| *l.kind() == linkify::LinkKind::Url)
+                .collect();
+            assert_eq!(links.len(), 1);
+
+            let mut confirmation_link = reqwest::Url::parse(links[0].as_str()).unwrap();
+            // Make sure we don't call random APIs on the web
+            assert_eq!(confirmation_link.host_str().unwrap(), "127.0.0.1");
+            // The link points to the configured port, rewrite it to the test server one
+            confirmation_link.set_port(Some(self.port)).unwrap();
+            confirmation_link
+        };
+
+        let html = get_link(html_body);
+        let plain_text = get_link(text_body);
+        ConfirmationLinks { html, plain_text }
+    }
+
+    /**
+     * Extract the List-Unsubscribe headers from a request intercepted by the mock email server
+     */
+    pub fn get_list_unsubscribe_headers(
+        &self,
+        email_request: &wiremock::Request,
+    ) -> ListUnsubscribeHeaders {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let get_header = |name: &str| {
+            body["Headers"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|h| h["Name"] == name)
+                .and_then(|h| h["Value"].as_str())
+                .unwrap()
+                .to_string()
+        };
+
+        // The link is wrapped in angle brackets, e.g. `<https://...>`
+        let link = get_header("List-Unsubscribe");
+        let mut link =
+            reqwest::Url::parse(link.trim_start_matches('<').trim_end_matches('>')).unwrap();
+        assert_eq!(link.host_str().unwrap(), "127.0.0.1");
+        link.set_port(Some(self.port)).unwrap();
+
+        ListUnsubscribeHeaders {
+            link,
+            post: get_header("List-Unsubscribe-Post"),
+        }
+    }
+}
+
 /**
 * Function to spawn server (at the start of each tests)
 */
-pub async fn spawn_server() -> (String, Settings) {
+pub async fn spawn_server() -> TestApp {
+    spawn_server_with(|_| {}).await
+}
+
+// Same as `spawn_server`, with test specific configurations on top
+pub async fn spawn_server_with(customize: impl FnOnce(&mut Settings)) -> TestApp {
     Lazy::force(&TRACING);
 
+    // Mock email API, so no real email is sent during tests
+    let email_server = MockServer::start().await;
+
     let configurations = {
         let mut config = read_configuration().expect("Failed to read configurations");
 
@@ -34,7 +711,23 @@ pub async fn spawn_server() -> (String, Settings) {
 
         // Mock random OS port
         config.application.port = 0;
+        config.metrics.port = 0;
 
+        // Mock email API
+        config.email_client.provider = EmailProvider::Postmark;
+        config.email_client.postmark = Some(PostmarkSettings {
+            base_url: email_server.uri(),
+            auth_token: Secret::new("super-secret-value".into()),
+        });
+        // Failures are checked on the first attempt, tests opt into retries
+        config.email_client.retry = None;
+        // Tests sending many failing emails would otherwise trip it, tests opt into it
+        config.email_client.circuit_breaker = None;
+
+        // Issue delivery is triggered explicitly by tests
+        config.delivery_worker.embedded = false;
+
+        customize(&mut config);
         config
     };
 
@@ -44,9 +737,56 @@ pub async fn spawn_server() -> (String, Settings) {
         .await
         .expect("Failed to build application");
 
-    let address = format!("http://127.0.0.1:{}", application.port());
+    let delivery_worker = IssueDeliveryWorker::build(&configurations);
+
+    let port = application.port();
+    let address = format!("http://127.0.0.1:{}", port);
+    let metrics_address = format!("http://127.0.0.1:{}", application.metrics_port());
 
     tokio::spawn(application.run_until_stopped());
 
-    (address, configurations)
+    let api_client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
+    // Any HTML page hands out the CSRF cookie, along with the token to send back
+    let login_html = api_client
+        .get(format!("{}/login", address))
+        .send()
+        .await
+        .expect("Failed to send the request to the server")
+        .text()
+        .await
+        .unwrap();
+    let csrf_token = extract_csrf_token(&login_html);
+
+    let test_app = TestApp {
+        address,
+        port,
+        metrics_address,
+        db_pool: configurations.database.pg_connection_pool(),
+        config: configurations,
+        email_server,
+        delivery_worker,
+        test_user: TestUser::generate(),
+        api_client,
+        csrf_token,
+    };
+    test_app.test_user.store(&test_app.db_pool).await;
+
+    test_app
+}
+
+// Value of the hidden CSRF field of the first form of the page
+pub fn extract_csrf_token(html: &str) -> String {
+    let marker = r#"name="csrf_token" value=""#;
+    let start = html.find(marker).expect("No CSRF field in the page") + marker.len();
+    let end = start + html[start..].find('"').unwrap();
+    html[start..end].to_string()
+}
+
+pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers().get("Location").unwrap(), location);
 }
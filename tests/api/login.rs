@@ -0,0 +1,72 @@
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, spawn_server};
+
+#[tokio::test]
+async fn login_form_is_served() {
+    let app = spawn_server().await;
+
+    let html_page = app.get_login_html().await;
+
+    assert!(html_page.contains(r#"<form action="/login" method="post">"#));
+}
+
+#[tokio::test]
+async fn login_401_with_an_error_message_for_wrong_password() {
+    let app = spawn_server().await;
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": Uuid::new_v4().to_string(),
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<p><i>Authentication failed.</i></p>"));
+}
+
+#[tokio::test]
+async fn login_401_for_unknown_username() {
+    let app = spawn_server().await;
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": Uuid::new_v4().to_string(),
+            "password": &app.test_user.password,
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn login_redirects_to_admin_dashboard_after_success() {
+    let app = spawn_server().await;
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn login_there_is_no_default_admin_account() {
+    let app = spawn_server().await;
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "admin",
+            "password": "everythinghastostartsomewhere",
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
@@ -1,3 +1,18 @@
+mod admin_api_keys;
+mod admin_dashboard;
+mod admin_password;
+mod admin_sessions;
+mod admin_subscribers;
+mod admin_users;
+mod csrf;
 mod health_check;
 mod helpers;
+mod login;
+mod metrics;
+mod newsletters;
+mod password_reset;
 mod subscriptions;
+mod subscriptions_confirm;
+mod subscriptions_resend;
+mod subscriptions_unsubscribe;
+mod two_factor;
@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use z2p::configurations::{EmailLimitSettings, EmailProvider};
+
+use crate::helpers::{spawn_server, spawn_server_with};
+
+#[tokio::test]
+async fn metrics_are_not_served_on_the_public_listener() {
+    let app = spawn_server().await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/metrics", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn metrics_report_no_permit_wait_before_any_email() {
+    let app = spawn_server().await;
+
+    let body = app.get_metrics_text().await;
+
+    assert!(body.contains("# TYPE email_permit_wait_seconds summary"));
+    assert!(body.contains("email_permit_wait_seconds_count{provider=\"postmark\"} 0"));
+}
+
+#[tokio::test]
+async fn emails_beyond_the_provider_rate_wait_and_the_wait_is_reported() {
+    let app = spawn_server_with(|config| {
+        config.email_client.limits = HashMap::from([(
+            EmailProvider::Postmark,
+            EmailLimitSettings {
+                rate_per_second: Some(10.0),
+                burst: Some(1),
+                max_in_flight: Some(1),
+            },
+        )]);
+    })
+    .await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+
+    for name in ["ursula", "octavia", "toni"] {
+        let response = app
+            .post_subscriptions(format!("name={name}&email={name}%40example.com"))
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    let body = app.get_metrics_text().await;
+    assert!(body.contains("email_permit_wait_seconds_count{provider=\"postmark\"} 3"));
+    // Two of the three waited about 100ms for a token
+    let sum: f64 = body
+        .lines()
+        .find_map(|line| line.strip_prefix("email_permit_wait_seconds_sum{provider=\"postmark\"} "))
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(sum >= 0.1, "waited {sum}s");
+}
@@ -0,0 +1,522 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use z2p::{configurations::EmailRetrySettings, idempotency::delete_expired_idempotency_keys};
+
+use crate::helpers::{
+    assert_is_redirect_to, spawn_server, spawn_server_with, ConfirmationLinks, TestApp,
+};
+
+async fn create_pending_subscriber(app: &TestApp) -> ConfirmationLinks {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create pending subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=test&email=test@gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(email_request)
+}
+
+async fn create_confirmed_subscriber(app: &TestApp) {
+    let confirmation_links = create_pending_subscriber(app).await;
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+fn newsletter_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    })
+}
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_pending_subscribers() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+    create_pending_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(newsletter_body()).await;
+
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn newsletters_are_delivered_to_confirmed_subscribers() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(newsletter_body()).await;
+
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+
+    // Every issue carries its recipient's unsubscribe link
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let headers = app.get_list_unsubscribe_headers(&email_request);
+    assert_eq!(headers.link.path(), "/subscriptions/unsubscribe/one-click");
+}
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_unsubscribed_subscribers() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to unsubscribe subscriber");
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(newsletter_body()).await;
+
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn newsletters_skip_confirmed_subscribers_with_invalid_stored_email() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+
+    // Simulate a row written before email validation existed
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, name, email, subscribed_at, status, unsubscribe_token)
+        VALUES ($1, 'invalid', 'definately-not-a-valid-email', NOW(), 'confirmed', $2)
+        "#,
+        Uuid::new_v4(),
+        "b".repeat(32),
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to insert invalid subscriber");
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(newsletter_body()).await;
+
+    assert_eq!(response.status().as_u16(), 202);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn newsletters_400_for_invalid_body() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+
+    let test_cases = vec![
+        (
+            serde_json::json!({
+                "content": {
+                    "text": "Newsletter body as plain text",
+                    "html": "<p>Newsletter body as HTML</p>",
+                }
+            }),
+            "missing title",
+        ),
+        (
+            serde_json::json!({ "title": "Newsletter title" }),
+            "missing content",
+        ),
+        (
+            serde_json::json!({
+                "title": "Newsletter title",
+                "content": { "text": "Newsletter body as plain text" }
+            }),
+            "missing html content",
+        ),
+    ];
+
+    for (invalid_body, message) in test_cases {
+        let response = app.post_newsletters(invalid_body).await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with 400 status code, case: {}",
+            message
+        );
+    }
+}
+
+#[tokio::test]
+async fn newsletters_are_persisted_before_delivery() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(newsletter_body()).await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    let saved_issue = sqlx::query!("SELECT title FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase");
+    assert_eq!(saved_issue.title, "Newsletter title");
+
+    let queued_tasks = sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase");
+    assert_eq!(queued_tasks.len(), 1);
+    assert_eq!(queued_tasks[0].subscriber_email, "test@gmail.com");
+}
+
+#[tokio::test]
+async fn newsletters_delivery_is_retried_later_on_transient_failure() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(newsletter_body()).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Still queued, scheduled for later
+    let queued_task = sqlx::query!(
+        "SELECT n_retries, execute_after > NOW() AS \"is_delayed!\" FROM issue_delivery_queue"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to query from the datadabase");
+    assert_eq!(queued_task.n_retries, 1);
+    assert!(queued_task.is_delayed);
+
+    let failures = sqlx::query!("SELECT error FROM issue_delivery_failures")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase");
+    assert!(failures.is_empty());
+}
+
+#[tokio::test]
+async fn newsletters_delivery_leaves_retries_to_the_queue() {
+    // Retries of the email client are for requests waiting on an email, not for the worker
+    let app = spawn_server_with(|config| {
+        config.email_client.retry = Some(EmailRetrySettings {
+            max_attempts: 3,
+            base_delay: 10,
+            max_delay: 50,
+            deadline: 5000,
+        })
+    })
+    .await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(newsletter_body()).await;
+    app.dispatch_all_pending_emails().await;
+
+    let queued_task = sqlx::query!("SELECT n_retries FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase");
+    assert_eq!(queued_task.n_retries, 1);
+}
+
+#[tokio::test]
+async fn newsletters_delivery_failure_is_recorded_after_max_retries() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(newsletter_body()).await;
+
+    // Pretend every retry has already been used up
+    sqlx::query!(
+        "UPDATE issue_delivery_queue SET n_retries = $1",
+        app.config.delivery_worker.max_retries
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to update issue delivery queue");
+
+    app.dispatch_all_pending_emails().await;
+
+    let queued_tasks = sqlx::query!("SELECT n_retries FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase");
+    assert!(queued_tasks.is_empty());
+
+    let failure = sqlx::query!("SELECT subscriber_email FROM issue_delivery_failures")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase");
+    assert_eq!(failure.subscriber_email, "test@gmail.com");
+}
+
+#[tokio::test]
+async fn newsletters_delivery_failure_is_recorded_without_retry_on_permanent_failure() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(422))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_newsletters(newsletter_body()).await;
+    app.dispatch_all_pending_emails().await;
+
+    let queued_tasks = sqlx::query!("SELECT n_retries FROM issue_delivery_queue")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase");
+    assert!(queued_tasks.is_empty());
+
+    let failure = sqlx::query!("SELECT n_retries FROM issue_delivery_failures")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase");
+    assert_eq!(failure.n_retries, 0);
+}
+
+#[tokio::test]
+async fn newsletters_publishing_is_idempotent() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    let response = app
+        .post_newsletters_with_idempotency_key(newsletter_body(), &idempotency_key)
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    // Retry the same request
+    let response = app
+        .post_newsletters_with_idempotency_key(newsletter_body(), &idempotency_key)
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    app.dispatch_all_pending_emails().await;
+
+    let saved_issues = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase");
+    assert_eq!(saved_issues.len(), 1);
+}
+
+#[tokio::test]
+async fn newsletters_concurrent_publishing_is_handled_gracefully() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    // Submit two requests with the same key at the same time
+    let (first_response, second_response) = tokio::join!(
+        app.post_newsletters_with_idempotency_key(newsletter_body(), &idempotency_key),
+        app.post_newsletters_with_idempotency_key(newsletter_body(), &idempotency_key),
+    );
+
+    assert_eq!(first_response.status(), second_response.status());
+    assert_eq!(
+        first_response.text().await.unwrap(),
+        second_response.text().await.unwrap()
+    );
+
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn newsletters_422_when_the_idempotency_key_is_reused_for_another_issue() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+
+    let idempotency_key = Uuid::new_v4().to_string();
+    let response = app
+        .post_newsletters_with_idempotency_key(newsletter_body(), &idempotency_key)
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    let mut other_body = newsletter_body();
+    other_body["title"] = "Another title".into();
+    let response = app
+        .post_newsletters_with_idempotency_key(other_body, &idempotency_key)
+        .await;
+
+    assert_eq!(response.status().as_u16(), 422);
+    let saved_issues = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase");
+    assert_eq!(saved_issues.len(), 1);
+}
+
+#[tokio::test]
+async fn newsletters_422_when_the_idempotency_key_is_reused_on_another_endpoint() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key(&["newsletters:publish"]).await;
+
+    let idempotency_key = Uuid::new_v4().to_string();
+    let response = app
+        .post_newsletters_with_idempotency_key(newsletter_body(), &idempotency_key)
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    let response = app
+        .post_api_newsletters_with_idempotency_key(&api_key, newsletter_body(), &idempotency_key)
+        .await;
+
+    assert_eq!(response.status().as_u16(), 422);
+}
+
+#[tokio::test]
+async fn newsletters_expired_idempotency_keys_are_processed_again() {
+    let app = spawn_server_with(|config| config.idempotency.ttl = 0).await;
+    app.test_user.login(&app).await;
+
+    let idempotency_key = Uuid::new_v4().to_string();
+    for _ in 0..2 {
+        let response = app
+            .post_newsletters_with_idempotency_key(newsletter_body(), &idempotency_key)
+            .await;
+        assert_eq!(response.status().as_u16(), 202);
+    }
+
+    let saved_issues = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase");
+    assert_eq!(saved_issues.len(), 2);
+    // The cleanup removes the key of the second request
+    let n_deleted = delete_expired_idempotency_keys(&app.db_pool, app.config.idempotency.ttl())
+        .await
+        .unwrap();
+    assert_eq!(n_deleted, 1);
+}
+
+#[tokio::test]
+async fn newsletters_400_for_invalid_idempotency_key() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_newsletters_with_idempotency_key(newsletter_body(), &"a".repeat(51))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn newsletters_redirect_anonymous_users_to_login() {
+    let app = spawn_server().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_newsletters(newsletter_body()).await;
+
+    assert_is_redirect_to(&response, "/login");
+    app.dispatch_all_pending_emails().await;
+}
@@ -0,0 +1,302 @@
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_server, spawn_server_with, TestApp};
+
+// Request a reset link for the test user and return the token it contains
+async fn request_reset_token(app: &TestApp) -> String {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Request password reset")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    let sent_before = app.email_server.received_requests().await.unwrap().len();
+    app.post_forgot_password(format!("email={}", app.test_user.email))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app
+        .wait_for_email_requests(sent_before + 1)
+        .await
+        .pop()
+        .unwrap();
+    let links = app.get_confirmation_links(email_request);
+    assert_eq!(links.html.path(), "/password/reset");
+
+    links
+        .html
+        .query_pairs()
+        .find(|(key, _)| key == "token")
+        .map(|(_, value)| value.into_owned())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn password_reset_sends_a_reset_link_to_known_users() {
+    let app = spawn_server().await;
+
+    let reset_token = request_reset_token(&app).await;
+
+    // Only a hash of the token is stored
+    let saved = sqlx::query!("SELECT token_hash, user_id FROM password_reset_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase");
+    assert_eq!(saved.user_id, app.test_user.user_id);
+    assert_ne!(saved.token_hash, reset_token);
+    assert_eq!(
+        saved.token_hash,
+        format!("{:x}", Sha256::digest(reset_token.as_bytes()))
+    );
+}
+
+#[tokio::test]
+async fn password_reset_does_not_send_an_email_to_unknown_addresses() {
+    let app = spawn_server().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_forgot_password("email=nobody%40example.com".into())
+        .await;
+
+    // Same answer as for known addresses
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn password_reset_200_and_no_saved_link_if_email_fails() {
+    let app = spawn_server().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_forgot_password(format!("email={}", app.test_user.email))
+        .await;
+
+    // Same answer as for unknown addresses, a failed delivery must not tell the account exists
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("a password reset link has been sent"));
+
+    // The link is removed once the delivery fails
+    app.wait_for_email_requests(1).await;
+    for _ in 0..100 {
+        let saved = sqlx::query!("SELECT token_hash FROM password_reset_tokens")
+            .fetch_all(&app.db_pool)
+            .await
+            .expect("Failed to query from the datadabase");
+        if saved.is_empty() {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    panic!("The reset link of the failed email was not removed");
+}
+
+#[tokio::test]
+async fn password_reset_answers_known_addresses_without_waiting_for_the_email() {
+    let app = spawn_server().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(30)))
+        .mount(&app.email_server)
+        .await;
+
+    let started_at = std::time::Instant::now();
+    let response = app
+        .post_forgot_password(format!("email={}", app.test_user.email))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(started_at.elapsed() < std::time::Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn password_reset_link_shows_the_reset_form() {
+    let app = spawn_server().await;
+    let reset_token = request_reset_token(&app).await;
+
+    let response = app
+        .api_client
+        .get(format!(
+            "{}/password/reset?token={}",
+            app.address, reset_token
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(&reset_token));
+}
+
+#[tokio::test]
+async fn password_reset_400_for_malformed_token() {
+    let app = spawn_server().await;
+    let new_password = Uuid::new_v4().to_string();
+
+    let response = app
+        .post_reset_password(&serde_json::json!({
+            "token": "not-a-token",
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn password_reset_changes_the_password() {
+    let app = spawn_server().await;
+    let reset_token = request_reset_token(&app).await;
+    let new_password = Uuid::new_v4().to_string();
+
+    let response = app
+        .post_reset_password(&serde_json::json!({
+            "token": &reset_token,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn password_reset_link_is_single_use() {
+    let app = spawn_server().await;
+    let reset_token = request_reset_token(&app).await;
+    let new_password = Uuid::new_v4().to_string();
+    let body = serde_json::json!({
+        "token": &reset_token,
+        "new_password": &new_password,
+        "new_password_check": &new_password,
+    });
+
+    let response = app.post_reset_password(&body).await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app.post_reset_password(&body).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn password_reset_401_for_expired_link() {
+    let app = spawn_server().await;
+    let reset_token = request_reset_token(&app).await;
+    let new_password = Uuid::new_v4().to_string();
+
+    sqlx::query!("UPDATE password_reset_tokens SET expires_at = NOW() - INTERVAL '1 second'")
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to expire reset tokens");
+
+    let response = app
+        .post_reset_password(&serde_json::json!({
+            "token": &reset_token,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn password_reset_only_latest_link_is_valid() {
+    let app = spawn_server_with(|config| config.password_reset.resend_interval = 0).await;
+    let first_token = request_reset_token(&app).await;
+    let second_token = request_reset_token(&app).await;
+    let new_password = Uuid::new_v4().to_string();
+
+    let response = app
+        .post_reset_password(&serde_json::json!({
+            "token": &first_token,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .post_reset_password(&serde_json::json!({
+            "token": &second_token,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn password_reset_emails_are_rate_limited_per_address() {
+    let app = spawn_server().await;
+    request_reset_token(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_forgot_password(format!("email={}", app.test_user.email))
+        .await;
+
+    // Same answer as when the link is sent
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("a password reset link has been sent"));
+}
+
+#[tokio::test]
+async fn password_reset_logs_out_every_session() {
+    let app = spawn_server().await;
+    let other_client = app.login_from_another_client().await;
+    let reset_token = request_reset_token(&app).await;
+    let new_password = Uuid::new_v4().to_string();
+
+    let response = app
+        .post_reset_password(&serde_json::json!({
+            "token": &reset_token,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = other_client
+        .get(format!("{}/admin/dashboard", app.address))
+        .send()
+        .await
+        .expect("Failed to send the request to the server");
+    assert_is_redirect_to(&response, "/login");
+}
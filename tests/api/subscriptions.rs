@@ -1,47 +1,44 @@
-use reqwest::Client;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use z2p::configurations::EmailRetrySettings;
 
-use crate::helpers::spawn_server;
+use crate::helpers::{assert_is_redirect_to, spawn_server, spawn_server_with};
 
 #[tokio::test]
 async fn subscribe_200_for_valid_form() {
-    let config = spawn_server().await;
-    let server_address = config.0;
-    let db_config = config.1;
-    let db_pool = db_config.database.pg_connection_pool();
+    let app = spawn_server().await;
 
-    let test_client = Client::new();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
 
     let test_name = "test";
     let test_email = "test@gmail.com";
 
     let body = format!("name={}&email={}", test_name, test_email);
-    let response = test_client
-        .post(format!("{}/subscriptions", server_address))
-        .header("Content-Type", "application/x-www-form-urlencoded")
-        .body(body)
-        .send()
-        .await
-        .expect("Failed to send the request to the server");
+    let response = app.post_subscriptions(body).await;
 
     // Check server response
     assert_eq!(response.status().as_u16(), 200);
 
     // Check database data
-    let saved_subscription = sqlx::query!("SELECT name, email FROM subscriptions")
-        .fetch_one(&db_pool)
+    let saved_subscription = sqlx::query!("SELECT name, email, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
         .await
         .expect("Failed to query from the datadabase");
 
     assert_eq!(saved_subscription.name, test_name);
     assert_eq!(saved_subscription.email, test_email);
+    assert_eq!(saved_subscription.status, "pending");
 }
 
 #[tokio::test]
 async fn subscribe_400_for_invalid_form() {
-    let config = spawn_server().await;
-    let server_address = config.0;
-
-    let test_client = Client::new();
+    let app = spawn_server().await;
 
     let test_cases = vec![
         ("name=test", "missing email case"),
@@ -50,13 +47,7 @@ async fn subscribe_400_for_invalid_form() {
     ];
 
     for (invalid_body, message) in test_cases {
-        let response = test_client
-            .post(format!("{}/subscriptions", server_address))
-            .header("Content-Type", "application/x-www-form-urlencoded")
-            .body(invalid_body)
-            .send()
-            .await
-            .expect("Failed to send the request to the server");
+        let response = app.post_subscriptions(invalid_body.into()).await;
 
         assert_eq!(
             response.status().as_u16(),
@@ -69,10 +60,7 @@ async fn subscribe_400_for_invalid_form() {
 
 #[tokio::test]
 async fn subscribe_400_for_invalid_payload() {
-    let config = spawn_server().await;
-    let server_address = config.0;
-
-    let test_client = Client::new();
+    let app = spawn_server().await;
 
     let test_cases = vec![
         ("name=&email=example@example.com", "empty name"),
@@ -84,13 +72,7 @@ async fn subscribe_400_for_invalid_payload() {
     ];
 
     for (invalid_body, message) in test_cases {
-        let response = test_client
-            .post(format!("{}/subscriptions", server_address))
-            .header("Content-Type", "application/x-www-form-urlencoded")
-            .body(invalid_body)
-            .send()
-            .await
-            .expect("Failed to send the request to the server");
+        let response = app.post_subscriptions(invalid_body.into()).await;
 
         assert_eq!(
             response.status().as_u16(),
@@ -100,3 +82,684 @@ async fn subscribe_400_for_invalid_payload() {
         );
     }
 }
+
+#[tokio::test]
+async fn subscribe_400_reports_every_invalid_field_as_problem_json() {
+    let app = spawn_server().await;
+
+    let response = app
+        .post_subscriptions("name=<script>&email=definitely-not-an-email".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        response.headers().get("Content-Type").unwrap(),
+        "application/problem+json"
+    );
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["type"], "/problems/validation-error");
+    assert_eq!(problem["status"], 400);
+    assert_eq!(
+        problem["errors"],
+        serde_json::json!([
+            { "field": "name", "message": "contains forbidden character '<'" },
+            { "field": "email", "message": "is not a valid email address" },
+        ])
+    );
+}
+
+#[tokio::test]
+async fn subscribe_sends_a_confirmation_email_for_valid_form() {
+    let app = spawn_server().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let body = "name=test&email=test@gmail.com".to_string();
+    app.post_subscriptions(body).await;
+
+    // Mock asserts on drop
+}
+
+#[tokio::test]
+async fn subscribe_sends_a_confirmation_email_with_a_link() {
+    let app = spawn_server().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let body = "name=test&email=test@gmail.com".to_string();
+    app.post_subscriptions(body).await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    // The two links should be identical
+    assert_eq!(confirmation_links.html, confirmation_links.plain_text);
+    assert_eq!(confirmation_links.html.path(), "/subscriptions/confirm");
+}
+
+#[tokio::test]
+async fn subscribe_500_and_nothing_saved_if_email_fails() {
+    let app = spawn_server().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let body = "name=test&email=test@gmail.com".to_string();
+    let response = app.post_subscriptions(body).await;
+
+    assert_eq!(response.status().as_u16(), 500);
+    // The cause is only logged, never sent to the client
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["type"], "about:blank");
+    assert_eq!(
+        problem["title"],
+        "Something went wrong on our side, please try again later."
+    );
+    assert!(problem.get("detail").is_none());
+
+    let saved_subscription = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_optional(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase");
+
+    assert!(saved_subscription.is_none());
+}
+
+#[tokio::test]
+async fn subscribe_twice_while_pending_resends_the_same_link() {
+    let app = spawn_server_with(|config| config.subscriptions.resend_interval = 0).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let body = "name=test&email=test@gmail.com".to_string();
+    let first_response = app.post_subscriptions(body.clone()).await;
+    let second_response = app.post_subscriptions(body).await;
+
+    assert_eq!(first_response.status().as_u16(), 200);
+    assert_eq!(second_response.status().as_u16(), 200);
+
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let first_links = app.get_confirmation_links(&email_requests[0]);
+    let second_links = app.get_confirmation_links(&email_requests[1]);
+    assert_eq!(first_links.html, second_links.html);
+
+    let saved_subscriptions = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase");
+
+    assert_eq!(saved_subscriptions.len(), 1);
+    assert_eq!(saved_subscriptions[0].status, "pending");
+}
+
+#[tokio::test]
+async fn subscribe_twice_while_pending_sends_a_fresh_link_if_expired() {
+    let app = spawn_server_with(|config| config.subscriptions.resend_interval = 0).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let body = "name=test&email=test@gmail.com".to_string();
+    app.post_subscriptions(body.clone()).await;
+
+    sqlx::query!("UPDATE subscription_tokens SET expires_at = NOW() - INTERVAL '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to expire subscription token");
+
+    let response = app.post_subscriptions(body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let first_links = app.get_confirmation_links(&email_requests[0]);
+    let second_links = app.get_confirmation_links(&email_requests[1]);
+    assert_ne!(first_links.html, second_links.html);
+
+    let response = reqwest::get(second_links.html)
+        .await
+        .expect("Failed to send the request to the server");
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn subscribe_twice_while_pending_within_the_resend_interval_sends_a_single_email() {
+    let app = spawn_server().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let body = "name=test&email=test@gmail.com".to_string();
+    let first_response = app.post_subscriptions(body.clone()).await;
+    let second_response = app.post_subscriptions(body).await;
+
+    // Same answer, so it doesn't tell that the address is pending
+    assert_eq!(first_response.status().as_u16(), 200);
+    assert_eq!(second_response.status().as_u16(), 200);
+    assert_eq!(
+        first_response.text().await.unwrap(),
+        second_response.text().await.unwrap()
+    );
+}
+
+#[tokio::test]
+async fn subscribe_while_pending_after_the_resend_interval_sends_one_more_email() {
+    let app = spawn_server().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let body = "name=test&email=test@gmail.com".to_string();
+    app.post_subscriptions(body.clone()).await;
+
+    // The first link is still valid, only the send time moves past the interval
+    sqlx::query!("UPDATE subscriptions SET confirmation_sent_at = NOW() - INTERVAL '1 hour'")
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to age the confirmation email");
+
+    // The resent link counts as a new send, so the next requests are rate limited again
+    for _ in 0..3 {
+        let response = app.post_subscriptions(body.clone()).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+}
+
+#[tokio::test]
+async fn concurrent_first_subscriptions_for_the_same_email_are_handled_gracefully() {
+    let app = spawn_server().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let body = "name=test&email=test@gmail.com".to_string();
+    let (first_response, second_response) = tokio::join!(
+        app.post_subscriptions(body.clone()),
+        app.post_subscriptions(body),
+    );
+
+    assert_eq!(first_response.status().as_u16(), 200);
+    assert_eq!(second_response.status().as_u16(), 200);
+    let saved_subscriptions = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase");
+    assert_eq!(saved_subscriptions.len(), 1);
+}
+
+#[tokio::test]
+async fn subscribe_200_without_email_for_confirmed_subscriber() {
+    let app = spawn_server().await;
+
+    let body = "name=test&email=test@gmail.com".to_string();
+    let first_body = {
+        let _mock_guard = Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount_as_scoped(&app.email_server)
+            .await;
+
+        let first_body = app
+            .post_subscriptions(body.clone())
+            .await
+            .text()
+            .await
+            .unwrap();
+
+        let email_request = &app.email_server.received_requests().await.unwrap()[0];
+        let confirmation_links = app.get_confirmation_links(email_request);
+        reqwest::get(confirmation_links.html)
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+        first_body
+    };
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions(body).await;
+
+    // Same answer as a brand new subscription
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.text().await.unwrap(), first_body);
+
+    let saved_subscription = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase");
+
+    assert_eq!(saved_subscription.status, "confirmed");
+}
+
+#[tokio::test]
+async fn subscribe_moves_unsubscribed_subscriber_back_to_pending() {
+    let app = spawn_server().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let body = "name=test&email=test@gmail.com".to_string();
+    app.post_subscriptions(body).await;
+
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to unsubscribe subscriber");
+
+    let response = app
+        .post_subscriptions("name=new%20name&email=test@gmail.com".into())
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let saved_subscription = sqlx::query!("SELECT name, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase");
+
+    assert_eq!(saved_subscription.name, "new name");
+    assert_eq!(saved_subscription.status, "pending");
+
+    // Only the newly sent link confirms the subscription
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let old_links = app.get_confirmation_links(&email_requests[0]);
+    let new_links = app.get_confirmation_links(&email_requests[1]);
+
+    let response = reqwest::get(old_links.html)
+        .await
+        .expect("Failed to send the request to the server");
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = reqwest::get(new_links.html)
+        .await
+        .expect("Failed to send the request to the server");
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+fn api_subscriber_body(email: &str) -> serde_json::Value {
+    serde_json::json!({ "name": "test", "email": email })
+}
+
+#[tokio::test]
+async fn subscribe_is_idempotent() {
+    // Without the rate limit, so only the key can stop a second confirmation email
+    let app = spawn_server_with(|config| config.subscriptions.resend_interval = 0).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let body = "name=test&email=test@gmail.com".to_string();
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+
+    let first_response = app
+        .post_subscriptions_with_idempotency_key(body.clone(), &idempotency_key)
+        .await;
+    // Without the key, this retry would send a second confirmation email
+    let second_response = app
+        .post_subscriptions_with_idempotency_key(body, &idempotency_key)
+        .await;
+
+    assert_eq!(first_response.status().as_u16(), 200);
+    assert_eq!(second_response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn subscribe_with_api_key_is_idempotent() {
+    let app = spawn_server_with(|config| config.subscriptions.resend_interval = 0).await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key(&["subscribers:write"]).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let body = api_subscriber_body("test@gmail.com");
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+
+    let first_response = app
+        .post_api_subscribers_with_idempotency_key(&api_key, body.clone(), &idempotency_key)
+        .await;
+    // Without the key, this retry would send a second confirmation email
+    let second_response = app
+        .post_api_subscribers_with_idempotency_key(&api_key, body, &idempotency_key)
+        .await;
+
+    assert_eq!(first_response.status().as_u16(), 200);
+    assert_eq!(second_response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn subscribe_retry_is_processed_if_first_attempt_failed() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key(&["subscribers:write"]).await;
+
+    let body = api_subscriber_body("test@gmail.com");
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+
+    {
+        let _mock_guard = Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount_as_scoped(&app.email_server)
+            .await;
+
+        let response = app
+            .post_api_subscribers_with_idempotency_key(&api_key, body.clone(), &idempotency_key)
+            .await;
+        assert_eq!(response.status().as_u16(), 500);
+    }
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Failures are not saved, the retry goes through
+    let response = app
+        .post_api_subscribers_with_idempotency_key(&api_key, body, &idempotency_key)
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn subscribe_422_when_the_idempotency_key_is_reused_for_another_payload() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+    let api_key = app.create_api_key(&["subscribers:write"]).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    app.post_api_subscribers_with_idempotency_key(
+        &api_key,
+        api_subscriber_body("first@gmail.com"),
+        &idempotency_key,
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let response = app
+        .post_api_subscribers_with_idempotency_key(
+            &api_key,
+            api_subscriber_body("second@gmail.com"),
+            &idempotency_key,
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 422);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["type"], "/problems/idempotency-key-reused");
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+}
+
+#[tokio::test]
+async fn subscribe_422_when_an_anonymous_idempotency_key_is_reused_for_another_payload() {
+    let app = spawn_server().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Anonymous callers share one key namespace, a key only replays the answer to the same form
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    let response = app
+        .post_subscriptions_with_idempotency_key(
+            "name=first&email=first@gmail.com".into(),
+            &idempotency_key,
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_subscriptions_with_idempotency_key(
+            "name=second&email=second@gmail.com".into(),
+            &idempotency_key,
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 422);
+
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+}
+
+#[tokio::test]
+async fn subscribe_accepts_json_bodies() {
+    let app = spawn_server().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions_json(serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["message"]
+        .as_str()
+        .unwrap()
+        .contains("Please check your inbox"));
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn subscribe_validates_json_bodies_like_forms() {
+    let app = spawn_server().await;
+
+    let response = app
+        .post_subscriptions_json(serde_json::json!({
+            "name": "le guin",
+            "email": "definitely-not-an-email",
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["errors"][0]["field"], "email");
+}
+
+#[tokio::test]
+async fn subscribe_stores_attributes_from_json_bodies() {
+    let app = spawn_server().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions_json(serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "attributes": { "company": "Acme", "country": "US" },
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT attributes FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        saved.attributes,
+        serde_json::json!({ "company": "Acme", "country": "US" })
+    );
+}
+
+#[tokio::test]
+async fn subscribe_rejects_attributes_outside_the_schema() {
+    let app = spawn_server().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({ "favourite_color": "blue" }),
+            "unknown attribute",
+        ),
+        (serde_json::json!({ "company": 42 }), "wrong type"),
+    ];
+
+    for (attributes, description) in test_cases {
+        let response = app
+            .post_subscriptions_json(serde_json::json!({
+                "name": "le guin",
+                "email": "ursula_le_guin@gmail.com",
+                "attributes": attributes,
+            }))
+            .await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with 400 Bad Request when the payload had an {}.",
+            description
+        );
+        let problem: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(problem["errors"][0]["field"], "attributes");
+    }
+}
+
+#[tokio::test]
+async fn browsers_are_redirected_to_the_form_with_a_success_message() {
+    let app = spawn_server().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions_from_browser("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    assert_is_redirect_to(&response, "/subscriptions");
+    let html_page = app.get_subscribe_form_html().await;
+    assert!(html_page.contains("Thanks for subscribing!"));
+}
+
+#[tokio::test]
+async fn browsers_see_field_errors_on_the_form() {
+    let app = spawn_server().await;
+
+    let response = app
+        .post_subscriptions_from_browser("name=<script>&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    assert_is_redirect_to(&response, "/subscriptions");
+    let html_page = app.get_subscribe_form_html().await;
+    assert!(html_page.contains("<p><i>name: contains forbidden character &#x27;&lt;&#x27;</i></p>"));
+}
+
+#[tokio::test]
+async fn subscribe_retries_transient_email_failures() {
+    let app = spawn_server_with(|config| {
+        config.email_client.retry = Some(EmailRetrySettings {
+            max_attempts: 3,
+            base_delay: 10,
+            max_delay: 50,
+            deadline: 5000,
+        })
+    })
+    .await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
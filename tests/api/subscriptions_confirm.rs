@@ -0,0 +1,261 @@
+use reqwest::Client;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+use z2p::{
+    configurations::{EmailProvider, InMemorySettings},
+    email_client::InMemoryTransport,
+};
+
+use crate::helpers::{spawn_server, spawn_server_with};
+
+#[tokio::test]
+async fn confirm_400_for_missing_token() {
+    let app = spawn_server().await;
+
+    let response = Client::new()
+        .get(format!("{}/subscriptions/confirm", app.address))
+        .send()
+        .await
+        .expect("Failed to send the request to the server");
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn confirm_400_for_malformed_token() {
+    let app = spawn_server().await;
+
+    let test_client = Client::new();
+
+    let test_cases = vec![
+        ("", "empty token"),
+        ("tooshort", "too short token"),
+        ("abcdefghijklmnopqrstuvwxyz0123456789", "too long token"),
+        ("abcdefghijkl-nopqrstuvwxy", "non alphanumeric token"),
+    ];
+
+    for (invalid_token, message) in test_cases {
+        let response = test_client
+            .get(format!(
+                "{}/subscriptions/confirm?subscription_token={}",
+                app.address, invalid_token
+            ))
+            .send()
+            .await
+            .expect("Failed to send the request to the server");
+
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with 400 status code, case: {}",
+            message
+        );
+    }
+}
+
+#[tokio::test]
+async fn confirm_401_for_unknown_token() {
+    let app = spawn_server().await;
+
+    let response = Client::new()
+        .get(format!(
+            "{}/subscriptions/confirm?subscription_token={}",
+            app.address, "abcdefghijklmnopqrstuvwxy"
+        ))
+        .send()
+        .await
+        .expect("Failed to send the request to the server");
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn confirm_200_for_link_in_confirmation_email() {
+    let app = spawn_server().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=test&email=test@gmail.com".into())
+        .await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    let response = reqwest::get(confirmation_links.html)
+        .await
+        .expect("Failed to send the request to the server");
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn confirm_link_confirms_subscriber() {
+    let app = spawn_server().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=test&email=test@gmail.com".into())
+        .await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    reqwest::get(confirmation_links.html)
+        .await
+        .expect("Failed to send the request to the server")
+        .error_for_status()
+        .expect("Failed to confirm subscriber");
+
+    // Check database data
+    let saved_subscription = sqlx::query!("SELECT name, email, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase");
+
+    assert_eq!(saved_subscription.name, "test");
+    assert_eq!(saved_subscription.email, "test@gmail.com");
+    assert_eq!(saved_subscription.status, "confirmed");
+}
+
+#[tokio::test]
+async fn confirm_does_not_bring_back_an_unsubscribed_subscriber() {
+    let app = spawn_server().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=test&email=test@gmail.com".into())
+        .await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    // Unsubscribed after the link was looked up, before it is used
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to unsubscribe");
+
+    let response = reqwest::get(confirmation_links.html)
+        .await
+        .expect("Failed to send the request to the server");
+    assert_eq!(response.status().as_u16(), 401);
+
+    let saved_subscription = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase");
+    assert_eq!(saved_subscription.status, "unsubscribed");
+}
+
+#[tokio::test]
+async fn confirm_link_can_be_followed_twice() {
+    let app = spawn_server().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=test&email=test@gmail.com".into())
+        .await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    for _ in 0..2 {
+        let response = reqwest::get(confirmation_links.html.clone())
+            .await
+            .expect("Failed to send the request to the server");
+        assert_eq!(response.status().as_u16(), 200);
+    }
+}
+
+#[tokio::test]
+async fn confirm_410_with_resend_page_for_expired_token() {
+    let app = spawn_server().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=test&email=test@gmail.com".into())
+        .await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    // Pretend the link was sent a long time ago
+    sqlx::query!("UPDATE subscription_tokens SET expires_at = NOW() - INTERVAL '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to expire subscription token");
+
+    let response = reqwest::get(confirmation_links.html)
+        .await
+        .expect("Failed to send the request to the server");
+
+    assert_eq!(response.status().as_u16(), 410);
+    let page = response.text().await.unwrap();
+    assert!(page.contains(r#"action="/subscriptions/resend""#));
+
+    // Subscriber stays pending
+    let saved_subscription = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase");
+
+    assert_eq!(saved_subscription.status, "pending");
+}
+
+#[tokio::test]
+async fn confirmation_link_can_be_followed_from_the_in_memory_outbox() {
+    let outbox = Uuid::new_v4().to_string();
+    let app = spawn_server_with(|config| {
+        config.email_client.provider = EmailProvider::InMemory;
+        config.email_client.in_memory = Some(InMemorySettings {
+            outbox: outbox.clone(),
+        });
+    })
+    .await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let sent = InMemoryTransport::named(&outbox).sent_to("ursula_le_guin@gmail.com");
+    assert_eq!(sent.len(), 1);
+    let confirmation_links = app.get_confirmation_links_from_outbox(&sent[0]);
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
@@ -0,0 +1,153 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{spawn_server, TestApp};
+
+async fn create_pending_subscriber(app: &TestApp) {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create pending subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=test&email=test@gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+// Move every confirmation email back in time, so the resend rate limit doesn't kick in
+async fn age_confirmation_emails(app: &TestApp) {
+    sqlx::query!("UPDATE subscriptions SET confirmation_sent_at = NOW() - INTERVAL '1 hour'")
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to age confirmation emails");
+}
+
+#[tokio::test]
+async fn resend_400_for_invalid_email() {
+    let app = spawn_server().await;
+
+    let test_cases = vec![
+        ("", "missing email"),
+        ("email=", "empty email"),
+        ("email=definately-not-a-valid-email", "invalid email"),
+    ];
+
+    for (invalid_body, message) in test_cases {
+        let response = app.post_resend_confirmation(invalid_body.into()).await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with 400 status code, case: {}",
+            message
+        );
+    }
+}
+
+#[tokio::test]
+async fn resend_200_without_email_for_unknown_address() {
+    let app = spawn_server().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_resend_confirmation("email=unknown@gmail.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn resend_sends_a_new_link_and_invalidates_the_old_one() {
+    let app = spawn_server().await;
+    create_pending_subscriber(&app).await;
+    age_confirmation_emails(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_resend_confirmation("email=test@gmail.com".into())
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let old_links = app.get_confirmation_links(&email_requests[0]);
+    let new_links = app.get_confirmation_links(&email_requests[1]);
+    assert_ne!(old_links.html, new_links.html);
+
+    // The previous link no longer works
+    let response = reqwest::get(old_links.html)
+        .await
+        .expect("Failed to send the request to the server");
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The new one does
+    let response = reqwest::get(new_links.html)
+        .await
+        .expect("Failed to send the request to the server");
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn resend_200_without_email_when_requested_too_often() {
+    let app = spawn_server().await;
+    create_pending_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_resend_confirmation("email=test@gmail.com".into())
+        .await;
+
+    // Same answer as for unknown addresses
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn resend_200_without_email_for_confirmed_subscriber() {
+    let app = spawn_server().await;
+    create_pending_subscriber(&app).await;
+    age_confirmation_emails(&app).await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_resend_confirmation("email=test@gmail.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
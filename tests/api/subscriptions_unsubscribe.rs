@@ -0,0 +1,255 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{spawn_server, TestApp};
+
+async fn create_confirmed_subscriber(app: &TestApp) -> String {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create confirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=test&email=test@gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    sqlx::query!("SELECT unsubscribe_token FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase")
+        .unsubscribe_token
+}
+
+#[tokio::test]
+async fn unsubscribe_page_400_for_malformed_token() {
+    let app = spawn_server().await;
+
+    let response = reqwest::get(format!(
+        "{}/subscriptions/unsubscribe?unsubscribe_token=not-a-token",
+        app.address
+    ))
+    .await
+    .expect("Failed to send the request to the server");
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn unsubscribe_page_401_for_unknown_token() {
+    let app = spawn_server().await;
+
+    let response = reqwest::get(format!(
+        "{}/subscriptions/unsubscribe?unsubscribe_token={}",
+        app.address,
+        "a".repeat(32)
+    ))
+    .await
+    .expect("Failed to send the request to the server");
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn unsubscribe_page_shows_a_confirmation_form_without_unsubscribing() {
+    let app = spawn_server().await;
+    let unsubscribe_token = create_confirmed_subscriber(&app).await;
+
+    let response = reqwest::get(format!(
+        "{}/subscriptions/unsubscribe?unsubscribe_token={}",
+        app.address, unsubscribe_token
+    ))
+    .await
+    .expect("Failed to send the request to the server");
+
+    assert_eq!(response.status().as_u16(), 200);
+    let page = response.text().await.unwrap();
+    assert!(page.contains(r#"action="/subscriptions/unsubscribe""#));
+    assert!(page.contains(&unsubscribe_token));
+
+    let saved_subscription = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase");
+
+    assert_eq!(saved_subscription.status, "confirmed");
+}
+
+#[tokio::test]
+async fn unsubscribe_401_for_unknown_token() {
+    let app = spawn_server().await;
+
+    let response = app
+        .post_unsubscribe(format!("unsubscribe_token={}", "a".repeat(32)))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn unsubscribe_marks_subscriber_as_unsubscribed() {
+    let app = spawn_server().await;
+    let unsubscribe_token = create_confirmed_subscriber(&app).await;
+
+    let response = app
+        .post_unsubscribe(format!("unsubscribe_token={}", unsubscribe_token))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let saved_subscription = sqlx::query!("SELECT status, unsubscribed_at FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase");
+
+    assert_eq!(saved_subscription.status, "unsubscribed");
+    assert!(saved_subscription.unsubscribed_at.is_some());
+}
+
+#[tokio::test]
+async fn unsubscribe_twice_keeps_the_first_unsubscription_time() {
+    let app = spawn_server().await;
+    let unsubscribe_token = create_confirmed_subscriber(&app).await;
+    let body = format!("unsubscribe_token={}", unsubscribe_token);
+
+    app.post_unsubscribe(body.clone()).await;
+    let first_time = sqlx::query!("SELECT unsubscribed_at FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase")
+        .unsubscribed_at;
+
+    let response = app.post_unsubscribe(body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let second_time = sqlx::query!("SELECT unsubscribed_at FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase")
+        .unsubscribed_at;
+
+    assert_eq!(first_time, second_time);
+}
+
+#[tokio::test]
+async fn unsubscribe_invalidates_pending_confirmation_links() {
+    let app = spawn_server().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=test&email=test@gmail.com".into())
+        .await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    let unsubscribe_token = sqlx::query!("SELECT unsubscribe_token FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase")
+        .unsubscribe_token;
+    app.post_unsubscribe(format!("unsubscribe_token={}", unsubscribe_token))
+        .await;
+
+    let response = reqwest::get(confirmation_links.html)
+        .await
+        .expect("Failed to send the request to the server");
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn confirmation_email_has_one_click_unsubscribe_headers() {
+    let app = spawn_server().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=test&email=test@gmail.com".into())
+        .await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let headers = app.get_list_unsubscribe_headers(email_request);
+
+    assert_eq!(headers.post, "List-Unsubscribe=One-Click");
+    assert_eq!(headers.link.path(), "/subscriptions/unsubscribe/one-click");
+}
+
+#[tokio::test]
+async fn one_click_unsubscribe_marks_subscriber_as_unsubscribed() {
+    let app = spawn_server().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=test&email=test@gmail.com".into())
+        .await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let headers = app.get_list_unsubscribe_headers(email_request);
+
+    // Mimic what mailbox providers send, as described in RFC 8058
+    let response = reqwest::Client::new()
+        .post(headers.link)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(headers.post)
+        .send()
+        .await
+        .expect("Failed to send the request to the server");
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let saved_subscription = sqlx::query!("SELECT status, unsubscribed_at FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase");
+
+    assert_eq!(saved_subscription.status, "unsubscribed");
+    assert!(saved_subscription.unsubscribed_at.is_some());
+}
+
+#[tokio::test]
+async fn one_click_unsubscribe_401_for_unknown_token() {
+    let app = spawn_server().await;
+
+    let response = reqwest::Client::new()
+        .post(format!(
+            "{}/subscriptions/unsubscribe/one-click?unsubscribe_token={}",
+            app.address,
+            "a".repeat(32)
+        ))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .expect("Failed to send the request to the server");
+
+    assert_eq!(response.status().as_u16(), 401);
+}
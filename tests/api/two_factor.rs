@@ -0,0 +1,308 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use totp_rs::TOTP;
+use z2p::authentication::build_totp;
+
+use crate::helpers::{assert_is_redirect_to, spawn_server, spawn_server_with, TestApp};
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+async fn login_with_password(app: &TestApp) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    }))
+    .await
+}
+
+fn extract_between<'a>(html: &'a str, start: &str, end: &str) -> &'a str {
+    let from = html.find(start).unwrap() + start.len();
+    let to = from + html[from..].find(end).unwrap();
+    &html[from..to]
+}
+
+// Enrol through the UI, returns the generator and the recovery codes
+async fn enrol_two_factor(app: &TestApp) -> (TOTP, Vec<String>) {
+    let html_page = app.get_two_factor_setup_html().await;
+    let secret = extract_between(&html_page, r#"<code id="totp-secret">"#, "</code>");
+    let totp = build_totp(secret, "z2p", &app.test_user.username).unwrap();
+
+    let response = app
+        .post_two_factor_setup(&totp.generate_current().unwrap())
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let html_page = response.text().await.unwrap();
+    let recovery_codes = html_page
+        .split("<li><code>")
+        .skip(1)
+        .map(|s| s.split("</code>").next().unwrap().to_string())
+        .collect();
+
+    (totp, recovery_codes)
+}
+
+#[tokio::test]
+async fn two_factor_login_requires_a_second_step() {
+    let app = spawn_server().await;
+    app.test_user.enable_two_factor(&app).await;
+
+    let response = login_with_password(&app).await;
+    assert_is_redirect_to(&response, "/login/2fa");
+
+    // Not logged in yet
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn two_factor_login_succeeds_with_a_valid_code() {
+    let app = spawn_server().await;
+    let totp = app.test_user.enable_two_factor(&app).await;
+
+    login_with_password(&app).await;
+    let response = app
+        .post_login_two_factor(&totp.generate_current().unwrap())
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn two_factor_login_401_for_invalid_code() {
+    let app = spawn_server().await;
+    let totp = app.test_user.enable_two_factor(&app).await;
+
+    login_with_password(&app).await;
+    let invalid_code = totp.generate(now() - 3600);
+    let response = app.post_login_two_factor(&invalid_code).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn two_factor_second_step_requires_the_password_step() {
+    let app = spawn_server().await;
+    let totp = app.test_user.enable_two_factor(&app).await;
+
+    let response = app
+        .post_login_two_factor(&totp.generate_current().unwrap())
+        .await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn two_factor_code_can_not_be_replayed() {
+    let app = spawn_server().await;
+    let totp = app.test_user.enable_two_factor(&app).await;
+    let code = totp.generate(now() + 30);
+
+    login_with_password(&app).await;
+    let response = app.post_login_two_factor(&code).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    app.post_logout().await;
+
+    login_with_password(&app).await;
+    let response = app.post_login_two_factor(&code).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn two_factor_login_restarts_after_too_many_invalid_codes() {
+    let app = spawn_server().await;
+    let totp = app.test_user.enable_two_factor(&app).await;
+    let invalid_code = totp.generate(now() - 3600);
+
+    login_with_password(&app).await;
+    for _ in 1..app.config.two_factor.max_attempts {
+        let response = app.post_login_two_factor(&invalid_code).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+    let response = app.post_login_two_factor(&invalid_code).await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Even a valid code is not accepted anymore
+    let response = app
+        .post_login_two_factor(&totp.generate_current().unwrap())
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn two_factor_lockout_is_not_reset_by_logging_in_again() {
+    let app = spawn_server().await;
+    let totp = app.test_user.enable_two_factor(&app).await;
+    let invalid_code = totp.generate(now() - 3600);
+
+    // Spread over several logins, the attempts still add up
+    for _ in 1..app.config.two_factor.max_attempts {
+        login_with_password(&app).await;
+        let response = app.post_login_two_factor(&invalid_code).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+    login_with_password(&app).await;
+    let response = app.post_login_two_factor(&invalid_code).await;
+    assert_is_redirect_to(&response, "/login");
+
+    login_with_password(&app).await;
+    let response = app
+        .post_login_two_factor(&totp.generate_current().unwrap())
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    // Once the lockout is over, a valid code goes through again
+    sqlx::query!(
+        "UPDATE users SET two_factor_locked_until = NOW() - INTERVAL '1 second' WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to update the user");
+    login_with_password(&app).await;
+    let response = app
+        .post_login_two_factor(&totp.generate_current().unwrap())
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn two_factor_enrolment_requires_a_valid_code() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+
+    let html_page = app.get_two_factor_setup_html().await;
+    assert!(html_page.contains("otpauth://totp/z2p:"));
+
+    let response = app.post_two_factor_setup("000000").await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let user = sqlx::query!(
+        "SELECT totp_secret FROM users WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to query from the datadabase");
+    assert!(user.totp_secret.is_none());
+}
+
+#[tokio::test]
+async fn two_factor_enrolment_can_not_replace_an_enabled_second_factor() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+
+    // A setup started before the second factor got enabled elsewhere
+    let html_page = app.get_two_factor_setup_html().await;
+    let secret = extract_between(&html_page, r#"<code id="totp-secret">"#, "</code>");
+    let totp = build_totp(secret, "z2p", &app.test_user.username).unwrap();
+    app.test_user.enable_two_factor(&app).await;
+    let enabled_secret = sqlx::query!(
+        "SELECT totp_secret FROM users WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to query from the datadabase")
+    .totp_secret;
+
+    let response = app
+        .post_two_factor_setup(&totp.generate_current().unwrap())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 409);
+    let user = sqlx::query!(
+        "SELECT totp_secret FROM users WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to query from the datadabase");
+    assert_eq!(user.totp_secret, enabled_secret);
+}
+
+#[tokio::test]
+async fn two_factor_enrolment_shows_recovery_codes_stored_hashed() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+
+    let (_, recovery_codes) = enrol_two_factor(&app).await;
+    assert_eq!(recovery_codes.len(), 10);
+
+    let saved_codes = sqlx::query!("SELECT code_hash FROM recovery_codes")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to query from the datadabase");
+    assert_eq!(saved_codes.len(), 10);
+    for saved_code in saved_codes {
+        assert!(!recovery_codes.contains(&saved_code.code_hash));
+    }
+}
+
+#[tokio::test]
+async fn two_factor_recovery_code_can_be_used_once() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+    let (_, recovery_codes) = enrol_two_factor(&app).await;
+    app.post_logout().await;
+
+    login_with_password(&app).await;
+    let response = app.post_login_two_factor(&recovery_codes[0]).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    app.post_logout().await;
+
+    login_with_password(&app).await;
+    let response = app.post_login_two_factor(&recovery_codes[0]).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn two_factor_can_be_disabled_with_the_current_password() {
+    let app = spawn_server().await;
+    app.test_user.login(&app).await;
+    enrol_two_factor(&app).await;
+
+    let response = app.post_two_factor_disable("wrong-password").await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_two_factor_disable(&app.test_user.password).await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.post_logout().await;
+
+    let response = login_with_password(&app).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn two_factor_enforcement_redirects_to_enrolment() {
+    let app = spawn_server_with(|config| config.two_factor.enforce = true).await;
+    app.test_user.login(&app).await;
+
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/admin/2fa");
+
+    enrol_two_factor(&app).await;
+
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn two_factor_can_not_be_disabled_when_enforced() {
+    let app = spawn_server_with(|config| config.two_factor.enforce = true).await;
+    app.test_user.login(&app).await;
+    enrol_two_factor(&app).await;
+
+    let response = app.post_two_factor_disable(&app.test_user.password).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+}